@@ -1,9 +1,17 @@
 #version 330 core
+// Colors are multiplied by their maps; missing maps are bound to a white
+// texture.
 struct Material {
-  sampler2D diffuse;
-  sampler2D specular;
-  // sampler2D emission;
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+  vec3 emissive;
   float shininess;
+  sampler2D diffuseMap;
+  sampler2D specularMap;
+  sampler2D normalMap;
+  sampler2D emissiveMap;
+  bool hasNormalMap;
 };
 
 struct DirLight {
@@ -50,55 +58,124 @@ in vec3 FragPos;
 in vec2 TexCoords;
 
 uniform vec3 viewPos;
+uniform mat4 view;
+
+// Shadows of the lights above, in the same slots. Directional maps hold
+// clip space depth, one layer per cascade; spot and point maps hold the
+// distance to the light divided by `far`.
+struct Shadow {
+  bool enabled;
+  mat4 lightSpace;
+  vec3 lightPos;
+  float far;
+  float bias;
+  int pcfRadius;
+};
+
+#define MAX_CASCADES 4
+
+// Where the directional map's cascades are. `ends` are distances along the
+// camera's view.
+struct Cascades {
+  int count;
+  mat4 lightSpaces[MAX_CASCADES];
+  float ends[MAX_CASCADES];
+  float blend;
+};
+
+uniform Shadow dirShadow;
+uniform Cascades dirCascades;
+uniform Shadow spotShadow;
+uniform Shadow pointShadows[NR_POINT_LIGHTS];
+uniform sampler2DArray dirShadowMap;
+uniform sampler2D spotShadowMap;
+uniform samplerCube pointShadowMaps[NR_POINT_LIGHTS];
+uniform bool receiveShadows;
+
+// Directions to spread cube map samples over.
+const vec3 cubeOffsets[20] = vec3[](
+  vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+  vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+  vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+  vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+  vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir, float shadow);
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow);
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow);
+vec3 PerturbNormal(vec3 normal, vec3 fragPos, vec2 texCoords);
+float DirShadow(vec3 normal, vec3 lightDir);
+float CascadeShadow(int cascade, vec3 normal, vec3 lightDir);
+float MapShadow(Shadow shadow, sampler2D map, vec3 normal, vec3 lightDir);
+float CubeShadow(Shadow shadow, samplerCube map, vec3 normal);
 
-vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir);
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
-vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+// The surface colors at this fragment, maps included.
+vec3 materialAmbient;
+vec3 materialDiffuse;
+vec3 materialSpecular;
 
 void main()
 {
+  vec3 diffuseSample = vec3(texture(material.diffuseMap, TexCoords));
+  materialAmbient = material.ambient * diffuseSample;
+  materialDiffuse = material.diffuse * diffuseSample;
+  materialSpecular = material.specular * vec3(texture(material.specularMap, TexCoords));
 
   vec3 norm = normalize(Normal);
+  if (material.hasNormalMap) {
+    norm = PerturbNormal(norm, FragPos, TexCoords);
+  }
   vec3 viewDir = normalize(viewPos - FragPos);
 
-  vec3 result = CalcDirLight(dirLight, norm, viewDir);
+  float dirShadowed = DirShadow(norm, normalize(-dirLight.direction));
+  float spotShadowed = MapShadow(spotShadow, spotShadowMap, norm, normalize(spotLight.position - FragPos));
+  // Sampler arrays can only be indexed with constants.
+  float pointShadow[NR_POINT_LIGHTS];
+  pointShadow[0] = CubeShadow(pointShadows[0], pointShadowMaps[0], norm);
+  pointShadow[1] = CubeShadow(pointShadows[1], pointShadowMaps[1], norm);
+  pointShadow[2] = CubeShadow(pointShadows[2], pointShadowMaps[2], norm);
+  pointShadow[3] = CubeShadow(pointShadows[3], pointShadowMaps[3], norm);
+
+  vec3 result = CalcDirLight(dirLight, norm, viewDir, dirShadowed);
   for (int i = 0; i < NR_POINT_LIGHTS; i++) {
-    result += CalcPointLight(pointLights[i], norm, FragPos, viewDir);
+    result += CalcPointLight(pointLights[i], norm, FragPos, viewDir, pointShadow[i]);
   }
-  result += CalcSpotLight(spotLight, norm, FragPos, viewDir);
-  
+  result += CalcSpotLight(spotLight, norm, FragPos, viewDir, spotShadowed);
+  result += material.emissive * vec3(texture(material.emissiveMap, TexCoords));
+
   FragColor = vec4(result, 1.0);
 }
 
-vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir) {
+vec3 CalcDirLight(DirLight light, vec3 normal, vec3 viewDir, float shadow) {
   vec3 lightDir = normalize(-light.direction);
   float diff = max(dot(normal, lightDir), 0.0);
   vec3 reflectDir = reflect(-lightDir, normal);
   float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
-  vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
-  vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
-  vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
-  return ambient + diffuse + specular;
+  vec3 ambient = light.ambient * materialAmbient;
+  vec3 diffuse = light.diffuse * diff * materialDiffuse;
+  vec3 specular = light.specular * spec * materialSpecular;
+  return ambient + (1.0 - shadow) * (diffuse + specular);
 }
 
 
-vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow) {
   vec3 lightDir = normalize(light.position - fragPos);
   float diff = max(dot(normal, lightDir), 0.0);
   vec3 reflectDir = reflect(-lightDir, normal);
   float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
   float distance = length(light.position - fragPos);
   float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
-  vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
-  vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
-  vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+  vec3 ambient = light.ambient * materialAmbient;
+  vec3 diffuse = light.diffuse * diff * materialDiffuse;
+  vec3 specular = light.specular * spec * materialSpecular;
   ambient *= attenuation;
   diffuse *= attenuation;
   specular *= attenuation;
-  return ambient + diffuse + specular;
+  return ambient + (1.0 - shadow) * (diffuse + specular);
 }
 
-vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir, float shadow) {
   vec3 lightDir = normalize(light.position - fragPos);
   float diff = max(dot(normal, lightDir), 0.0);
   vec3 reflectDir = reflect(-lightDir, normal);
@@ -108,11 +185,132 @@ vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
   float theta = dot(lightDir, normalize(-light.direction));
   float epsilon = light.cutOff - light.outerCutOff;
   float intensity = clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
-  vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoords));
-  vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoords));
-  vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoords));
+  vec3 ambient = light.ambient * materialAmbient;
+  vec3 diffuse = light.diffuse * diff * materialDiffuse;
+  vec3 specular = light.specular * spec * materialSpecular;
   ambient *= attenuation * intensity;
   diffuse *= attenuation * intensity;
   specular *= attenuation * intensity;
-  return ambient + diffuse + specular;
-}
\ No newline at end of file
+  return ambient + (1.0 - shadow) * (diffuse + specular);
+}
+
+// The bias grows on surfaces the light grazes, where acne is worst.
+float ShadowBias(Shadow shadow, vec3 normal, vec3 lightDir) {
+  float cosTheta = clamp(dot(normal, lightDir), 0.0, 1.0);
+  return max(shadow.bias * 10.0 * (1.0 - cosTheta), shadow.bias);
+}
+
+// How much of the directional light is blocked, from 0 to 1. The cascade
+// is picked by the fragment's depth in the camera's view, and fades into
+// the next one towards its end.
+float DirShadow(vec3 normal, vec3 lightDir) {
+  if (!receiveShadows || !dirShadow.enabled) {
+    return 0.0;
+  }
+  float viewDepth = -(view * vec4(FragPos, 1.0)).z;
+  float start = 0.0;
+  for (int i = 0; i < dirCascades.count; i++) {
+    float end = dirCascades.ends[i];
+    if (viewDepth < end) {
+      float shadow = CascadeShadow(i, normal, lightDir);
+      float fadeStart = end - dirCascades.blend * (end - start);
+      if (viewDepth > fadeStart) {
+        float next = i + 1 < dirCascades.count ? CascadeShadow(i + 1, normal, lightDir) : 0.0;
+        shadow = mix(shadow, next, (viewDepth - fadeStart) / (end - fadeStart));
+      }
+      return shadow;
+    }
+    start = end;
+  }
+  return 0.0;
+}
+
+// The shadow from one layer of the directional map.
+float CascadeShadow(int cascade, vec3 normal, vec3 lightDir) {
+  vec4 lightSpacePos = dirCascades.lightSpaces[cascade] * vec4(FragPos, 1.0);
+  vec3 proj = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  if (any(lessThan(proj.xy, vec2(0.0))) || any(greaterThan(proj.xy, vec2(1.0)))) {
+    return 0.0;
+  }
+  // Anything past the far plane is behind every caster.
+  float depth = min(proj.z, 1.0);
+  float bias = ShadowBias(dirShadow, normal, lightDir);
+
+  vec2 texel = 1.0 / vec2(textureSize(dirShadowMap, 0).xy);
+  float shadowed = 0.0;
+  for (int x = -dirShadow.pcfRadius; x <= dirShadow.pcfRadius; x++) {
+    for (int y = -dirShadow.pcfRadius; y <= dirShadow.pcfRadius; y++) {
+      float closest = texture(dirShadowMap, vec3(proj.xy + vec2(x, y) * texel, cascade)).r;
+      shadowed += depth - bias > closest ? 1.0 : 0.0;
+    }
+  }
+  float size = float(2 * dirShadow.pcfRadius + 1);
+  return shadowed / (size * size);
+}
+
+// How much of a spot light is blocked, from 0 to 1.
+float MapShadow(Shadow shadow, sampler2D map, vec3 normal, vec3 lightDir) {
+  if (!receiveShadows || !shadow.enabled) {
+    return 0.0;
+  }
+  vec4 lightSpacePos = shadow.lightSpace * vec4(FragPos, 1.0);
+  vec3 proj = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  if (lightSpacePos.w <= 0.0 || any(lessThan(proj.xy, vec2(0.0))) || any(greaterThan(proj.xy, vec2(1.0)))) {
+    return 0.0;
+  }
+  // Anything past the far plane is behind every caster.
+  float depth = min(length(FragPos - shadow.lightPos) / shadow.far, 1.0);
+  float bias = ShadowBias(shadow, normal, lightDir);
+
+  vec2 texel = 1.0 / vec2(textureSize(map, 0));
+  float shadowed = 0.0;
+  for (int x = -shadow.pcfRadius; x <= shadow.pcfRadius; x++) {
+    for (int y = -shadow.pcfRadius; y <= shadow.pcfRadius; y++) {
+      float closest = texture(map, proj.xy + vec2(x, y) * texel).r;
+      shadowed += depth - bias > closest ? 1.0 : 0.0;
+    }
+  }
+  float size = float(2 * shadow.pcfRadius + 1);
+  return shadowed / (size * size);
+}
+
+// How much of a point light is blocked, from 0 to 1.
+float CubeShadow(Shadow shadow, samplerCube map, vec3 normal) {
+  if (!receiveShadows || !shadow.enabled) {
+    return 0.0;
+  }
+  vec3 fromLight = FragPos - shadow.lightPos;
+  float depth = min(length(fromLight) / shadow.far, 1.0);
+  float bias = ShadowBias(shadow, normal, normalize(-fromLight));
+  if (shadow.pcfRadius == 0) {
+    return depth - bias > texture(map, fromLight).r ? 1.0 : 0.0;
+  }
+
+  // A texel of a face is 2 / size wide at distance 1 from the light.
+  float radius = float(shadow.pcfRadius) * 2.0 / float(textureSize(map, 0).x) * length(fromLight);
+  float shadowed = 0.0;
+  for (int i = 0; i < 20; i++) {
+    float closest = texture(map, fromLight + cubeOffsets[i] * radius).r;
+    shadowed += depth - bias > closest ? 1.0 : 0.0;
+  }
+  return shadowed / 20.0;
+}
+
+// Tangent-space normal mapping without vertex tangents: the tangent frame
+// is rebuilt from screen-space derivatives of the position and UVs.
+vec3 PerturbNormal(vec3 normal, vec3 fragPos, vec2 texCoords) {
+  vec3 dp1 = dFdx(fragPos);
+  vec3 dp2 = dFdy(fragPos);
+  vec2 duv1 = dFdx(texCoords);
+  vec2 duv2 = dFdy(texCoords);
+
+  vec3 dp2perp = cross(dp2, normal);
+  vec3 dp1perp = cross(normal, dp1);
+  vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+  float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+  mat3 tbn = mat3(tangent * invmax, bitangent * invmax, normal);
+
+  vec3 mapped = vec3(texture(material.normalMap, texCoords)) * 2.0 - 1.0;
+  return normalize(tbn * mapped);
+}
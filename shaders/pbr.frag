@@ -0,0 +1,337 @@
+#version 330 core
+// Metallic/roughness lighting with the Cook-Torrance BRDF: GGX normal
+// distribution, Smith-Schlick geometry and Fresnel-Schlick. Factors are
+// multiplied by their maps; missing maps are bound to a white texture.
+struct Material {
+  vec3 baseColor;
+  float metallic;
+  float roughness;
+  float ao;
+  vec3 emissive;
+  sampler2D baseColorMap;
+  sampler2D metallicMap;
+  sampler2D roughnessMap;
+  sampler2D aoMap;
+  sampler2D normalMap;
+  sampler2D emissiveMap;
+  bool hasNormalMap;
+};
+
+// The same lights as the Phong shader. Their diffuse color is the light's
+// color; specular is left out, as the BRDF decides the highlight.
+struct DirLight {
+  vec3 direction;
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+};
+
+struct PointLight {
+  vec3 position;
+  float constant;
+  float linear;
+  float quadratic;
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+};
+
+struct SpotLight {
+  vec3 position;
+  vec3 direction;
+  float cutOff;
+  float outerCutOff;
+  float constant;
+  float linear;
+  float quadratic;
+  vec3 ambient;
+  vec3 diffuse;
+  vec3 specular;
+};
+
+#define NR_POINT_LIGHTS 4
+
+const float PI = 3.14159265359;
+
+uniform DirLight dirLight;
+uniform PointLight pointLights[NR_POINT_LIGHTS];
+uniform SpotLight spotLight;
+uniform Material material;
+
+out vec4 FragColor;
+
+in vec3 Normal;
+in vec3 FragPos;
+in vec2 TexCoords;
+
+uniform vec3 viewPos;
+uniform mat4 view;
+
+// Shadows of the lights above, in the same slots. Directional maps hold
+// clip space depth, one layer per cascade; spot and point maps hold the
+// distance to the light divided by `far`.
+struct Shadow {
+  bool enabled;
+  mat4 lightSpace;
+  vec3 lightPos;
+  float far;
+  float bias;
+  int pcfRadius;
+};
+
+#define MAX_CASCADES 4
+
+// Where the directional map's cascades are. `ends` are distances along the
+// camera's view.
+struct Cascades {
+  int count;
+  mat4 lightSpaces[MAX_CASCADES];
+  float ends[MAX_CASCADES];
+  float blend;
+};
+
+uniform Shadow dirShadow;
+uniform Cascades dirCascades;
+uniform Shadow spotShadow;
+uniform Shadow pointShadows[NR_POINT_LIGHTS];
+uniform sampler2DArray dirShadowMap;
+uniform sampler2D spotShadowMap;
+uniform samplerCube pointShadowMaps[NR_POINT_LIGHTS];
+uniform bool receiveShadows;
+
+// Directions to spread cube map samples over.
+const vec3 cubeOffsets[20] = vec3[](
+  vec3(1, 1, 1), vec3(1, -1, 1), vec3(-1, -1, 1), vec3(-1, 1, 1),
+  vec3(1, 1, -1), vec3(1, -1, -1), vec3(-1, -1, -1), vec3(-1, 1, -1),
+  vec3(1, 1, 0), vec3(1, -1, 0), vec3(-1, -1, 0), vec3(-1, 1, 0),
+  vec3(1, 0, 1), vec3(-1, 0, 1), vec3(1, 0, -1), vec3(-1, 0, -1),
+  vec3(0, 1, 1), vec3(0, -1, 1), vec3(0, -1, -1), vec3(0, 1, -1)
+);
+
+vec3 Radiance(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir);
+vec3 PerturbNormal(vec3 normal, vec3 fragPos, vec2 texCoords);
+float DirShadow(vec3 normal, vec3 lightDir);
+float CascadeShadow(int cascade, vec3 normal, vec3 lightDir);
+float MapShadow(Shadow shadow, sampler2D map, vec3 normal, vec3 lightDir);
+float CubeShadow(Shadow shadow, samplerCube map, vec3 normal);
+
+// The surface at this fragment, maps included.
+vec3 albedo;
+float metallic;
+float roughness;
+vec3 F0;
+
+void main()
+{
+  // Color maps are sRGB textures, so sampling already decodes them to the
+  // linear space lighting is done in.
+  albedo = material.baseColor * vec3(texture(material.baseColorMap, TexCoords));
+  metallic = clamp(material.metallic * texture(material.metallicMap, TexCoords).r, 0.0, 1.0);
+  // A perfectly smooth surface makes the highlight vanish to a point.
+  roughness = clamp(material.roughness * texture(material.roughnessMap, TexCoords).r, 0.04, 1.0);
+  float ao = material.ao * texture(material.aoMap, TexCoords).r;
+  // Non-metals reflect about 4% head-on; metals reflect their own color.
+  F0 = mix(vec3(0.04), albedo, metallic);
+
+  vec3 norm = normalize(Normal);
+  if (material.hasNormalMap) {
+    norm = PerturbNormal(norm, FragPos, TexCoords);
+  }
+  vec3 viewDir = normalize(viewPos - FragPos);
+
+  float dirShadowed = DirShadow(norm, normalize(-dirLight.direction));
+  float spotShadowed = MapShadow(spotShadow, spotShadowMap, norm, normalize(spotLight.position - FragPos));
+  // Sampler arrays can only be indexed with constants.
+  float pointShadow[NR_POINT_LIGHTS];
+  pointShadow[0] = CubeShadow(pointShadows[0], pointShadowMaps[0], norm);
+  pointShadow[1] = CubeShadow(pointShadows[1], pointShadowMaps[1], norm);
+  pointShadow[2] = CubeShadow(pointShadows[2], pointShadowMaps[2], norm);
+  pointShadow[3] = CubeShadow(pointShadows[3], pointShadowMaps[3], norm);
+
+  vec3 ambient = dirLight.ambient + spotLight.ambient;
+  vec3 result = Radiance(-dirLight.direction, dirLight.diffuse * (1.0 - dirShadowed), norm, viewDir);
+
+  for (int i = 0; i < NR_POINT_LIGHTS; i++) {
+    PointLight light = pointLights[i];
+    float distance = length(light.position - FragPos);
+    float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
+    result += Radiance(light.position - FragPos, light.diffuse * attenuation * (1.0 - pointShadow[i]), norm, viewDir);
+    ambient += light.ambient * attenuation;
+  }
+
+  vec3 spotDir = normalize(spotLight.position - FragPos);
+  float theta = dot(spotDir, normalize(-spotLight.direction));
+  float epsilon = spotLight.cutOff - spotLight.outerCutOff;
+  float intensity = clamp((theta - spotLight.outerCutOff) / epsilon, 0.0, 1.0);
+  float spotDistance = length(spotLight.position - FragPos);
+  float spotAttenuation = 1.0 / (spotLight.constant + spotLight.linear * spotDistance + spotLight.quadratic * (spotDistance * spotDistance));
+  result += Radiance(spotDir, spotLight.diffuse * spotAttenuation * intensity * (1.0 - spotShadowed), norm, viewDir);
+
+  result += ambient * albedo * ao;
+  result += material.emissive * vec3(texture(material.emissiveMap, TexCoords));
+
+  // Reinhard tone mapping, then back to sRGB for the screen.
+  result = result / (result + vec3(1.0));
+  FragColor = vec4(pow(result, vec3(1.0 / 2.2)), 1.0);
+}
+
+float DistributionGGX(vec3 N, vec3 H)
+{
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float NdotH = max(dot(N, H), 0.0);
+  float denom = NdotH * NdotH * (a2 - 1.0) + 1.0;
+  return a2 / (PI * denom * denom);
+}
+
+float GeometrySchlickGGX(float NdotV)
+{
+  float r = roughness + 1.0;
+  float k = (r * r) / 8.0;
+  return NdotV / (NdotV * (1.0 - k) + k);
+}
+
+vec3 FresnelSchlick(float cosTheta)
+{
+  return F0 + (1.0 - F0) * pow(clamp(1.0 - cosTheta, 0.0, 1.0), 5.0);
+}
+
+// Light reflected towards the viewer from light coming from `lightDir`
+// (towards the light) with the given radiance.
+vec3 Radiance(vec3 lightDir, vec3 radiance, vec3 normal, vec3 viewDir)
+{
+  vec3 L = normalize(lightDir);
+  vec3 H = normalize(viewDir + L);
+  float NdotL = max(dot(normal, L), 0.0);
+  float NdotV = max(dot(normal, viewDir), 0.0);
+
+  float NDF = DistributionGGX(normal, H);
+  float G = GeometrySchlickGGX(NdotV) * GeometrySchlickGGX(NdotL);
+  vec3 F = FresnelSchlick(max(dot(H, viewDir), 0.0));
+
+  vec3 specular = NDF * G * F / (4.0 * NdotV * NdotL + 0.0001);
+  // What isn't reflected is refracted and scattered as diffuse light,
+  // except by metals, which absorb it.
+  vec3 kD = (vec3(1.0) - F) * (1.0 - metallic);
+  return (kD * albedo / PI + specular) * radiance * NdotL;
+}
+
+// Tangent-space normal mapping without vertex tangents, as in cube.frag.
+vec3 PerturbNormal(vec3 normal, vec3 fragPos, vec2 texCoords) {
+  vec3 dp1 = dFdx(fragPos);
+  vec3 dp2 = dFdy(fragPos);
+  vec2 duv1 = dFdx(texCoords);
+  vec2 duv2 = dFdy(texCoords);
+
+  vec3 dp2perp = cross(dp2, normal);
+  vec3 dp1perp = cross(normal, dp1);
+  vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+  vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+  float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+  mat3 tbn = mat3(tangent * invmax, bitangent * invmax, normal);
+
+  vec3 mapped = vec3(texture(material.normalMap, texCoords)) * 2.0 - 1.0;
+  return normalize(tbn * mapped);
+}
+
+// The bias grows on surfaces the light grazes, where acne is worst.
+float ShadowBias(Shadow shadow, vec3 normal, vec3 lightDir) {
+  float cosTheta = clamp(dot(normal, lightDir), 0.0, 1.0);
+  return max(shadow.bias * 10.0 * (1.0 - cosTheta), shadow.bias);
+}
+
+// How much of the directional light is blocked, from 0 to 1. The cascade
+// is picked by the fragment's depth in the camera's view, and fades into
+// the next one towards its end.
+float DirShadow(vec3 normal, vec3 lightDir) {
+  if (!receiveShadows || !dirShadow.enabled) {
+    return 0.0;
+  }
+  float viewDepth = -(view * vec4(FragPos, 1.0)).z;
+  float start = 0.0;
+  for (int i = 0; i < dirCascades.count; i++) {
+    float end = dirCascades.ends[i];
+    if (viewDepth < end) {
+      float shadow = CascadeShadow(i, normal, lightDir);
+      float fadeStart = end - dirCascades.blend * (end - start);
+      if (viewDepth > fadeStart) {
+        float next = i + 1 < dirCascades.count ? CascadeShadow(i + 1, normal, lightDir) : 0.0;
+        shadow = mix(shadow, next, (viewDepth - fadeStart) / (end - fadeStart));
+      }
+      return shadow;
+    }
+    start = end;
+  }
+  return 0.0;
+}
+
+// The shadow from one layer of the directional map.
+float CascadeShadow(int cascade, vec3 normal, vec3 lightDir) {
+  vec4 lightSpacePos = dirCascades.lightSpaces[cascade] * vec4(FragPos, 1.0);
+  vec3 proj = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  if (any(lessThan(proj.xy, vec2(0.0))) || any(greaterThan(proj.xy, vec2(1.0)))) {
+    return 0.0;
+  }
+  // Anything past the far plane is behind every caster.
+  float depth = min(proj.z, 1.0);
+  float bias = ShadowBias(dirShadow, normal, lightDir);
+
+  vec2 texel = 1.0 / vec2(textureSize(dirShadowMap, 0).xy);
+  float shadowed = 0.0;
+  for (int x = -dirShadow.pcfRadius; x <= dirShadow.pcfRadius; x++) {
+    for (int y = -dirShadow.pcfRadius; y <= dirShadow.pcfRadius; y++) {
+      float closest = texture(dirShadowMap, vec3(proj.xy + vec2(x, y) * texel, cascade)).r;
+      shadowed += depth - bias > closest ? 1.0 : 0.0;
+    }
+  }
+  float size = float(2 * dirShadow.pcfRadius + 1);
+  return shadowed / (size * size);
+}
+
+// How much of a spot light is blocked, from 0 to 1.
+float MapShadow(Shadow shadow, sampler2D map, vec3 normal, vec3 lightDir) {
+  if (!receiveShadows || !shadow.enabled) {
+    return 0.0;
+  }
+  vec4 lightSpacePos = shadow.lightSpace * vec4(FragPos, 1.0);
+  vec3 proj = lightSpacePos.xyz / lightSpacePos.w * 0.5 + 0.5;
+  if (lightSpacePos.w <= 0.0 || any(lessThan(proj.xy, vec2(0.0))) || any(greaterThan(proj.xy, vec2(1.0)))) {
+    return 0.0;
+  }
+  // Anything past the far plane is behind every caster.
+  float depth = min(length(FragPos - shadow.lightPos) / shadow.far, 1.0);
+  float bias = ShadowBias(shadow, normal, lightDir);
+
+  vec2 texel = 1.0 / vec2(textureSize(map, 0));
+  float shadowed = 0.0;
+  for (int x = -shadow.pcfRadius; x <= shadow.pcfRadius; x++) {
+    for (int y = -shadow.pcfRadius; y <= shadow.pcfRadius; y++) {
+      float closest = texture(map, proj.xy + vec2(x, y) * texel).r;
+      shadowed += depth - bias > closest ? 1.0 : 0.0;
+    }
+  }
+  float size = float(2 * shadow.pcfRadius + 1);
+  return shadowed / (size * size);
+}
+
+// How much of a point light is blocked, from 0 to 1.
+float CubeShadow(Shadow shadow, samplerCube map, vec3 normal) {
+  if (!receiveShadows || !shadow.enabled) {
+    return 0.0;
+  }
+  vec3 fromLight = FragPos - shadow.lightPos;
+  float depth = min(length(fromLight) / shadow.far, 1.0);
+  float bias = ShadowBias(shadow, normal, normalize(-fromLight));
+  if (shadow.pcfRadius == 0) {
+    return depth - bias > texture(map, fromLight).r ? 1.0 : 0.0;
+  }
+
+  // A texel of a face is 2 / size wide at distance 1 from the light.
+  float radius = float(shadow.pcfRadius) * 2.0 / float(textureSize(map, 0).x) * length(fromLight);
+  float shadowed = 0.0;
+  for (int i = 0; i < 20; i++) {
+    float closest = texture(map, fromLight + cubeOffsets[i] * radius).r;
+    shadowed += depth - bias > closest ? 1.0 : 0.0;
+  }
+  return shadowed / 20.0;
+}
@@ -0,0 +1,17 @@
+#version 330 core
+// Spot and point light maps store the distance to the light as a fraction
+// of farPlane; directional maps keep the depth as rasterized.
+in vec3 FragPos;
+
+uniform bool linearDepth;
+uniform vec3 lightPos;
+uniform float farPlane;
+
+void main()
+{
+  if (linearDepth) {
+    gl_FragDepth = length(FragPos - lightPos) / farPlane;
+  } else {
+    gl_FragDepth = gl_FragCoord.z;
+  }
+}
@@ -0,0 +1,124 @@
+//! Compresses PNG textures into DDS files that `load_texture` uploads
+//! without decoding, with their mip chain built ahead of time.
+//!
+//! ```text
+//! cargo run --bin compress_textures -- [--format bc1|bc3|bc4|bc5] [--srgb] [--no-flip-y] [--no-mipmaps] PATH...
+//! ```
+//!
+//! Each path is a PNG file or a directory searched for them. Every image is
+//! written next to itself with a `.dds` extension. Without `--format`, gray
+//! images become BC4, images with alpha BC3 and the rest BC1. Images are
+//! stored bottom row first, where OpenGL expects it, like `load_texture`
+//! does with PNGs; `--no-flip-y` keeps them as they are.
+
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use cogwheel::engine::rendering::PixelFormat;
+use cogwheel::engine::texture::{bcn, CompressedImage};
+
+struct Options {
+  format: Option<PixelFormat>,
+  srgb: bool,
+  flip_y: bool,
+  mipmaps: bool,
+}
+
+fn main() -> ExitCode {
+  let mut options = Options { format: None, srgb: false, flip_y: true, mipmaps: true };
+  let mut paths = Vec::new();
+
+  let mut args = std::env::args().skip(1);
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--format" => {
+        options.format = match args.next().as_deref() {
+          Some("bc1") => Some(PixelFormat::Bc1),
+          Some("bc3") => Some(PixelFormat::Bc3),
+          Some("bc4") => Some(PixelFormat::Bc4),
+          Some("bc5") => Some(PixelFormat::Bc5),
+          other => return usage(&format!("unknown format {:?}", other.unwrap_or(""))),
+        }
+      }
+      "--srgb" => options.srgb = true,
+      "--no-flip-y" => options.flip_y = false,
+      "--no-mipmaps" => options.mipmaps = false,
+      flag if flag.starts_with("--") => return usage(&format!("unknown option {}", flag)),
+      path => paths.push(PathBuf::from(path)),
+    }
+  }
+  if paths.is_empty() {
+    return usage("no files given");
+  }
+
+  let mut files = Vec::new();
+  for path in &paths {
+    if let Err(error) = find_pngs(path, &mut files) {
+      eprintln!("{}: {}", path.display(), error);
+      return ExitCode::FAILURE;
+    }
+  }
+
+  let mut failed = false;
+  for file in &files {
+    match compress_file(file, &options) {
+      Ok(output) => println!("{} -> {}", file.display(), output.display()),
+      Err(error) => {
+        eprintln!("{}: {}", file.display(), error);
+        failed = true;
+      }
+    }
+  }
+
+  if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS }
+}
+
+fn usage(error: &str) -> ExitCode {
+  eprintln!("{}", error);
+  eprintln!("usage: compress_textures [--format bc1|bc3|bc4|bc5] [--srgb] [--no-flip-y] [--no-mipmaps] PATH...");
+  ExitCode::FAILURE
+}
+
+// Adds `path` if it is a PNG file, or the PNGs under it if it is a directory.
+fn find_pngs(path: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+  if path.is_dir() {
+    let mut entries = std::fs::read_dir(path)?.map(|entry| entry.map(|entry| entry.path())).collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in entries {
+      if entry.is_dir() || is_png(&entry) {
+        find_pngs(&entry, files)?;
+      }
+    }
+  } else {
+    files.push(path.to_path_buf());
+  }
+  Ok(())
+}
+
+fn is_png(path: &Path) -> bool {
+  path.extension().and_then(|extension| extension.to_str()).is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+fn compress_file(path: &Path, options: &Options) -> Result<PathBuf, String> {
+  let image = image::open(path).map_err(|error| error.to_string())?;
+  let image = if options.flip_y { image.flipv() } else { image };
+
+  let format = options.format.unwrap_or_else(|| {
+    let color = image.color();
+    if color.has_alpha() {
+      PixelFormat::Bc3
+    } else if color.has_color() {
+      PixelFormat::Bc1
+    } else {
+      PixelFormat::Bc4
+    }
+  });
+  let format = if options.srgb { format.to_srgb() } else { format };
+
+  // Gray ends up in red, green and blue, where BC4 and BC5 read it.
+  let compressed: CompressedImage =
+    bcn::compress(&image.to_rgba8(), format, options.mipmaps).ok_or("format can't be encoded")?;
+  let output = path.with_extension("dds");
+  std::fs::write(&output, compressed.to_dds()).map_err(|error| error.to_string())?;
+  Ok(output)
+}
@@ -1,5 +1,6 @@
 use crate::engine;
 use engine::material::*;
+use engine::rendering::RenderDevice;
 
 pub enum Materials {
   Emerald,
@@ -10,38 +11,40 @@ pub enum Materials {
 }
 
 impl Materials {
-  pub fn get_material(self) -> Material {
-    match self {
-      Materials::Emerald => Material::new(
+  // Classic OpenGL material table. Shininess there is a fraction of 128.
+  pub fn get_material<D: RenderDevice>(self) -> Material<D> {
+    let material = match self {
+      Materials::Emerald => PhongMaterial::new(
         Vector3::new(0.0215, 0.1745, 0.0215),
         Vector3::new(0.07568, 0.61424, 0.07568),
         Vector3::new(0.633, 0.727811, 0.633),
-        0.6,
+        0.6 * 128.0,
       ),
-      Materials::Pearl => Material::new(
+      Materials::Pearl => PhongMaterial::new(
         Vector3::new(0.25, 0.20725, 0.20725),
         Vector3::new(1.0, 0.829, 0.829),
         Vector3::new(0.296648, 0.296648, 0.296648),
-        0.088,
+        0.088 * 128.0,
       ),
-      Materials::Copper => Material::new(
+      Materials::Copper => PhongMaterial::new(
         Vector3::new(0.19125, 0.0735, 0.0225),
         Vector3::new(0.7038, 0.27048, 0.0828),
         Vector3::new(0.256777, 0.137622, 0.086014),
-        0.1,
+        0.1 * 128.0,
       ),
-      Materials::RedPlastic => Material::new(
+      Materials::RedPlastic => PhongMaterial::new(
         Vector3::new(0.0, 0.0, 0.0),
         Vector3::new(0.5, 0.0, 0.0),
         Vector3::new(0.7, 0.6, 0.6),
-        0.25,
+        0.25 * 128.0,
       ),
-      Materials::YellowRubber => Material::new(
+      Materials::YellowRubber => PhongMaterial::new(
         Vector3::new(0.05, 0.05, 0.0),
         Vector3::new(0.5, 0.5, 0.4),
         Vector3::new(0.7, 0.7, 0.04),
-        0.078125,
+        0.078125 * 128.0,
       ),
-    }
+    };
+    material.into()
   }
 }
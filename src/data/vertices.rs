@@ -1,35 +1,183 @@
+use std::collections::HashMap;
+
+use crate::engine::math::Aabb;
+use crate::engine::rendering::{
+  as_bytes, Attribute, ComponentType, IndexType, VertexAttribute, VertexLayout,
+};
+use crate::engine::types::Point3;
+
+/// Index data for a `VertexArray`. `u16` is used whenever every index fits,
+/// which halves the size of the element buffer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Indices {
+  U16(Vec<u16>),
+  U32(Vec<u32>),
+}
+
+impl Indices {
+  /// Picks the smallest index type that can address every vertex.
+  pub fn from_u32(indices: Vec<u32>) -> Self {
+    if indices.iter().all(|&i| i <= u16::MAX as u32) {
+      Indices::U16(indices.into_iter().map(|i| i as u16).collect())
+    } else {
+      Indices::U32(indices)
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      Indices::U16(indices) => indices.len(),
+      Indices::U32(indices) => indices.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn index_type(&self) -> IndexType {
+    match self {
+      Indices::U16(_) => IndexType::U16,
+      Indices::U32(_) => IndexType::U32,
+    }
+  }
+
+  pub fn get(&self, i: usize) -> u32 {
+    match self {
+      Indices::U16(indices) => indices[i] as u32,
+      Indices::U32(indices) => indices[i],
+    }
+  }
+
+  pub fn as_bytes(&self) -> &[u8] {
+    match self {
+      Indices::U16(indices) => as_bytes(indices),
+      Indices::U32(indices) => as_bytes(indices),
+    }
+  }
+}
+
 #[derive(Clone)]
 pub struct VertexArray {
-  // Vertices of the object. This always includes position vertices, but
-  // may also include normals and texture coordinates.
+  // Interleaved vertex data of the object, laid out as described by
+  // `layout`.
   pub vertices: Vec<f32>,
 
-  // Does the vertices -array contain normals?
-  pub has_normals: bool,
-
-  // Does the vertices -array contain texture coordinates?
-  pub has_tex_coords: bool,
-
-  // The stride of the vertices array. It should be noted that this is
-  // multiplied by the size of f32 in bytes (4), so only the amount of floats
-  // should be given.
-  pub stride: u8,
+  // Which attributes each vertex has and in what order. Strides and
+  // offsets are derived from this.
+  pub layout: VertexLayout,
 
   // Triangle count of the rendering itself. For example, if this is 2, then
   // the renderer will expect to render two triangles.
-  pub triangle_count: i32
+  pub triangle_count: i32,
+
+  // Optional indices into `vertices`. When present, the object is drawn
+  // with an element buffer and `triangle_count` is the number of indices.
+  pub indices: Option<Indices>
 }
 
 impl VertexArray {
-  pub fn new(vertices: &[f32], stride: u8, has_normals: bool, has_tex_coords: bool, triangle_count: i32) -> Self {
+  pub fn new(vertices: &[f32], layout: VertexLayout, triangle_count: i32) -> Self {
+    Self {
+      vertices: vertices.to_vec(),
+      layout,
+      triangle_count,
+      indices: None
+    }
+  }
+
+  pub fn new_indexed(vertices: &[f32], layout: VertexLayout, indices: Indices) -> Self {
     Self {
       vertices: vertices.to_vec(),
-      has_normals,
-      has_tex_coords,
-      stride: stride * 4,
-      triangle_count
+      layout,
+      triangle_count: indices.len() as i32,
+      indices: Some(indices)
     }
   }
+
+  pub fn is_indexed(&self) -> bool {
+    self.indices.is_some()
+  }
+
+  pub fn vertex_count(&self) -> usize {
+    self.vertices.len() / self.layout.float_stride().max(1)
+  }
+
+  // Floats of the i:th vertex.
+  pub fn vertex(&self, i: usize) -> &[f32] {
+    let stride = self.layout.float_stride();
+    &self.vertices[i * stride..(i + 1) * stride]
+  }
+
+  // Converts to indexed form, merging vertices whose every attribute is
+  // bit-for-bit identical. The flat cube goes from 36 vertices to 24
+  // (corners are still split where face normals differ), the skybox from 36
+  // to 8. Already indexed arrays are returned as they are.
+  pub fn to_indexed(&self) -> VertexArray {
+    if self.is_indexed() {
+      return self.clone();
+    }
+
+    let stride = self.layout.float_stride();
+    let mut unique: HashMap<Vec<u32>, u32> = HashMap::new();
+    let mut vertices: Vec<f32> = Vec::with_capacity(self.vertices.len());
+    let mut indices: Vec<u32> = Vec::with_capacity(self.triangle_count as usize);
+
+    for i in 0..self.triangle_count as usize {
+      let vertex = &self.vertices[i * stride..(i + 1) * stride];
+      let key: Vec<u32> = vertex.iter().map(|f| f.to_bits()).collect();
+      let index = *unique.entry(key).or_insert_with(|| {
+        vertices.extend_from_slice(vertex);
+        (vertices.len() / stride - 1) as u32
+      });
+      indices.push(index);
+    }
+
+    VertexArray::new_indexed(&vertices, self.layout.clone(), Indices::from_u32(indices))
+  }
+
+  // Box around the positions, or None if there are no vertices (or no
+  // float positions).
+  pub fn bounds(&self) -> Option<Aabb> {
+    let offset = self.position_offset()?;
+    Aabb::from_points((0..self.vertex_count()).map(|i| self.position(i, offset)))
+  }
+
+  // Corners of every drawn triangle, following the indices if there are
+  // any. Empty without float positions.
+  pub fn triangles(&self) -> impl Iterator<Item = [Point3; 3]> + '_ {
+    let offset = self.position_offset();
+    let count = offset.map_or(0, |_| self.triangle_count.max(0) as usize / 3);
+    (0..count).map(move |triangle| {
+      [0, 1, 2].map(|corner| {
+        let i = triangle * 3 + corner;
+        let vertex = self.indices.as_ref().map_or(i, |indices| indices.get(i) as usize);
+        self.position(vertex, offset.unwrap_or(0))
+      })
+    })
+  }
+
+  // Float offset of the position within a vertex.
+  fn position_offset(&self) -> Option<usize> {
+    let (position, offset) = self.layout.offsets().find(|(a, _)| a.attribute == Attribute::Position)?;
+    if position.component_type != ComponentType::F32 || position.components < 3 {
+      return None;
+    }
+    Some(offset / 4)
+  }
+
+  fn position(&self, i: usize, offset: usize) -> Point3 {
+    let v = &self.vertex(i)[offset..offset + 3];
+    Point3::new(v[0], v[1], v[2])
+  }
+
+  pub fn has_normals(&self) -> bool {
+    self.layout.has(Attribute::Normal)
+  }
+
+  pub fn has_tex_coords(&self) -> bool {
+    self.layout.has(Attribute::TexCoord0)
+  }
 }
 
 // Used in object creation. The values of these enums are defined below.
@@ -44,61 +192,60 @@ pub enum Vertices {
 }
 
 impl Vertices {
+  // The same geometry as `get_vertices`, deduplicated into indexed form.
+  pub fn get_indexed_vertices(self) -> VertexArray {
+    self.get_vertices().to_indexed()
+  }
+
   pub fn get_vertices(self) -> VertexArray {
     match self {
       Vertices::Cube => VertexArray::new(
         &CUBE_VERTICES,
-        8,
-        true,
-        true,
+        VertexLayout::position_normal_tex_coords(),
         36
       ),
       Vertices::CubeWithoutNormals => VertexArray::new(
         &CUBE_VERTICES_NO_NORMALS,
-        5,
-        false,
-        true,
+        VertexLayout::position_tex_coords(),
         36
       ),
       Vertices::PlaneWithoutNormals => VertexArray::new(
         &PLANE_VERTICES,
-        5,
-        false,
-        true,
+        VertexLayout::position_tex_coords(),
         6
       ),
       Vertices::FlatImage => VertexArray::new(
         &FLAT_IMAGE_VERTICES,
-        5,
-        false,
-        true,
+        VertexLayout::position_tex_coords(),
         6
       ),
       Vertices::Screen => VertexArray::new(
         &QUAD_VERTICES,
-        4,
-        false,
-        true,
+        screen_layout(),
         6
       ),
       Vertices::ReverseCamera => VertexArray::new(
         &REVERSE_CAMERA_VERTICES,
-        4,
-        false,
-        true,
+        screen_layout(),
         6
       ),
       Vertices::Skybox => VertexArray::new(
         &SKYBOX,
-        3,
-        false,
-        false,
+        VertexLayout::position(),
         36
       )
     }
   }
 }
 
+// Screen-space quads only have 2D positions.
+fn screen_layout() -> VertexLayout {
+  VertexLayout::new(&[
+    VertexAttribute::float(Attribute::Position, 2),
+    VertexAttribute::float(Attribute::TexCoord0, 2),
+  ])
+}
+
 const CUBE_VERTICES: [f32; 288] = [
  -0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  0.0, 0.0,
   0.5, -0.5, -0.5,  0.0,  0.0, -1.0,  1.0, 0.0,
@@ -266,4 +413,18 @@ const SKYBOX: [f32; 108] = [
   1.0, -1.0, -1.0,
   -1.0, -1.0,  1.0,
   1.0, -1.0,  1.0
-];
\ No newline at end of file
+];
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn indexing_the_cube_merges_shared_corners() {
+    let cube = Vertices::Cube.get_vertices();
+    let indexed = cube.to_indexed();
+    assert_eq!(cube.vertex_count(), 36);
+    assert_eq!(indexed.vertex_count(), 24);
+    assert!(indexed.is_indexed());
+    assert_eq!(indexed.triangles().collect::<Vec<_>>(), cube.triangles().collect::<Vec<_>>());
+  }
+}
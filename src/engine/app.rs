@@ -0,0 +1,302 @@
+use std::collections::HashSet;
+
+use glfw::{
+  Context, GlfwReceiver, JoystickId, OpenGlProfileHint, PWindow, SwapInterval, WindowHint,
+  WindowMode,
+};
+
+pub use glfw::{Action, Key, MouseButton, WindowEvent};
+
+use super::error::{Error, Result};
+use super::input::Input;
+use super::material::fallback_texture_on;
+use super::rendering::{GlCapabilities, GlDevice};
+use super::Texture;
+use super::time::FixedTimestep;
+
+/// How the window of an `App` is created.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowConfig {
+  pub title: String,
+  pub width: u32,
+  pub height: u32,
+  /// Wait for the display's vertical blank before swapping buffers.
+  pub vsync: bool,
+  /// Use the primary monitor at `width` x `height` instead of a window.
+  pub fullscreen: bool,
+  /// Samples per pixel for multisample anti-aliasing, 0 to turn it off.
+  pub msaa_samples: u32,
+}
+
+impl Default for WindowConfig {
+  fn default() -> Self {
+    WindowConfig {
+      title: "Cogwheel".into(),
+      width: 800,
+      height: 600,
+      vsync: true,
+      fullscreen: false,
+      msaa_samples: 4,
+    }
+  }
+}
+
+/// A game built on cogwheel. The runner creates the window and the OpenGL
+/// context, then calls these in order:
+///
+/// * `init` once, to build the app after the context exists (so shaders,
+///   textures and scenes can be loaded in it),
+/// * every frame: `on_event` for each window event, `fixed_update` as many
+///   times as fixed steps have passed, `update` once, then `render`,
+/// * `shutdown` once the window is closing, before the app is dropped.
+pub trait App: Sized {
+  fn init(ctx: &mut AppContext) -> Result<Self>;
+
+  /// Advances the simulation by exactly `step` seconds. Anything that must
+  /// behave the same at every frame rate (movement, physics, gameplay
+  /// systems) belongs here.
+  fn fixed_update(&mut self, _ctx: &mut AppContext, _step: f64) -> () {}
+
+  /// Runs once per frame; `delta_time` is the time since the last frame.
+  fn update(&mut self, _ctx: &mut AppContext, _delta_time: f64) -> () {}
+
+  /// `alpha` is how far the frame is between the last simulated state and
+  /// the next one (0 to 1). Blending the previous and current state by it,
+  /// e.g. with `Transform::interpolate`, hides the step rate.
+  fn render(&mut self, _ctx: &mut AppContext, _alpha: f32) -> () {}
+
+  fn on_event(&mut self, _ctx: &mut AppContext, _event: &WindowEvent) -> () {}
+
+  fn shutdown(&mut self, _ctx: &mut AppContext) -> () {}
+}
+
+/// The window and timing state an `App` can reach from its callbacks.
+pub struct AppContext {
+  // Declared first so it is deleted before the window takes the context
+  // down with it.
+  fallback_texture: Texture,
+  glfw: glfw::Glfw,
+  window: PWindow,
+  config: WindowConfig,
+  framebuffer_size: (u32, u32),
+  timestep: FixedTimestep,
+  input: Input,
+  // Joystick slots a gamepad was polled from, so only those get
+  // disconnected and virtual gamepads in the other slots stay.
+  physical_gamepads: HashSet<usize>,
+}
+
+impl AppContext {
+  pub fn window(&self) -> &glfw::Window {
+    &self.window
+  }
+
+  pub fn window_mut(&mut self) -> &mut glfw::Window {
+    &mut self.window
+  }
+
+  pub fn config(&self) -> &WindowConfig {
+    &self.config
+  }
+
+  /// Size of the drawable area in pixels. Follows resizes, and may differ
+  /// from the configured window size on high-DPI displays.
+  pub fn framebuffer_size(&self) -> (u32, u32) {
+    self.framebuffer_size
+  }
+
+  /// Width over height of the drawable area, for projection matrices.
+  pub fn aspect_ratio(&self) -> f32 {
+    let (width, height) = self.framebuffer_size;
+    width as f32 / height.max(1) as f32
+  }
+
+  /// Seconds since the window was created.
+  pub fn time(&self) -> f64 {
+    self.glfw.get_time()
+  }
+
+  /// The simulation clock: step length, steps taken and the current alpha.
+  pub fn timestep(&self) -> &FixedTimestep {
+    &self.timestep
+  }
+
+  /// A 1x1 white texture in this window's context, for drawing materials
+  /// without all their maps (see `material::fallback_texture_on`).
+  pub fn fallback_texture(&self) -> &Texture {
+    &self.fallback_texture
+  }
+
+  /// Keyboard, mouse and gamepad state, and the actions bound to them.
+  pub fn input(&self) -> &Input {
+    &self.input
+  }
+
+  pub fn input_mut(&mut self) -> &mut Input {
+    &mut self.input
+  }
+
+  /// The cursor in framebuffer pixels, the coordinates `Camera` expects.
+  /// They differ from the window coordinates `Input` reports on high-DPI
+  /// displays.
+  pub fn cursor_position(&self) -> Option<(f32, f32)> {
+    let (x, y) = self.input.cursor_position()?;
+    let (window_width, window_height) = self.window.get_size();
+    let (width, height) = self.framebuffer_size;
+    Some((
+      x as f32 * width as f32 / window_width.max(1) as f32,
+      y as f32 * height as f32 / window_height.max(1) as f32,
+    ))
+  }
+
+  pub fn is_key_down(&self, key: Key) -> bool {
+    self.window.get_key(key) != Action::Release
+  }
+
+  /// Ends the loop after the current frame.
+  pub fn quit(&mut self) -> () {
+    self.window.set_should_close(true);
+  }
+
+  fn handle_event(&mut self, event: &WindowEvent) -> () {
+    self.input.handle_event(event);
+
+    if let WindowEvent::FramebufferSize(width, height) = *event {
+      self.framebuffer_size = (width.max(0) as u32, height.max(0) as u32);
+      unsafe {
+        gl::Viewport(0, 0, width, height);
+      }
+    }
+  }
+
+  // Feeds every connected gamepad into the input state. Joysticks that no
+  // mapping matches aren't gamepads and are left out.
+  fn poll_gamepads(&mut self) -> () {
+    for id in (0..16).filter_map(JoystickId::from_i32) {
+      let joystick = self.glfw.get_joystick(id);
+      let state = joystick.is_gamepad().then(|| joystick.get_gamepad_state()).flatten();
+      let slot = id as usize;
+      match state {
+        Some(state) => {
+          let name = joystick.get_gamepad_name().unwrap_or_default();
+          self.input.update_gamepad(slot, &name, state.into());
+          self.physical_gamepads.insert(slot);
+        }
+        None => {
+          if self.physical_gamepads.remove(&slot) {
+            self.input.disconnect_gamepad(slot);
+          }
+        }
+      }
+    }
+  }
+
+  /// Adds controller mappings in the SDL_GameControllerDB format (one
+  /// `guid,name,a:b0,b:b1,...` line per controller), e.g. a copy of
+  /// `gamecontrollerdb.txt`. GLFW ships with a built-in database; this
+  /// adds controllers it doesn't know or overrides its entries.
+  pub fn load_gamepad_mappings(&mut self, path: &str) -> Result<()> {
+    let mappings = std::fs::read_to_string(path).map_err(|source| Error::Io { path: path.into(), source })?;
+
+    if !self.glfw.update_gamepad_mappings(&mappings) {
+      let message = "GLFW rejected the gamepad mappings".to_string();
+      return Err(Error::Format { path: path.into(), message });
+    }
+    Ok(())
+  }
+}
+
+/// Opens a window as described by `config`, builds the app and runs it
+/// until the window is closed. `fixed_update` is called at the rate set by
+/// `timestep`.
+pub fn run<A: App>(config: WindowConfig, timestep: FixedTimestep) -> Result<()> {
+  let (mut ctx, events) = create_context(config, timestep)?;
+  let mut app = A::init(&mut ctx)?;
+
+  let mut last_frame = ctx.time();
+
+  while !ctx.window.should_close() {
+    let current_frame = ctx.time();
+    let delta_time = current_frame - last_frame;
+    last_frame = current_frame;
+
+    ctx.glfw.poll_events();
+    for (_, event) in glfw::flush_messages(&events) {
+      ctx.handle_event(&event);
+      app.on_event(&mut ctx, &event);
+    }
+    ctx.poll_gamepads();
+    ctx.input.update();
+
+    let steps = ctx.timestep.advance(delta_time);
+    let step = ctx.timestep.step();
+    for _ in 0..steps {
+      app.fixed_update(&mut ctx, step);
+    }
+
+    app.update(&mut ctx, delta_time);
+    let alpha = ctx.timestep.alpha() as f32;
+    app.render(&mut ctx, alpha);
+    ctx.window.swap_buffers();
+  }
+
+  app.shutdown(&mut ctx);
+  // The app's GL resources must go before the context that owns them.
+  drop(app);
+
+  Ok(())
+}
+
+fn create_context(
+  config: WindowConfig, timestep: FixedTimestep
+) -> Result<(AppContext, GlfwReceiver<(f64, WindowEvent)>)> {
+  let mut glfw = glfw::init(glfw::fail_on_errors)
+    .map_err(|e| Error::ContextCreation { message: format!("could not initialize GLFW: {}", e) })?;
+
+  glfw.window_hint(WindowHint::ContextVersion(3, 3));
+  glfw.window_hint(WindowHint::OpenGlProfile(OpenGlProfileHint::Core));
+  if config.msaa_samples > 0 {
+    glfw.window_hint(WindowHint::Samples(Some(config.msaa_samples)));
+  }
+
+  #[cfg(target_os = "macos")]
+  glfw.window_hint(WindowHint::OpenGlForwardCompat(true));
+
+  let (mut window, events) = glfw
+    .with_primary_monitor(|glfw, monitor| {
+      let mode = match monitor {
+        Some(monitor) if config.fullscreen => WindowMode::FullScreen(monitor),
+        _ => WindowMode::Windowed,
+      };
+      glfw.create_window(config.width, config.height, &config.title, mode)
+    })
+    .ok_or_else(|| Error::ContextCreation { message: "could not create a GLFW window".into() })?;
+
+  window.make_current();
+  window.set_all_polling(true);
+  glfw.set_swap_interval(if config.vsync { SwapInterval::Sync(1) } else { SwapInterval::None });
+
+  // This loads OpenGL function pointers (the ones used inside unsafe blocks)
+  gl::load_with(|s| window.get_proc_address(s) as *const _);
+  GlCapabilities::detect();
+
+  let (width, height) = window.get_framebuffer_size();
+  unsafe {
+    gl::Viewport(0, 0, width, height);
+    if config.msaa_samples > 0 {
+      gl::Enable(gl::MULTISAMPLE);
+    }
+  }
+
+  let ctx = AppContext {
+    fallback_texture: fallback_texture_on(&mut GlDevice),
+    glfw,
+    window,
+    config,
+    framebuffer_size: (width.max(0) as u32, height.max(0) as u32),
+    timestep,
+    input: Input::default(),
+    physical_gamepads: HashSet::new(),
+  };
+  Ok((ctx, events))
+}
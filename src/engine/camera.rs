@@ -1,123 +1,286 @@
-use cgmath::{vec3, InnerSpace};
+use cgmath::{ortho, perspective, vec3, vec4, Deg, InnerSpace, Matrix4, SquareMatrix};
 
+use super::math::{Frustum, Ray};
+use super::rendering::DepthFunc;
 use super::types::*;
 
-static mut FIRST_MOUSE_MOVEMENT: bool = true;
+pub mod controller;
+pub use controller::*;
 
+/// How the camera maps what it sees onto the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+  /// Perspective with the camera's field of view.
+  Perspective { near: f32, far: f32 },
+  /// Perspective without a far plane. Depth is reversed (1 at `near`, 0 at
+  /// infinity) and goes from 0 to 1, which keeps its precision far away.
+  /// The device has to be switched over with `RenderDevice::set_reverse_z`.
+  InfinitePerspective { near: f32 },
+  /// Parallel projection. `size` is the height of the view in world units;
+  /// the width follows the aspect ratio.
+  Orthographic { size: f32, near: f32, far: f32 },
+}
+
+impl Default for Projection {
+  fn default() -> Self {
+    Projection::Perspective { near: 0.1, far: 100.0 }
+  }
+}
+
+impl Projection {
+  pub fn is_reverse_z(&self) -> bool {
+    matches!(self, Projection::InfinitePerspective { .. })
+  }
+
+  /// Distances of the near and far planes. An infinite perspective has no
+  /// far plane.
+  pub fn depth_range(&self) -> (f32, Option<f32>) {
+    match *self {
+      Projection::Perspective { near, far } | Projection::Orthographic { near, far, .. } => (near, Some(far)),
+      Projection::InfinitePerspective { near } => (near, None),
+    }
+  }
+
+  /// The projection matrix for a vertical field of view in degrees.
+  pub fn matrix(&self, fov: f32, aspect: f32) -> Mat4 {
+    match *self {
+      Projection::Perspective { near, far } => perspective(Deg(fov), aspect, near, far),
+      Projection::InfinitePerspective { near } => {
+        let f = 1.0 / (fov.to_radians() / 2.0).tan();
+        Matrix4::new(
+          f / aspect, 0.0, 0.0, 0.0,
+          0.0, f, 0.0, 0.0,
+          0.0, 0.0, 0.0, -1.0,
+          0.0, 0.0, near, 0.0,
+        )
+      }
+      Projection::Orthographic { size, near, far } => {
+        let (half_width, half_height) = (size * aspect / 2.0, size / 2.0);
+        ortho(-half_width, half_width, -half_height, half_height, near, far)
+      }
+    }
+  }
+
+  // Depth of the near plane and of some point past it, in normalized device
+  // coordinates.
+  fn ndc_depths(&self) -> (f32, f32) {
+    if self.is_reverse_z() {
+      (1.0, 0.5)
+    } else {
+      (-1.0, 0.0)
+    }
+  }
+}
+
+/// Where the camera is, where it looks and how it projects. The camera
+/// itself doesn't react to input; a `CameraController` moves it.
+///
+/// Screen coordinates are in pixels of the viewport, with the origin in the
+/// top-left corner. Keep the viewport size up to date with
+/// `set_viewport_size` when the framebuffer is resized.
 #[derive(Debug)]
 pub struct Camera {
   camera_pos: Point3,
   camera_front: Vec3,
   camera_up: Vec3,
   camera_right: Vec3,
-  world_up: Vec3,
-  pub yaw: f32,
-  pitch: f32,
-  last_x: f32,
-  last_y: f32,
-  fov: f32
+  fov: f32,
+  projection: Projection,
+  viewport_size: (u32, u32)
 }
 
 impl Camera {
-  pub fn new(camera_pos: Point3, win_w: u32, win_h: u32) -> Self {
-    Self { 
+  /// A camera at `camera_pos` looking down -Z, with the default
+  /// perspective projection and an 800x600 viewport.
+  pub fn new(camera_pos: Point3) -> Self {
+    Self {
       camera_pos,
       camera_front: vec3(0.0, 0.0, -1.0),
       camera_up: vec3(0.0, 1.0, 0.0),
-      camera_right: vec3(0.0, 0.0, 0.0),
-      world_up: vec3(0.0, 1.0, 0.0),
-      yaw: -90.0,
-      pitch: 0.0,
-      last_x: win_w as f32 / 2.0,
-      last_y: win_h as f32 / 2.0,
-      fov: 45.0
+      camera_right: vec3(1.0, 0.0, 0.0),
+      fov: 45.0,
+      projection: Projection::default(),
+      viewport_size: (800, 600)
     }
   }
 
-  pub fn get_pos(&mut self) -> Point3 {
-    return self.camera_pos;
+  pub fn get_pos(&self) -> Point3 {
+    self.camera_pos
   }
 
-  pub fn get_front(&mut self) -> Vec3 {
-    return self.camera_front;
+  pub fn get_front(&self) -> Vec3 {
+    self.camera_front
   }
 
-  pub fn get_view_matrix(&mut self) -> Mat4 {
-    return Mat4::look_at_rh(self.camera_pos, self.camera_pos + self.camera_front, self.camera_up);
+  pub fn get_up(&self) -> Vec3 {
+    self.camera_up
   }
 
-  // Updates camera position when moving front (f) or back (b)
-  pub fn update_camera_pos_fb(&mut self, change: f32) -> () {
-    self.camera_pos += self.camera_front * change;
+  pub fn get_right(&self) -> Vec3 {
+    self.camera_right
   }
 
-  // Updates camera position when moving left (l) or right (r)
-  pub fn update_camera_pos_lr(&mut self, change: f32) -> () {
-    self.camera_pos += self.camera_front.cross(self.camera_up) * change;
+  pub fn get_view_matrix(&self) -> Mat4 {
+    Mat4::look_at_rh(self.camera_pos, self.camera_pos + self.camera_front, self.camera_up)
   }
 
-  pub fn update_coordinates(&mut self, x: f32, y: f32) -> () {
-    self.last_x = x;
-    self.last_y = y;
+  /// Vertical field of view in degrees.
+  pub fn get_fov(&self) -> f32 {
+    self.fov
   }
 
-  pub fn handle_mouse(&mut self, x_pos: f64, y_pos: f64) {
-    let x: f32 = x_pos as f32;
-    let y: f32 = y_pos as f32;
+  pub fn set_pos(&mut self, camera_pos: Point3) -> () {
+    self.camera_pos = camera_pos;
+  }
 
-    unsafe {
-      if FIRST_MOUSE_MOVEMENT {
-        self.last_x = x;
-        self.last_y = y;
-        FIRST_MOUSE_MOVEMENT = false;
-      }
+  pub fn set_fov(&mut self, fov: f32) -> () {
+    self.fov = fov;
+  }
+
+  /// Points the camera along `front`. `up` only needs to be roughly
+  /// perpendicular to it; it is straightened out here.
+  pub fn set_orientation(&mut self, front: Vec3, up: Vec3) -> () {
+    self.camera_front = front.normalize();
+    self.camera_right = self.camera_front.cross(up).normalize();
+    self.camera_up = self.camera_right.cross(self.camera_front).normalize();
+  }
+
+  pub fn look_at(&mut self, target: Point3, up: Vec3) -> () {
+    self.set_orientation(target - self.camera_pos, up);
+  }
+
+  pub fn get_projection(&self) -> Projection {
+    self.projection
+  }
+
+  pub fn set_projection(&mut self, projection: Projection) -> () {
+    self.projection = projection;
+  }
+
+  pub fn get_viewport_size(&self) -> (u32, u32) {
+    self.viewport_size
+  }
+
+  /// Call with the new size on `WindowEvent::FramebufferSize`. Sizes of 0
+  /// (a minimized window) are ignored so the aspect ratio stays usable.
+  pub fn set_viewport_size(&mut self, width: u32, height: u32) -> () {
+    if width > 0 && height > 0 {
+      self.viewport_size = (width, height);
     }
+  }
 
-    let mut x_offset = x - self.last_x;
-    let mut y_offset = self.last_y - y;
-    self.last_x = x;
-    self.last_y = y;
+  pub fn get_aspect_ratio(&self) -> f32 {
+    self.viewport_size.0 as f32 / self.viewport_size.1 as f32
+  }
 
-    let sens = 0.1;
-    x_offset *= sens;
-    y_offset *= sens;
+  pub fn get_projection_matrix(&self) -> Mat4 {
+    self.projection.matrix(self.fov, self.get_aspect_ratio())
+  }
 
-    self.yaw += x_offset;
-    self.pitch += y_offset;
+  pub fn view_projection(&self) -> Mat4 {
+    self.get_projection_matrix() * self.get_view_matrix()
+  }
 
-    if self.pitch > 89.0 {
-      self.pitch = 89.0;
+  /// Corners of the part of the view between the distances `near` and
+  /// `far` along the camera's front: the near corners, then the far ones.
+  pub fn slice_corners(&self, near: f32, far: f32) -> [Point3; 8] {
+    let aspect = self.get_aspect_ratio();
+    let half_height = |distance: f32| match self.projection {
+      Projection::Orthographic { size, .. } => size / 2.0,
+      _ => distance * (self.fov.to_radians() / 2.0).tan(),
+    };
+    let mut corners = [self.camera_pos; 8];
+    for (i, distance) in [near, far].into_iter().enumerate() {
+      let center = self.camera_pos + self.camera_front * distance;
+      let up = self.camera_up * half_height(distance);
+      let right = self.camera_right * half_height(distance) * aspect;
+      corners[i * 4] = center - right - up;
+      corners[i * 4 + 1] = center + right - up;
+      corners[i * 4 + 2] = center + right + up;
+      corners[i * 4 + 3] = center - right + up;
     }
-    else if self.pitch < -89.0 {
-      self.pitch = -89.0;
+    corners
+  }
+
+  /// What the camera can see, for culling.
+  pub fn frustum(&self) -> Frustum {
+    if self.projection.is_reverse_z() {
+      Frustum::from_matrix_zero_to_one(&self.view_projection())
+    } else {
+      Frustum::from_matrix(&self.view_projection())
     }
+  }
 
-    self.update_camera_pos();
+  /// The depth test that matches the projection.
+  pub fn depth_func(&self) -> DepthFunc {
+    if self.projection.is_reverse_z() {
+      DepthFunc::Greater
+    } else {
+      DepthFunc::Less
+    }
   }
 
-  pub fn handle_scroll(&mut self, scroll: f64) {
-    self.fov -= scroll as f32;
+  /// The ray from the camera through a point on the screen, starting on the
+  /// near plane. For an orthographic projection the rays are parallel.
+  pub fn screen_to_ray(&self, x: f32, y: f32) -> Ray {
+    let (width, height) = self.viewport_size;
+    let ndc_x = 2.0 * x / width as f32 - 1.0;
+    let ndc_y = 1.0 - 2.0 * y / height as f32;
 
-    if self.fov < 1.0 {
-      self.fov = 1.0;
-    }
-    else if self.fov > 65.0 {
-      self.fov = 65.0;
+    let inverse = self.view_projection().invert().unwrap_or_else(Mat4::identity);
+    let unproject = |depth: f32| {
+      let point = inverse * vec4(ndc_x, ndc_y, depth, 1.0);
+      Point3::new(point.x / point.w, point.y / point.w, point.z / point.w)
+    };
+
+    let (near_depth, far_depth) = self.projection.ndc_depths();
+    let near = unproject(near_depth);
+    Ray::new(near, unproject(far_depth) - near)
+  }
+
+  /// Where a point in the world shows up on the screen, or `None` if it is
+  /// behind the camera. Points off the sides of the screen are still
+  /// returned, outside the viewport.
+  pub fn world_to_screen(&self, point: Point3) -> Option<(f32, f32)> {
+    let clip = self.view_projection() * point.to_homogeneous();
+    if clip.w <= 0.0 {
+      return None;
     }
+
+    let (width, height) = self.viewport_size;
+    let (ndc_x, ndc_y) = (clip.x / clip.w, clip.y / clip.w);
+    Some(((ndc_x + 1.0) / 2.0 * width as f32, (1.0 - ndc_y) / 2.0 * height as f32))
   }
+}
 
-  pub fn update_camera_pos(&mut self) {
-    let mut front: Vec3 = vec3(0.0,0.0,0.0);
-    front.x = self.yaw.to_radians().cos() * self.pitch.to_radians().cos();
-    front.y = self.pitch.to_radians().sin();
-    front.z = self.yaw.to_radians().sin() * self.pitch.to_radians().cos();
+#[cfg(test)]
+mod tests {
+  use cgmath::point3;
 
-    self.camera_front = front.normalize();
-    self.camera_right = self.camera_front.cross(self.world_up).normalize();
-    self.camera_up = self.camera_right.cross(self.camera_front).normalize();
+  use super::*;
+
+  #[test]
+  fn the_center_of_the_screen_looks_straight_ahead() {
+    let camera = Camera::new(point3(0.0, 0.0, 3.0));
+    let ray = camera.screen_to_ray(400.0, 300.0);
+    assert!((ray.direction - vec3(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+    assert!(ray.origin.x.abs() < 1e-4 && ray.origin.y.abs() < 1e-4 && ray.origin.z < 3.0);
   }
 
-  pub fn get_fov(&mut self) -> f32 {
-    return self.fov;
+  #[test]
+  fn screen_rays_pass_through_what_is_shown_there() {
+    let mut camera = Camera::new(point3(1.0, 2.0, 5.0));
+    camera.look_at(point3(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+    let target = point3(0.5, -0.25, -1.0);
+    let (x, y) = camera.world_to_screen(target).unwrap();
+
+    let ray = camera.screen_to_ray(x, y);
+    let to_target = target - ray.origin;
+    let off_ray = to_target - ray.direction * to_target.dot(ray.direction);
+    assert!(off_ray.magnitude() < 1e-3, "{:?} misses by {}", ray, off_ray.magnitude());
+    assert!(to_target.dot(ray.direction) > 0.0);
+
+    let corner = camera.screen_to_ray(0.0, 0.0).direction;
+    assert!(corner.dot(camera.get_right()) < 0.0 && corner.dot(camera.get_up()) > 0.0);
   }
-}
\ No newline at end of file
+}
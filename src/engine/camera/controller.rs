@@ -0,0 +1,354 @@
+use cgmath::{vec3, Deg, EuclideanSpace, InnerSpace, Matrix3, Rotation, Rotation3, Zero};
+
+use super::Camera;
+use crate::engine::input::Input;
+use crate::engine::types::{Point3, Quat, Vec3};
+
+/// What a controller reacts to in one frame. It usually comes from the
+/// input system through `from_input`, but cutscenes or tests can fill it
+/// in directly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ControllerInput {
+  /// Look movement this frame with x to the right and y downwards, in
+  /// mouse pixels (or anything scaled like them).
+  pub look: (f32, f32),
+  /// Look speed, like from a stick, in the units of `look` per second.
+  /// Controllers scale it by the time passed, so it turns equally fast at
+  /// any frame rate.
+  pub look_rate: (f32, f32),
+  /// Movement relative to the camera: x right, y up and z forward, each
+  /// from -1 to 1.
+  pub movement: Vec3,
+  /// Roll from -1 (counter-clockwise) to 1 (clockwise).
+  pub roll: f32,
+  /// Zoom steps, positive to zoom in (like scrolling up).
+  pub zoom: f32,
+}
+
+impl Default for ControllerInput {
+  fn default() -> Self {
+    ControllerInput { look: (0.0, 0.0), look_rate: (0.0, 0.0), movement: Vec3::zero(), roll: 0.0, zoom: 0.0 }
+  }
+}
+
+impl ControllerInput {
+  /// Reads the axes `look_x`, `look_y`, `turn_x`, `turn_y`, `move_right`,
+  /// `move_up`, `move_forward`, `roll` and `zoom`. Axes that aren't bound
+  /// read as 0.
+  pub fn from_input(input: &Input) -> Self {
+    let movement = vec3(input.axis("move_right"), input.axis("move_up"), input.axis("move_forward"));
+    ControllerInput {
+      look: (input.axis("look_x"), input.axis("look_y")),
+      look_rate: (input.axis("turn_x"), input.axis("turn_y")),
+      // Keyboard and stick together shouldn't move faster than either.
+      movement: if movement.magnitude2() > 1.0 { movement.normalize() } else { movement },
+      roll: input.axis("roll").clamp(-1.0, 1.0),
+      zoom: input.axis("zoom"),
+    }
+  }
+
+  /// How far to look over `delta_time` seconds: `look` plus `look_rate`
+  /// for that long.
+  pub fn look_delta(&self, delta_time: f32) -> (f32, f32) {
+    (self.look.0 + self.look_rate.0 * delta_time, self.look.1 + self.look_rate.1 * delta_time)
+  }
+}
+
+/// Moves a `Camera` in response to input. Settings such as sensitivity and
+/// limits are held by each controller, so different cameras can feel
+/// different.
+pub trait CameraController {
+  /// Takes over the camera where it currently is, so switching controllers
+  /// doesn't make it jump.
+  fn attach(&mut self, _camera: &Camera) -> () {}
+
+  fn update(&mut self, camera: &mut Camera, input: &ControllerInput, delta_time: f32) -> ();
+}
+
+fn up() -> Vec3 {
+  Vec3::unit_y()
+}
+
+// Yaw and pitch in degrees, yaw -90 looking down -Z.
+fn front_from_angles(yaw: f32, pitch: f32) -> Vec3 {
+  let (yaw, pitch) = (yaw.to_radians(), pitch.to_radians());
+  vec3(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos()).normalize()
+}
+
+fn angles_from_front(front: Vec3) -> (f32, f32) {
+  let front = front.normalize();
+  (front.z.atan2(front.x).to_degrees(), front.y.clamp(-1.0, 1.0).asin().to_degrees())
+}
+
+fn zoom_fov(camera: &mut Camera, zoom: f32, min_fov: f32, max_fov: f32) -> () {
+  if zoom != 0.0 {
+    camera.set_fov((camera.get_fov() - zoom).clamp(min_fov, max_fov));
+  }
+}
+
+/// First-person walking: looking around with yaw and pitch, moving on the
+/// ground plane regardless of where the camera looks, and straight up or
+/// down along the world's up axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FpsController {
+  pub yaw: f32,
+  pub pitch: f32,
+  /// Degrees turned per unit of look input.
+  pub sensitivity: f32,
+  /// Units per second.
+  pub move_speed: f32,
+  /// Pitch is kept within plus or minus this many degrees.
+  pub pitch_limit: f32,
+  pub min_fov: f32,
+  pub max_fov: f32,
+}
+
+impl Default for FpsController {
+  fn default() -> Self {
+    FpsController {
+      yaw: -90.0,
+      pitch: 0.0,
+      sensitivity: 0.1,
+      move_speed: 2.5,
+      pitch_limit: 89.0,
+      min_fov: 1.0,
+      max_fov: 65.0,
+    }
+  }
+}
+
+impl CameraController for FpsController {
+  fn attach(&mut self, camera: &Camera) -> () {
+    let (yaw, pitch) = angles_from_front(camera.get_front());
+    self.yaw = yaw;
+    self.pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+  }
+
+  fn update(&mut self, camera: &mut Camera, input: &ControllerInput, delta_time: f32) -> () {
+    let look = input.look_delta(delta_time);
+    self.yaw += look.0 * self.sensitivity;
+    self.pitch = (self.pitch - look.1 * self.sensitivity).clamp(-self.pitch_limit, self.pitch_limit);
+    camera.set_orientation(front_from_angles(self.yaw, self.pitch), up());
+
+    let forward = front_from_angles(self.yaw, 0.0);
+    let right = forward.cross(up());
+    let movement = right * input.movement.x + up() * input.movement.y + forward * input.movement.z;
+    camera.set_pos(camera.get_pos() + movement * self.move_speed * delta_time);
+
+    zoom_fov(camera, input.zoom, self.min_fov, self.max_fov);
+  }
+}
+
+/// Six degrees of freedom: looking, rolling and moving are all relative to
+/// the camera itself, like a spaceship or a debug camera.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FreeFlyController {
+  pub orientation: Quat,
+  /// Degrees turned per unit of look input.
+  pub sensitivity: f32,
+  /// Units per second.
+  pub move_speed: f32,
+  /// Degrees per second at full roll input.
+  pub roll_speed: f32,
+  pub min_fov: f32,
+  pub max_fov: f32,
+}
+
+impl Default for FreeFlyController {
+  fn default() -> Self {
+    FreeFlyController {
+      orientation: Quat::from_axis_angle(Vec3::unit_y(), Deg(0.0)),
+      sensitivity: 0.1,
+      move_speed: 5.0,
+      roll_speed: 90.0,
+      min_fov: 1.0,
+      max_fov: 65.0,
+    }
+  }
+}
+
+impl CameraController for FreeFlyController {
+  fn attach(&mut self, camera: &Camera) -> () {
+    let basis = Matrix3::from_cols(camera.get_right(), camera.get_up(), -camera.get_front());
+    self.orientation = Quat::from(basis).normalize();
+  }
+
+  fn update(&mut self, camera: &mut Camera, input: &ControllerInput, delta_time: f32) -> () {
+    // Rotations are about the camera's own axes: it looks down -Z.
+    let look = input.look_delta(delta_time);
+    let yaw = Quat::from_axis_angle(Vec3::unit_y(), Deg(-look.0 * self.sensitivity));
+    let pitch = Quat::from_axis_angle(Vec3::unit_x(), Deg(-look.1 * self.sensitivity));
+    let roll = Quat::from_axis_angle(Vec3::unit_z(), Deg(-input.roll * self.roll_speed * delta_time));
+    self.orientation = (self.orientation * yaw * pitch * roll).normalize();
+
+    let front = self.orientation.rotate_vector(-Vec3::unit_z());
+    let up = self.orientation.rotate_vector(Vec3::unit_y());
+    camera.set_orientation(front, up);
+
+    let movement =
+      camera.get_right() * input.movement.x + camera.get_up() * input.movement.y + front * input.movement.z;
+    camera.set_pos(camera.get_pos() + movement * self.move_speed * delta_time);
+
+    zoom_fov(camera, input.zoom, self.min_fov, self.max_fov);
+  }
+}
+
+/// Circles around a target point. Looking moves the camera around the
+/// target, zooming changes the distance and moving pans the target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrbitController {
+  pub target: Point3,
+  pub distance: f32,
+  /// Angle around the world's up axis, in degrees. 90 puts the camera on
+  /// the +Z side of the target.
+  pub yaw: f32,
+  /// Height angle above the target, in degrees.
+  pub pitch: f32,
+  /// Degrees turned per unit of look input.
+  pub sensitivity: f32,
+  /// Fraction of the distance each zoom step takes off (or adds).
+  pub zoom_speed: f32,
+  /// Target movement per second, as a multiple of the distance.
+  pub pan_speed: f32,
+  pub min_distance: f32,
+  pub max_distance: f32,
+  pub pitch_limit: f32,
+}
+
+impl Default for OrbitController {
+  fn default() -> Self {
+    OrbitController {
+      target: Point3::origin(),
+      distance: 5.0,
+      yaw: 90.0,
+      pitch: 20.0,
+      sensitivity: 0.3,
+      zoom_speed: 0.1,
+      pan_speed: 0.5,
+      min_distance: 0.5,
+      max_distance: 100.0,
+      pitch_limit: 89.0,
+    }
+  }
+}
+
+impl CameraController for OrbitController {
+  fn attach(&mut self, camera: &Camera) -> () {
+    let front = camera.get_front();
+    self.target = camera.get_pos() + front * self.distance;
+    let (yaw, pitch) = angles_from_front(-front);
+    self.yaw = yaw;
+    self.pitch = pitch.clamp(-self.pitch_limit, self.pitch_limit);
+  }
+
+  fn update(&mut self, camera: &mut Camera, input: &ControllerInput, delta_time: f32) -> () {
+    let look = input.look_delta(delta_time);
+    self.yaw += look.0 * self.sensitivity;
+    self.pitch = (self.pitch + look.1 * self.sensitivity).clamp(-self.pitch_limit, self.pitch_limit);
+    self.distance = (self.distance * (1.0 - input.zoom * self.zoom_speed))
+      .clamp(self.min_distance, self.max_distance);
+
+    // Pan on the ground plane relative to the current view.
+    let forward = -front_from_angles(self.yaw, 0.0);
+    let right = forward.cross(up());
+    let pan = right * input.movement.x + up() * input.movement.y + forward * input.movement.z;
+    self.target += pan * self.pan_speed * self.distance * delta_time;
+
+    camera.set_pos(self.target + front_from_angles(self.yaw, self.pitch) * self.distance);
+    camera.look_at(self.target, up());
+  }
+}
+
+/// Trails behind a moving target (set with `set_target` every frame), easing
+/// towards its spot instead of sticking to it rigidly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FollowController {
+  pub target: Point3,
+  /// The direction the target faces. The offset is relative to it.
+  pub target_forward: Vec3,
+  /// Where the camera sits relative to the target: x to its right, y up
+  /// and z behind it.
+  pub offset: Vec3,
+  /// Seconds the camera takes to close about two thirds of the gap to its
+  /// spot. 0 follows rigidly.
+  pub smoothing: f32,
+  /// How far in front of the target the camera looks.
+  pub look_ahead: f32,
+  position: Option<Point3>,
+}
+
+impl Default for FollowController {
+  fn default() -> Self {
+    FollowController {
+      target: Point3::origin(),
+      target_forward: -Vec3::unit_z(),
+      offset: vec3(0.0, 2.0, 6.0),
+      smoothing: 0.25,
+      look_ahead: 2.0,
+      position: None,
+    }
+  }
+}
+
+impl FollowController {
+  pub fn set_target(&mut self, target: Point3, forward: Vec3) -> () {
+    self.target = target;
+    self.target_forward = forward;
+  }
+}
+
+impl CameraController for FollowController {
+  fn attach(&mut self, camera: &Camera) -> () {
+    self.position = Some(camera.get_pos());
+  }
+
+  fn update(&mut self, camera: &mut Camera, _input: &ControllerInput, delta_time: f32) -> () {
+    let flat = vec3(self.target_forward.x, 0.0, self.target_forward.z);
+    let forward = if flat.magnitude2() > 0.0 { flat.normalize() } else { -Vec3::unit_z() };
+    let right = forward.cross(up());
+    let spot = self.target + right * self.offset.x + up() * self.offset.y - forward * self.offset.z;
+
+    // Exponential easing, so the result doesn't depend on the frame rate.
+    let t = if self.smoothing > 0.0 { 1.0 - (-delta_time / self.smoothing).exp() } else { 1.0 };
+    let position = match self.position {
+      Some(position) => position + (spot - position) * t,
+      None => spot,
+    };
+    self.position = Some(position);
+
+    camera.set_pos(position);
+    camera.look_at(self.target + forward * self.look_ahead, up());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn look_rate_turns_the_same_at_any_frame_rate() {
+    let input = ControllerInput { look_rate: (100.0, 0.0), ..Default::default() };
+    let mut camera = Camera::new(Point3::origin());
+
+    let mut slow = FpsController::default();
+    slow.update(&mut camera, &input, 0.5);
+    let mut fast = FpsController::default();
+    for _ in 0..4 {
+      fast.update(&mut camera, &input, 0.125);
+    }
+
+    assert_eq!(slow.yaw, fast.yaw);
+    assert_eq!(slow.yaw, -90.0 + 50.0 * slow.sensitivity);
+  }
+
+  #[test]
+  fn look_is_applied_once_whatever_the_frame_time() {
+    let input = ControllerInput { look: (100.0, 0.0), ..Default::default() };
+    let mut camera = Camera::new(Point3::origin());
+
+    let mut controller = FpsController::default();
+    controller.update(&mut camera, &input, 0.5);
+
+    assert_eq!(controller.yaw, -90.0 + 100.0 * controller.sensitivity);
+  }
+}
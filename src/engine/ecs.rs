@@ -0,0 +1,25 @@
+//! A small entity-component-system.
+//!
+//! Entities are plain ids, components are any `'static` type stored per type
+//! in a `World`, and systems are closures run once per fixed step by a
+//! `Schedule`. Gameplay code adds behaviour by adding components and
+//! systems; the app only has to run the schedule from `fixed_update` and
+//! draw what is in the world.
+
+pub mod entity;
+pub use entity::*;
+
+pub mod storage;
+pub use storage::*;
+
+pub mod query;
+pub use query::*;
+
+pub mod world;
+pub use world::*;
+
+pub mod schedule;
+pub use schedule::*;
+
+pub mod components;
+pub use components::*;
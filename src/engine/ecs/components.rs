@@ -0,0 +1,54 @@
+use std::rc::Rc;
+
+use crate::data::VertexArray;
+use crate::engine::material::Material;
+use crate::engine::object::Object;
+use crate::engine::rendering::{Geometry, GlDevice, RenderDevice, Uniform};
+use crate::engine::{Shader, Texture, Transform};
+
+use super::World;
+
+/// Uploaded geometry for an entity, along with the vertices it was built
+/// from. Both are shared, so many entities can use the same mesh.
+pub struct MeshComponent<D: RenderDevice = GlDevice> {
+  pub vertex_array: Rc<VertexArray>,
+  pub geometry: Rc<Geometry<D>>,
+}
+
+impl<D: RenderDevice> Clone for MeshComponent<D> {
+  fn clone(&self) -> Self {
+    MeshComponent { vertex_array: self.vertex_array.clone(), geometry: self.geometry.clone() }
+  }
+}
+
+impl<D: RenderDevice> Object<D> {
+  /// Splits the object into the components an entity is drawn from. The
+  /// material is a component of its own.
+  pub fn into_components(self) -> (Transform, MeshComponent<D>, Material<D>) {
+    let mesh = MeshComponent {
+      vertex_array: Rc::new(self.vertex_array),
+      geometry: Rc::new(self.geometry),
+    };
+    (self.transform, mesh, self.material)
+  }
+}
+
+/// Draws every entity that has a transform, a mesh and a material with the
+/// given (already activated) shader. `fallback` is bound in place of the
+/// maps a material doesn't have, e.g. `AppContext::fallback_texture`.
+pub fn render(world: &World, shader: &mut Shader, fallback: &Texture) -> () {
+  render_on(world, &mut GlDevice, shader, fallback);
+}
+
+/// `fallback` is bound in place of the maps a material doesn't have.
+pub fn render_on<D: RenderDevice + 'static>(
+  world: &World, device: &mut D, shader: &D::Pipeline, fallback: &D::Texture
+) -> () {
+  world.query::<(&Transform, &MeshComponent<D>, &Material<D>)>(
+    |_, (transform, mesh, material)| {
+      material.apply_on(device, shader, fallback);
+      device.set_uniform(shader, "model", Uniform::Mat4(transform.matrix()));
+      mesh.geometry.draw(device);
+    },
+  );
+}
@@ -0,0 +1,74 @@
+/// A thing in a `World`. Entities hold no data themselves; everything about
+/// them lives in components. The generation makes a handle of a despawned
+/// entity stale even after its index has been handed out again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+  index: u32,
+  generation: u32,
+}
+
+impl Entity {
+  pub fn index(&self) -> u32 {
+    self.index
+  }
+
+  pub fn generation(&self) -> u32 {
+    self.generation
+  }
+}
+
+/// Hands out entity ids and recycles the indices of despawned ones.
+#[derive(Debug, Default)]
+pub(crate) struct Entities {
+  generations: Vec<u32>,
+  alive: Vec<bool>,
+  free: Vec<u32>,
+}
+
+impl Entities {
+  pub fn alloc(&mut self) -> Entity {
+    match self.free.pop() {
+      Some(index) => {
+        self.alive[index as usize] = true;
+        Entity { index, generation: self.generations[index as usize] }
+      }
+      None => {
+        let index = self.generations.len() as u32;
+        self.generations.push(0);
+        self.alive.push(true);
+        Entity { index, generation: 0 }
+      }
+    }
+  }
+
+  /// Returns false if the entity was already gone.
+  pub fn free(&mut self, entity: Entity) -> bool {
+    if !self.is_alive(entity) {
+      return false;
+    }
+
+    let index = entity.index as usize;
+    self.alive[index] = false;
+    self.generations[index] = self.generations[index].wrapping_add(1);
+    self.free.push(entity.index);
+    true
+  }
+
+  pub fn is_alive(&self, entity: Entity) -> bool {
+    let index = entity.index as usize;
+    self.alive.get(index).copied().unwrap_or(false) && self.generations[index] == entity.generation
+  }
+
+  pub fn len(&self) -> usize {
+    self.alive.len() - self.free.len()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+    self
+      .alive
+      .iter()
+      .enumerate()
+      .filter(|(_, &alive)| alive)
+      .map(|(i, _)| Entity { index: i as u32, generation: self.generations[i] })
+  }
+}
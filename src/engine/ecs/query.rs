@@ -0,0 +1,91 @@
+use std::cell::{Ref, RefMut};
+
+use super::{Entity, Storage, World};
+
+/// Component types that can be fetched together in `World::query`: `&T`,
+/// `&mut T`, or a tuple of those such as `(&mut Transform, &Velocity)`.
+///
+/// Only entities that have every requested component are visited. Asking
+/// for the same component twice with `&mut` panics, as would borrowing a
+/// storage mutably that is already borrowed elsewhere.
+pub trait Query {
+  /// The storages held for the duration of the query.
+  type Borrow<'w>;
+  type Item<'a>;
+
+  /// None if some component type has never been added to the world.
+  fn borrow(world: &World) -> Option<Self::Borrow<'_>>;
+
+  /// Candidates to visit: the entities of the smallest storage involved.
+  fn entities<'b>(borrow: &'b Self::Borrow<'_>) -> &'b [Entity];
+
+  fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: Entity) -> Option<Self::Item<'a>>;
+}
+
+impl<T: 'static> Query for &T {
+  type Borrow<'w> = Ref<'w, Storage<T>>;
+  type Item<'a> = &'a T;
+
+  fn borrow(world: &World) -> Option<Self::Borrow<'_>> {
+    world.storage::<T>()
+  }
+
+  fn entities<'b>(borrow: &'b Self::Borrow<'_>) -> &'b [Entity] {
+    borrow.entities()
+  }
+
+  fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: Entity) -> Option<Self::Item<'a>> {
+    borrow.get(entity)
+  }
+}
+
+impl<T: 'static> Query for &mut T {
+  type Borrow<'w> = RefMut<'w, Storage<T>>;
+  type Item<'a> = &'a mut T;
+
+  fn borrow(world: &World) -> Option<Self::Borrow<'_>> {
+    world.storage_mut::<T>()
+  }
+
+  fn entities<'b>(borrow: &'b Self::Borrow<'_>) -> &'b [Entity] {
+    borrow.entities()
+  }
+
+  fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: Entity) -> Option<Self::Item<'a>> {
+    borrow.get_mut(entity)
+  }
+}
+
+macro_rules! impl_query {
+  ($($query:ident),+) => {
+    #[allow(non_snake_case)]
+    impl<$($query: Query),+> Query for ($($query,)+) {
+      type Borrow<'w> = ($($query::Borrow<'w>,)+);
+      type Item<'a> = ($($query::Item<'a>,)+);
+
+      fn borrow(world: &World) -> Option<Self::Borrow<'_>> {
+        Some(($($query::borrow(world)?,)+))
+      }
+
+      fn entities<'b>(borrow: &'b Self::Borrow<'_>) -> &'b [Entity] {
+        let ($($query,)+) = borrow;
+        [$($query::entities($query)),+]
+          .into_iter()
+          .min_by_key(|entities| entities.len())
+          .unwrap()
+      }
+
+      fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: Entity) -> Option<Self::Item<'a>> {
+        let ($($query,)+) = borrow;
+        Some(($($query::fetch($query, entity)?,)+))
+      }
+    }
+  };
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+impl_query!(A, B, C, D, E);
+impl_query!(A, B, C, D, E, F);
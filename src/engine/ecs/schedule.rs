@@ -0,0 +1,48 @@
+use super::World;
+
+/// Behaviour that runs once per fixed step (see `App::fixed_update`). Any
+/// `FnMut(&mut World, f64)` closure is a system; the `f64` is the length of
+/// the step in seconds.
+pub trait System {
+  fn run(&mut self, world: &mut World, step: f64) -> ();
+}
+
+impl<F: FnMut(&mut World, f64)> System for F {
+  fn run(&mut self, world: &mut World, step: f64) -> () {
+    self(world, step)
+  }
+}
+
+/// The systems to run each fixed step, in the order they were added.
+#[derive(Default)]
+pub struct Schedule {
+  systems: Vec<(String, Box<dyn System>)>,
+}
+
+impl Schedule {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn add_system<S: System + 'static>(&mut self, name: &str, system: S) -> &mut Self {
+    self.systems.push((name.into(), Box::new(system)));
+    self
+  }
+
+  /// Returns false if there was no system by that name.
+  pub fn remove_system(&mut self, name: &str) -> bool {
+    let count = self.systems.len();
+    self.systems.retain(|(n, _)| n != name);
+    self.systems.len() != count
+  }
+
+  pub fn system_names(&self) -> impl Iterator<Item = &str> {
+    self.systems.iter().map(|(name, _)| name.as_str())
+  }
+
+  pub fn run(&mut self, world: &mut World, step: f64) -> () {
+    for (_, system) in &mut self.systems {
+      system.run(world, step);
+    }
+  }
+}
@@ -0,0 +1,171 @@
+use std::any::Any;
+
+use super::Entity;
+
+/// The components of one type, packed together so that iterating over them
+/// doesn't skip over entities that don't have one (a sparse set).
+pub struct Storage<T> {
+  values: Vec<T>,
+  entities: Vec<Entity>,
+  // Position in `values` for each entity index.
+  sparse: Vec<Option<usize>>,
+}
+
+impl<T> Storage<T> {
+  pub fn new() -> Self {
+    Storage { values: Vec::new(), entities: Vec::new(), sparse: Vec::new() }
+  }
+
+  /// Returns the component the entity had before, if any.
+  pub fn insert(&mut self, entity: Entity, value: T) -> Option<T> {
+    if let Some(dense) = self.dense_index(entity) {
+      return Some(std::mem::replace(&mut self.values[dense], value));
+    }
+
+    let index = entity.index() as usize;
+    if self.sparse.len() <= index {
+      self.sparse.resize(index + 1, None);
+    }
+    self.sparse[index] = Some(self.values.len());
+    self.values.push(value);
+    self.entities.push(entity);
+    None
+  }
+
+  pub fn remove(&mut self, entity: Entity) -> Option<T> {
+    let dense = self.dense_index(entity)?;
+    self.sparse[entity.index() as usize] = None;
+
+    let value = self.values.swap_remove(dense);
+    self.entities.swap_remove(dense);
+    if let Some(moved) = self.entities.get(dense) {
+      self.sparse[moved.index() as usize] = Some(dense);
+    }
+    Some(value)
+  }
+
+  pub fn get(&self, entity: Entity) -> Option<&T> {
+    self.dense_index(entity).map(|dense| &self.values[dense])
+  }
+
+  pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+    self.dense_index(entity).map(|dense| &mut self.values[dense])
+  }
+
+  pub fn contains(&self, entity: Entity) -> bool {
+    self.dense_index(entity).is_some()
+  }
+
+  pub fn len(&self) -> usize {
+    self.values.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.values.is_empty()
+  }
+
+  /// Entities that have this component, in storage order.
+  pub fn entities(&self) -> &[Entity] {
+    &self.entities
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+    self.entities.iter().copied().zip(self.values.iter())
+  }
+
+  pub fn iter_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> {
+    self.entities.iter().copied().zip(self.values.iter_mut())
+  }
+
+  fn dense_index(&self, entity: Entity) -> Option<usize> {
+    let dense = (*self.sparse.get(entity.index() as usize)?)?;
+    (self.entities[dense] == entity).then_some(dense)
+  }
+}
+
+impl<T> Default for Storage<T> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// Lets the world keep storages of different component types side by side
+/// and drop an entity from all of them without knowing their types.
+pub(crate) trait AnyStorage {
+  fn remove_entity(&mut self, entity: Entity) -> ();
+  fn as_any(&self) -> &dyn Any;
+  fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyStorage for Storage<T> {
+  fn remove_entity(&mut self, entity: Entity) -> () {
+    self.remove(entity);
+  }
+
+  fn as_any(&self) -> &dyn Any {
+    self
+  }
+
+  fn as_any_mut(&mut self) -> &mut dyn Any {
+    self
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::ecs::entity::Entities;
+
+  fn entities(count: usize) -> Vec<Entity> {
+    let mut entities = Entities::default();
+    (0..count).map(|_| entities.alloc()).collect()
+  }
+
+  #[test]
+  fn insert_replaces_and_returns_the_previous_value() {
+    let [a, b] = entities(2)[..] else { unreachable!() };
+    let mut storage = Storage::new();
+
+    assert_eq!(storage.insert(a, "a"), None);
+    assert_eq!(storage.insert(b, "b"), None);
+    assert_eq!(storage.insert(a, "a2"), Some("a"));
+    assert_eq!(storage.len(), 2);
+    assert_eq!(storage.get(a), Some(&"a2"));
+    assert_eq!(storage.get(b), Some(&"b"));
+  }
+
+  #[test]
+  fn remove_moves_the_last_value_into_the_gap() {
+    let [a, b, c] = entities(3)[..] else { unreachable!() };
+    let mut storage = Storage::new();
+    storage.insert(a, 1);
+    storage.insert(b, 2);
+    storage.insert(c, 3);
+
+    assert_eq!(storage.remove(a), Some(1));
+    assert_eq!(storage.entities(), &[c, b]);
+    assert_eq!(storage.get(c), Some(&3));
+    assert_eq!(storage.get(b), Some(&2));
+    assert!(!storage.contains(a));
+
+    // Removing the last value has nothing to move.
+    assert_eq!(storage.remove(b), Some(2));
+    assert_eq!(storage.remove(b), None);
+    assert_eq!(storage.iter().collect::<Vec<_>>(), vec![(c, &3)]);
+  }
+
+  #[test]
+  fn stale_entities_do_not_see_the_new_owner_of_their_index() {
+    let mut entities = Entities::default();
+    let old = entities.alloc();
+    entities.free(old);
+    let new = entities.alloc();
+    assert_eq!(old.index(), new.index());
+
+    let mut storage = Storage::new();
+    storage.insert(new, "new");
+    assert_eq!(storage.get(old), None);
+    assert_eq!(storage.remove(old), None);
+    assert_eq!(storage.get(new), Some(&"new"));
+  }
+}
@@ -0,0 +1,241 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::HashMap;
+
+use crate::engine::object::Object;
+use crate::engine::rendering::RenderDevice;
+
+use super::entity::Entities;
+use super::storage::AnyStorage;
+use super::{Entity, Query, Storage};
+
+/// Owns every entity, their components and any shared state (resources)
+/// systems need, such as the time or the input state.
+///
+/// Storages sit behind `RefCell`s so one query can write to some component
+/// types while reading others. Spawning, despawning and adding or removing
+/// components need `&mut World`, so they can't happen during a query.
+#[derive(Default)]
+pub struct World {
+  entities: Entities,
+  storages: HashMap<TypeId, RefCell<Box<dyn AnyStorage>>>,
+  resources: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+}
+
+impl World {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn spawn(&mut self) -> Entity {
+    self.entities.alloc()
+  }
+
+  /// Spawns an entity with the object's transform, mesh and material as its
+  /// components.
+  pub fn spawn_object<D: RenderDevice + 'static>(&mut self, object: Object<D>) -> Entity {
+    let (transform, mesh, material) = object.into_components();
+    let entity = self.spawn();
+    self.insert(entity, transform);
+    self.insert(entity, mesh);
+    self.insert(entity, material);
+    entity
+  }
+
+  /// Removes the entity and all of its components. Returns false if it was
+  /// already gone.
+  pub fn despawn(&mut self, entity: Entity) -> bool {
+    if !self.entities.free(entity) {
+      return false;
+    }
+    for storage in self.storages.values_mut() {
+      storage.get_mut().remove_entity(entity);
+    }
+    true
+  }
+
+  pub fn is_alive(&self, entity: Entity) -> bool {
+    self.entities.is_alive(entity)
+  }
+
+  /// Number of living entities.
+  pub fn len(&self) -> usize {
+    self.entities.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn entities(&self) -> impl Iterator<Item = Entity> + '_ {
+    self.entities.iter()
+  }
+
+  /// Adds a component, replacing (and returning) any previous one of the
+  /// same type.
+  pub fn insert<T: 'static>(&mut self, entity: Entity, component: T) -> Option<T> {
+    assert!(self.is_alive(entity), "Entity has been despawned!");
+
+    self
+      .storages
+      .entry(TypeId::of::<T>())
+      .or_insert_with(|| RefCell::new(Box::new(Storage::<T>::new())))
+      .get_mut()
+      .as_any_mut()
+      .downcast_mut::<Storage<T>>()
+      .unwrap()
+      .insert(entity, component)
+  }
+
+  pub fn remove<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+    let storage = self.storages.get_mut(&TypeId::of::<T>())?.get_mut();
+    storage.as_any_mut().downcast_mut::<Storage<T>>().unwrap().remove(entity)
+  }
+
+  pub fn has<T: 'static>(&self, entity: Entity) -> bool {
+    self.storage::<T>().is_some_and(|storage| storage.contains(entity))
+  }
+
+  /// Reads a component. Unlike `get_mut` this works inside a query, e.g.
+  /// to look at the entity being followed.
+  pub fn get<T: 'static>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+    Ref::filter_map(self.storage::<T>()?, |storage| storage.get(entity)).ok()
+  }
+
+  pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+    let storage = self.storages.get_mut(&TypeId::of::<T>())?.get_mut();
+    storage.as_any_mut().downcast_mut::<Storage<T>>().unwrap().get_mut(entity)
+  }
+
+  /// Every component of type `T`. None if no entity ever had one.
+  pub fn storage<T: 'static>(&self) -> Option<Ref<'_, Storage<T>>> {
+    let storage = self.storages.get(&TypeId::of::<T>())?.borrow();
+    Ref::filter_map(storage, |s| s.as_any().downcast_ref::<Storage<T>>()).ok()
+  }
+
+  pub fn storage_mut<T: 'static>(&self) -> Option<RefMut<'_, Storage<T>>> {
+    let storage = self.storages.get(&TypeId::of::<T>())?.borrow_mut();
+    RefMut::filter_map(storage, |s| s.as_any_mut().downcast_mut::<Storage<T>>()).ok()
+  }
+
+  /// Calls `f` for every entity that has all the components in `Q`:
+  ///
+  /// ```ignore
+  /// world.query::<(&mut Transform, &Velocity)>(|_, (transform, velocity)| {
+  ///   transform.translation += velocity.0 * delta_time;
+  /// });
+  /// ```
+  pub fn query<Q: Query>(&self, mut f: impl FnMut(Entity, Q::Item<'_>)) -> () {
+    let Some(mut borrow) = Q::borrow(self) else {
+      return;
+    };
+
+    let entities = Q::entities(&borrow).to_vec();
+    for entity in entities {
+      if let Some(item) = Q::fetch(&mut borrow, entity) {
+        f(entity, item);
+      }
+    }
+  }
+
+  /// Adds a value shared by the whole world, replacing any previous one of
+  /// the same type.
+  pub fn insert_resource<T: 'static>(&mut self, resource: T) -> () {
+    self.resources.insert(TypeId::of::<T>(), RefCell::new(Box::new(resource)));
+  }
+
+  pub fn remove_resource<T: 'static>(&mut self) -> Option<T> {
+    let resource = self.resources.remove(&TypeId::of::<T>())?.into_inner();
+    resource.downcast::<T>().ok().map(|resource| *resource)
+  }
+
+  pub fn resource<T: 'static>(&self) -> Option<Ref<'_, T>> {
+    let resource = self.resources.get(&TypeId::of::<T>())?.borrow();
+    Ref::filter_map(resource, |r| r.downcast_ref::<T>()).ok()
+  }
+
+  pub fn resource_mut<T: 'static>(&self) -> Option<RefMut<'_, T>> {
+    let resource = self.resources.get(&TypeId::of::<T>())?.borrow_mut();
+    RefMut::filter_map(resource, |r| r.downcast_mut::<T>()).ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[derive(Debug, PartialEq)]
+  struct Position(f32);
+
+  #[derive(Debug, PartialEq)]
+  struct Velocity(f32);
+
+  #[test]
+  fn query_visits_entities_with_every_component() {
+    let mut world = World::new();
+    let moving = world.spawn();
+    world.insert(moving, Position(0.0));
+    world.insert(moving, Velocity(2.0));
+    let still = world.spawn();
+    world.insert(still, Position(5.0));
+
+    world.query::<(&mut Position, &Velocity)>(|_, (position, velocity)| position.0 += velocity.0);
+
+    assert_eq!(world.get::<Position>(moving).as_deref(), Some(&Position(2.0)));
+    assert_eq!(world.get::<Position>(still).as_deref(), Some(&Position(5.0)));
+  }
+
+  #[test]
+  fn query_of_a_component_never_added_visits_nothing() {
+    let mut world = World::new();
+    let entity = world.spawn();
+    world.insert(entity, Position(0.0));
+
+    let mut visited = 0;
+    world.query::<(&Position, &Velocity)>(|_, _| visited += 1);
+    assert_eq!(visited, 0);
+  }
+
+  #[test]
+  fn despawn_removes_every_component() {
+    let mut world = World::new();
+    let entity = world.spawn();
+    world.insert(entity, Position(0.0));
+    world.insert(entity, Velocity(1.0));
+
+    assert!(world.despawn(entity));
+    assert!(!world.despawn(entity));
+    assert!(!world.has::<Position>(entity));
+    assert!(world.storage::<Velocity>().unwrap().is_empty());
+
+    // The index is reused, but the old handle stays dead.
+    let reused = world.spawn();
+    assert_eq!(reused.index(), entity.index());
+    assert!(!world.is_alive(entity));
+    assert!(world.is_alive(reused));
+  }
+
+  #[test]
+  #[should_panic(expected = "already borrowed")]
+  fn query_borrowing_a_storage_twice_mutably_panics() {
+    let mut world = World::new();
+    let entity = world.spawn();
+    world.insert(entity, Position(0.0));
+
+    world.query::<(&mut Position, &mut Position)>(|_, _| ());
+  }
+
+  #[test]
+  fn get_reads_other_entities_during_a_query() {
+    let mut world = World::new();
+    let leader = world.spawn();
+    world.insert(leader, Position(10.0));
+    let follower = world.spawn();
+    world.insert(follower, Velocity(0.0));
+
+    world.query::<&mut Velocity>(|_, velocity| {
+      velocity.0 = world.get::<Position>(leader).unwrap().0;
+    });
+    assert_eq!(world.get::<Velocity>(follower).as_deref(), Some(&Velocity(10.0)));
+  }
+}
@@ -0,0 +1,213 @@
+use std::{fmt, io, path::PathBuf};
+
+use image::ImageError;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// All the ways loading an asset or creating a GPU resource can fail.
+///
+/// Constructors that touch the file system or compile something return this
+/// instead of panicking, so that the game can substitute a fallback asset.
+#[derive(Debug)]
+pub enum Error {
+  /// A file could not be read from or written to disk.
+  Io { path: PathBuf, source: io::Error },
+
+  /// An image file was found but could not be decoded.
+  Image { path: PathBuf, source: ImageError },
+
+  /// A shader stage failed to compile. The driver log is split into entries.
+  ShaderCompile { path: PathBuf, stage: ShaderStage, log: Vec<ShaderLogEntry> },
+
+  /// The compiled shader stages could not be linked into a program.
+  ProgramLink { log: String },
+
+  /// An OBJ model (or one of its material libraries) could not be parsed.
+  Obj { path: PathBuf, source: tobj::LoadError },
+
+  /// No OpenGL context could be created, e.g. because EGL is missing.
+  ContextCreation { message: String },
+
+  /// A framebuffer was not complete; `status` is the GL status enum.
+  IncompleteFramebuffer { status: u32 },
+
+  /// A text file (scene, input bindings) or a compressed texture (DDS,
+  /// KTX2) could not be parsed, or a value could not be written to one
+  /// (e.g. a scene with objects that weren't loaded from an asset).
+  Format { path: PathBuf, message: String },
+}
+
+impl Error {
+  /// `image::open` reports missing files as an `ImageError` too; those are
+  /// surfaced as `Error::Io` so callers only need to check one variant.
+  pub(crate) fn from_image(path: impl Into<PathBuf>, source: ImageError) -> Self {
+    match source {
+      ImageError::IoError(source) => Error::Io { path: path.into(), source },
+      source => Error::Image { path: path.into(), source },
+    }
+  }
+}
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Error::Io { path, source } =>
+        write!(f, "could not access {}: {}", path.display(), source),
+      Error::Image { path, source } =>
+        write!(f, "could not decode image {}: {}", path.display(), source),
+      Error::ShaderCompile { path, stage, log } => {
+        write!(f, "{} shader {} failed to compile", stage, path.display())?;
+        for entry in log {
+          write!(f, "\n  {}", entry)?;
+        }
+        Ok(())
+      }
+      Error::ProgramLink { log } =>
+        write!(f, "shader program failed to link: {}", log.trim_end()),
+      Error::Obj { path, source } =>
+        write!(f, "could not load model {}: {}", path.display(), source),
+      Error::ContextCreation { message } =>
+        write!(f, "could not create an OpenGL context: {}", message),
+      Error::IncompleteFramebuffer { status } =>
+        write!(f, "framebuffer is incomplete (status 0x{:X})", status),
+      Error::Format { path, message } =>
+        write!(f, "invalid file {}: {}", path.display(), message),
+    }
+  }
+}
+
+impl std::error::Error for Error {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      Error::Io { source, .. } => Some(source),
+      Error::Image { source, .. } => Some(source),
+      Error::Obj { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+  Vertex,
+  Fragment,
+}
+
+impl ShaderStage {
+  pub fn gl_enum(self) -> gl::types::GLenum {
+    match self {
+      ShaderStage::Vertex => gl::VERTEX_SHADER,
+      ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+    }
+  }
+}
+
+impl fmt::Display for ShaderStage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ShaderStage::Vertex => f.write_str("vertex"),
+      ShaderStage::Fragment => f.write_str("fragment"),
+    }
+  }
+}
+
+/// One message from a shader compiler info log.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderLogEntry {
+  /// Source line the message refers to, if the driver reported one.
+  pub line: Option<u32>,
+  pub message: String,
+}
+
+impl ShaderLogEntry {
+  /// Splits a raw info log into entries.
+  ///
+  /// Drivers disagree on the format, so this understands the common ones:
+  /// Mesa (`0:12(3): error: ...`), NVIDIA (`0(12) : error C0000: ...`) and
+  /// AMD/Intel (`ERROR: 0:12: ...`). Lines that match none of them are kept
+  /// as-is without a line number.
+  pub fn parse_log(log: &str) -> Vec<ShaderLogEntry> {
+    log
+      .lines()
+      .map(str::trim)
+      .filter(|l| !l.is_empty())
+      .map(|l| match Self::parse_line(l) {
+        Some((line, message)) => ShaderLogEntry { line: Some(line), message },
+        None => ShaderLogEntry { line: None, message: l.to_string() },
+      })
+      .collect()
+  }
+
+  fn parse_line(l: &str) -> Option<(u32, String)> {
+    // "ERROR: 0:12: message" / "WARNING: 0:12: message"
+    if let Some((severity, rest)) = l.split_once(": ") {
+      if severity == "ERROR" || severity == "WARNING" {
+        let mut parts = rest.splitn(3, ':');
+        let _file = parts.next()?;
+        let line = parts.next()?.trim().parse().ok()?;
+        let message = parts.next()?.trim();
+        return Some((line, format!("{}: {}", severity.to_lowercase(), message)));
+      }
+    }
+
+    // "0:12(3): error: message" (Mesa)
+    if let Some((location, message)) = l.split_once("): ") {
+      let (_file, rest) = location.split_once(':')?;
+      let (line, _column) = rest.split_once('(')?;
+      return Some((line.trim().parse().ok()?, message.trim().to_string()));
+    }
+
+    // "0(12) : error C0000: message" (NVIDIA)
+    if let Some((location, message)) = l.split_once(") : ") {
+      let (_file, line) = location.split_once('(')?;
+      return Some((line.trim().parse().ok()?, message.trim().to_string()));
+    }
+
+    None
+  }
+}
+
+impl fmt::Display for ShaderLogEntry {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.line {
+      Some(line) => write!(f, "line {}: {}", line, self.message),
+      None => f.write_str(&self.message),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn entry(line: Option<u32>, message: &str) -> ShaderLogEntry {
+    ShaderLogEntry { line, message: message.into() }
+  }
+
+  #[test]
+  fn parses_mesa_logs() {
+    let log = "0:12(3): error: `foo' undeclared\n";
+    assert_eq!(ShaderLogEntry::parse_log(log), vec![entry(Some(12), "error: `foo' undeclared")]);
+  }
+
+  #[test]
+  fn parses_nvidia_logs() {
+    let log = "0(7) : error C0000: syntax error, unexpected '}'\n";
+    assert_eq!(ShaderLogEntry::parse_log(log), vec![entry(Some(7), "error C0000: syntax error, unexpected '}'")]);
+  }
+
+  #[test]
+  fn parses_amd_and_intel_logs() {
+    let log = "ERROR: 0:3: 'vec5' : undeclared identifier\nWARNING: 0:9: unused variable\n";
+    assert_eq!(ShaderLogEntry::parse_log(log), vec![
+      entry(Some(3), "error: 'vec5' : undeclared identifier"),
+      entry(Some(9), "warning: unused variable"),
+    ]);
+  }
+
+  #[test]
+  fn keeps_unknown_lines_without_a_line_number() {
+    let log = "  Compilation failed: 2 errors  \n\n";
+    assert_eq!(ShaderLogEntry::parse_log(log), vec![entry(None, "Compilation failed: 2 errors")]);
+  }
+}
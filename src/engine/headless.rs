@@ -0,0 +1,159 @@
+use std::ffi::c_void;
+use std::mem::ManuallyDrop;
+
+use image::RgbaImage;
+use khronos_egl as egl;
+
+use super::error::{Error, Result};
+use super::rendering::{Framebuffer, GlCapabilities};
+use super::scene::Scene;
+use super::Camera;
+
+// From EGL_MESA_platform_surfaceless. Lets Mesa (llvmpipe included) hand out
+// a display without any window system being present.
+const PLATFORM_SURFACELESS_MESA: egl::Enum = 0x31DD;
+
+/// Renders into an offscreen framebuffer through an EGL context that has no
+/// window or surface. This is what the tests and CI use on machines without
+/// a GPU or a display server; with Mesa installed it falls back to llvmpipe.
+///
+/// Creating a renderer makes its context current on the calling thread and
+/// loads the GL function pointers, so engine types can be created and drawn
+/// exactly like they are in a windowed program.
+pub struct HeadlessRenderer {
+  // Released by hand in `drop`, while the context is still current.
+  framebuffer: ManuallyDrop<Framebuffer>,
+  egl: egl::DynamicInstance<egl::EGL1_4>,
+  display: egl::Display,
+  context: egl::Context,
+}
+
+impl HeadlessRenderer {
+  pub fn new(width: u32, height: u32) -> Result<Self> {
+    let egl = unsafe { egl::DynamicInstance::<egl::EGL1_4>::load_required() }
+      .map_err(|e| context_error("could not load libEGL", e))?;
+
+    let display = Self::get_display(&egl)?;
+    egl.initialize(display).map_err(|e| context_error("eglInitialize failed", e))?;
+
+    let config_attribs = [
+      egl::RENDERABLE_TYPE, egl::OPENGL_BIT,
+      // Any surface type. The default asks for windows, which a surfaceless
+      // display has no configs for.
+      egl::SURFACE_TYPE, 0,
+      egl::NONE,
+    ];
+    let config = egl
+      .choose_first_config(display, &config_attribs)
+      .map_err(|e| context_error("eglChooseConfig failed", e))?
+      .ok_or_else(|| Error::ContextCreation { message: "no OpenGL capable EGL config".into() })?;
+
+    egl.bind_api(egl::OPENGL_API).map_err(|e| context_error("eglBindAPI failed", e))?;
+
+    let context_attribs = [
+      egl::CONTEXT_MAJOR_VERSION, 3,
+      egl::CONTEXT_MINOR_VERSION, 3,
+      egl::CONTEXT_OPENGL_PROFILE_MASK, egl::CONTEXT_OPENGL_CORE_PROFILE_BIT,
+      egl::NONE,
+    ];
+    let context = egl
+      .create_context(display, config, None, &context_attribs)
+      .map_err(|e| context_error("eglCreateContext failed", e))?;
+
+    // Surfaceless: every draw goes to our own framebuffer object.
+    egl
+      .make_current(display, None, None, Some(context))
+      .map_err(|e| context_error("eglMakeCurrent failed", e))?;
+
+    gl::load_with(|s| match egl.get_proc_address(s) {
+      Some(f) => f as *const c_void,
+      None => std::ptr::null(),
+    });
+    GlCapabilities::detect();
+
+    let framebuffer = match Framebuffer::new(width, height) {
+      Ok(framebuffer) => ManuallyDrop::new(framebuffer),
+      Err(e) => {
+        let _ = egl.make_current(display, None, None, None);
+        let _ = egl.destroy_context(display, context);
+        let _ = egl.terminate(display);
+        return Err(e);
+      }
+    };
+
+    Ok(HeadlessRenderer { framebuffer, egl, display, context })
+  }
+
+  fn get_display(egl: &egl::DynamicInstance<egl::EGL1_4>) -> Result<egl::Display> {
+    if let Some(egl15) = egl.upcast::<egl::EGL1_5>() {
+      let display = unsafe {
+        egl15.get_platform_display(PLATFORM_SURFACELESS_MESA, egl::DEFAULT_DISPLAY, &[egl::ATTRIB_NONE])
+      };
+      if let Ok(display) = display {
+        return Ok(display);
+      }
+    }
+
+    unsafe { egl.get_display(egl::DEFAULT_DISPLAY) }
+      .ok_or_else(|| Error::ContextCreation { message: "no EGL display available".into() })
+  }
+
+  pub fn width(&self) -> u32 {
+    self.framebuffer.width()
+  }
+
+  pub fn height(&self) -> u32 {
+    self.framebuffer.height()
+  }
+
+  /// Clears the target to `clear_color`, runs `draw` with the offscreen
+  /// framebuffer bound and returns what ended up in it.
+  pub fn render<F: FnOnce()>(&mut self, clear_color: [f32; 4], draw: F) -> RgbaImage {
+    unsafe {
+      self.framebuffer.bind();
+      gl::Enable(gl::DEPTH_TEST);
+      gl::DepthFunc(gl::LESS);
+      gl::ClearColor(clear_color[0], clear_color[1], clear_color[2], clear_color[3]);
+      gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+    }
+
+    draw();
+
+    unsafe {
+      gl::Finish();
+    }
+    let img = self.framebuffer.read_pixels();
+
+    unsafe {
+      Framebuffer::unbind();
+    }
+
+    img
+  }
+
+  /// Renders the scene from the camera's point of view the way
+  /// `Scene::draw` does (with its own shaders, shadows and skybox), using
+  /// the scene's background color as the clear color. The camera's viewport
+  /// is set to the size of the target.
+  pub fn render_scene(&mut self, scene: &mut Scene, camera: &mut Camera) -> RgbaImage {
+    camera.set_viewport_size(self.width(), self.height());
+    let bg = scene.bg_color;
+
+    self.render([bg.x, bg.y, bg.z, 1.0], || scene.draw(camera))
+  }
+}
+
+impl Drop for HeadlessRenderer {
+  fn drop(&mut self) {
+    unsafe {
+      ManuallyDrop::drop(&mut self.framebuffer);
+    }
+    let _ = self.egl.make_current(self.display, None, None, None);
+    let _ = self.egl.destroy_context(self.display, self.context);
+    let _ = self.egl.terminate(self.display);
+  }
+}
+
+fn context_error(what: &str, e: impl std::fmt::Display) -> Error {
+  Error::ContextCreation { message: format!("{}: {}", what, e) }
+}
@@ -0,0 +1,454 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+pub mod gamepad;
+pub use gamepad::*;
+
+use serde::{Deserialize, Serialize};
+
+pub use glfw::{GamepadAxis, GamepadButton, Key, MouseButton};
+
+use super::error::Result;
+use super::text_format;
+use glfw::{Action, WindowEvent};
+
+/// A physical input that bindings can refer to.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Source {
+  Key(Key),
+  MouseButton(MouseButton),
+  /// Cursor movement during the frame, in pixels. Right is positive.
+  MouseX,
+  /// Cursor movement during the frame, in pixels. Down is positive.
+  MouseY,
+  /// Scrolling during the frame, in wheel steps.
+  ScrollX,
+  ScrollY,
+  /// A button of the first connected gamepad.
+  GamepadButton(GamepadButton),
+  /// An axis of the first connected gamepad, with dead zones applied.
+  /// Sticks go from -1 to 1 (down and right are positive), triggers from 0
+  /// to 1.
+  GamepadAxis(GamepadAxis),
+}
+
+/// How an axis reads its value from the sources.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AxisBinding {
+  /// -1 while `negative` is held, 1 while `positive` is held, 0 for both.
+  Buttons { negative: Source, positive: Source },
+  /// The source's own value, multiplied by `scale` (e.g. -1 to invert).
+  Analog {
+    source: Source,
+    #[serde(default = "default_scale")]
+    scale: f32,
+  },
+}
+
+fn default_scale() -> f32 {
+  1.0
+}
+
+/// What the named actions and axes of a game are bound to.
+///
+/// Bindings are saved as RON (or JSON) so players can edit them:
+///
+/// ```ron
+/// (
+///   actions: {
+///     "jump": [Key(Space), GamepadButton(ButtonA)],
+///     "fire": [MouseButton(Button1)],
+///   },
+///   axes: {
+///     "move_forward": [
+///       Buttons(negative: Key(S), positive: Key(W)),
+///       Analog(source: GamepadAxis(AxisLeftY), scale: -1.0),
+///     ],
+///   },
+///   dead_zones: (stick: 0.2, trigger: 0.05),
+/// )
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bindings {
+  /// Each action is held while any of its sources is.
+  #[serde(default)]
+  pub actions: BTreeMap<String, Vec<Source>>,
+  /// Each axis is the sum of its bindings.
+  #[serde(default)]
+  pub axes: BTreeMap<String, Vec<AxisBinding>>,
+  /// Applied to every gamepad.
+  #[serde(default)]
+  pub dead_zones: DeadZones,
+}
+
+impl Bindings {
+  pub fn load(path: &str) -> Result<Self> {
+    text_format::read(path)
+  }
+
+  pub fn save(&self, path: &str) -> Result<()> {
+    text_format::write(path, self)
+  }
+
+  /// Adds a source to an action, creating the action if needed.
+  pub fn bind_action(&mut self, action: &str, source: Source) -> &mut Self {
+    self.actions.entry(action.into()).or_default().push(source);
+    self
+  }
+
+  pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) -> &mut Self {
+    self.axes.entry(axis.into()).or_default().push(binding);
+    self
+  }
+
+  /// Replaces every source of an action, e.g. from a rebinding menu.
+  pub fn rebind_action(&mut self, action: &str, sources: Vec<Source>) -> () {
+    self.actions.insert(action.into(), sources);
+  }
+}
+
+/// How far an analog source must be pushed to count as held for an action.
+const PRESS_THRESHOLD: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, Default)]
+struct ActionState {
+  held: bool,
+  was_held: bool,
+}
+
+/// The state of every input device, and of the actions bound to them.
+///
+/// Gamepads are kept by slot (GLFW's joystick id when they come from the
+/// runner). Bindings read the connected gamepad with the lowest slot; the
+/// others can be read through `gamepad`.
+///
+/// Window events and gamepad state are fed in as they arrive; `update`
+/// then settles the frame, so within one frame every query sees the same
+/// state. An action is `pressed` (or `released`) only on the frame it
+/// changed, so toggles should be read from per-frame code such as
+/// `App::update` rather than from `fixed_update`, which may run zero or
+/// several times a frame.
+#[derive(Debug, Default)]
+pub struct Input {
+  bindings: Bindings,
+  actions: HashMap<String, ActionState>,
+
+  keys: HashSet<Key>,
+  mouse_buttons: HashSet<MouseButton>,
+  cursor: Option<(f64, f64)>,
+  pending_mouse_delta: (f64, f64),
+  pending_scroll: (f64, f64),
+  mouse_delta: (f64, f64),
+  scroll: (f64, f64),
+  gamepads: BTreeMap<usize, Gamepad>,
+}
+
+impl Input {
+  pub fn new(bindings: Bindings) -> Self {
+    Input { bindings, ..Default::default() }
+  }
+
+  pub fn bindings(&self) -> &Bindings {
+    &self.bindings
+  }
+
+  /// Swaps in new bindings. Actions keep their state where they still
+  /// exist, so a held key doesn't read as a fresh press.
+  pub fn set_bindings(&mut self, bindings: Bindings) -> () {
+    self.bindings = bindings;
+    self.actions.retain(|name, _| self.bindings.actions.contains_key(name));
+    for gamepad in self.gamepads.values_mut() {
+      gamepad.dead_zones = self.bindings.dead_zones;
+    }
+  }
+
+  pub fn handle_event(&mut self, event: &WindowEvent) -> () {
+    match *event {
+      WindowEvent::Key(key, _, Action::Press, _) => {
+        self.keys.insert(key);
+      }
+      WindowEvent::Key(key, _, Action::Release, _) => {
+        self.keys.remove(&key);
+      }
+      WindowEvent::MouseButton(button, Action::Press, _) => {
+        self.mouse_buttons.insert(button);
+      }
+      WindowEvent::MouseButton(button, Action::Release, _) => {
+        self.mouse_buttons.remove(&button);
+      }
+      WindowEvent::CursorPos(x, y) => {
+        // The first position only tells where the cursor starts.
+        if let Some((last_x, last_y)) = self.cursor {
+          self.pending_mouse_delta.0 += x - last_x;
+          self.pending_mouse_delta.1 += y - last_y;
+        }
+        self.cursor = Some((x, y));
+      }
+      WindowEvent::Scroll(x, y) => {
+        self.pending_scroll.0 += x;
+        self.pending_scroll.1 += y;
+      }
+      WindowEvent::Focus(false) => {
+        // Releases that happen while unfocused never arrive.
+        self.keys.clear();
+        self.mouse_buttons.clear();
+      }
+      _ => {}
+    }
+  }
+
+  /// Reports the state of the gamepad in `slot`, connecting it if it's
+  /// new. The state takes effect at the next `update`.
+  pub fn update_gamepad(&mut self, slot: usize, name: &str, state: GamepadState) -> () {
+    let dead_zones = self.bindings.dead_zones;
+    self
+      .gamepads
+      .entry(slot)
+      .or_insert_with(|| Gamepad::new(name, dead_zones))
+      .set_incoming(name, state);
+  }
+
+  /// Plugs a virtual gamepad into `slot`. Call again after changing it.
+  /// The app runner leaves it connected, unless a physical gamepad is
+  /// plugged into the same joystick slot.
+  pub fn connect_virtual(&mut self, slot: usize, gamepad: &VirtualGamepad) -> () {
+    self.update_gamepad(slot, &gamepad.name, gamepad.state);
+  }
+
+  pub fn disconnect_gamepad(&mut self, slot: usize) -> () {
+    self.gamepads.remove(&slot);
+  }
+
+  pub fn gamepad(&self, slot: usize) -> Option<&Gamepad> {
+    self.gamepads.get(&slot)
+  }
+
+  pub fn gamepad_mut(&mut self, slot: usize) -> Option<&mut Gamepad> {
+    self.gamepads.get_mut(&slot)
+  }
+
+  /// Connected gamepads and their slots, lowest slot first.
+  pub fn gamepads(&self) -> impl Iterator<Item = (usize, &Gamepad)> {
+    self.gamepads.iter().map(|(&slot, gamepad)| (slot, gamepad))
+  }
+
+  /// Starts a new frame: takes in the movement since the last call and
+  /// works out which actions were pressed or released.
+  pub fn update(&mut self) -> () {
+    self.mouse_delta = std::mem::take(&mut self.pending_mouse_delta);
+    self.scroll = std::mem::take(&mut self.pending_scroll);
+    for gamepad in self.gamepads.values_mut() {
+      gamepad.update();
+    }
+
+    for (name, sources) in &self.bindings.actions {
+      let held = sources.iter().any(|&source| self.source_value(source).abs() >= PRESS_THRESHOLD);
+      let state = self.actions.entry(name.clone()).or_default();
+      state.was_held = state.held;
+      state.held = held;
+    }
+  }
+
+  /// Whether any source of the action is down.
+  pub fn is_held(&self, action: &str) -> bool {
+    self.actions.get(action).is_some_and(|s| s.held)
+  }
+
+  /// Whether the action went down this frame.
+  pub fn is_pressed(&self, action: &str) -> bool {
+    self.actions.get(action).is_some_and(|s| s.held && !s.was_held)
+  }
+
+  /// Whether the action was let go this frame.
+  pub fn is_released(&self, action: &str) -> bool {
+    self.actions.get(action).is_some_and(|s| !s.held && s.was_held)
+  }
+
+  /// The sum of the axis' bindings; 0 for unknown axes.
+  pub fn axis(&self, axis: &str) -> f32 {
+    let Some(bindings) = self.bindings.axes.get(axis) else {
+      return 0.0;
+    };
+
+    bindings
+      .iter()
+      .map(|binding| match *binding {
+        AxisBinding::Buttons { negative, positive } => {
+          let down = |source| self.source_value(source).abs() >= PRESS_THRESHOLD;
+          down(positive) as i32 as f32 - down(negative) as i32 as f32
+        }
+        AxisBinding::Analog { source, scale } => self.source_value(source) * scale,
+      })
+      .sum()
+  }
+
+  pub fn is_key_down(&self, key: Key) -> bool {
+    self.keys.contains(&key)
+  }
+
+  pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+    self.mouse_buttons.contains(&button)
+  }
+
+  /// Last known cursor position in window coordinates.
+  pub fn cursor_position(&self) -> Option<(f64, f64)> {
+    self.cursor
+  }
+
+  pub fn mouse_delta(&self) -> (f64, f64) {
+    self.mouse_delta
+  }
+
+  pub fn scroll(&self) -> (f64, f64) {
+    self.scroll
+  }
+
+  /// The current value of a source: 0 or 1 for buttons, the movement for
+  /// the mouse and the position for gamepad axes.
+  pub fn source_value(&self, source: Source) -> f32 {
+    match source {
+      Source::Key(key) => self.keys.contains(&key) as i32 as f32,
+      Source::MouseButton(button) => self.mouse_buttons.contains(&button) as i32 as f32,
+      Source::MouseX => self.mouse_delta.0 as f32,
+      Source::MouseY => self.mouse_delta.1 as f32,
+      Source::ScrollX => self.scroll.0 as f32,
+      Source::ScrollY => self.scroll.1 as f32,
+      Source::GamepadButton(button) => {
+        self.first_gamepad().is_some_and(|g| g.is_button_down(button)) as i32 as f32
+      }
+      Source::GamepadAxis(axis) => self.first_gamepad().map_or(0.0, |g| g.axis(axis)),
+    }
+  }
+
+  fn first_gamepad(&self) -> Option<&Gamepad> {
+    self.gamepads.values().next()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use glfw::Modifiers;
+
+  use super::*;
+
+  fn key(key: Key, action: Action) -> WindowEvent {
+    WindowEvent::Key(key, 0, action, Modifiers::empty())
+  }
+
+  fn input() -> Input {
+    let mut bindings = Bindings::default();
+    bindings
+      .bind_action("jump", Source::Key(Key::Space))
+      .bind_action("jump", Source::MouseButton(MouseButton::Button1))
+      .bind_axis("move", AxisBinding::Buttons { negative: Source::Key(Key::S), positive: Source::Key(Key::W) })
+      .bind_axis("move", AxisBinding::Analog { source: Source::MouseY, scale: -0.5 });
+    Input::new(bindings)
+  }
+
+  #[test]
+  fn actions_are_pressed_and_released_for_one_frame() {
+    let mut input = input();
+
+    input.handle_event(&key(Key::Space, Action::Press));
+    input.update();
+    assert!(input.is_pressed("jump") && input.is_held("jump"));
+
+    input.handle_event(&key(Key::Space, Action::Repeat));
+    input.update();
+    assert!(!input.is_pressed("jump") && input.is_held("jump"));
+
+    input.handle_event(&key(Key::Space, Action::Release));
+    input.update();
+    assert!(input.is_released("jump") && !input.is_held("jump"));
+
+    input.update();
+    assert!(!input.is_released("jump"));
+  }
+
+  #[test]
+  fn an_action_stays_held_while_any_source_is() {
+    let mut input = input();
+    input.handle_event(&key(Key::Space, Action::Press));
+    input.update();
+
+    input.handle_event(&WindowEvent::MouseButton(MouseButton::Button1, Action::Press, Modifiers::empty()));
+    input.handle_event(&key(Key::Space, Action::Release));
+    input.update();
+    assert!(input.is_held("jump") && !input.is_pressed("jump") && !input.is_released("jump"));
+  }
+
+  #[test]
+  fn losing_focus_releases_everything() {
+    let mut input = input();
+    input.handle_event(&key(Key::Space, Action::Press));
+    input.update();
+
+    input.handle_event(&WindowEvent::Focus(false));
+    input.update();
+    assert!(input.is_released("jump"));
+  }
+
+  #[test]
+  fn new_bindings_do_not_press_held_actions_again() {
+    let mut input = input();
+    input.handle_event(&key(Key::Space, Action::Press));
+    input.update();
+
+    let bindings = input.bindings().clone();
+    input.set_bindings(bindings);
+    input.update();
+    assert!(input.is_held("jump") && !input.is_pressed("jump"));
+  }
+
+  #[test]
+  fn axes_add_up_their_bindings() {
+    let mut input = input();
+    assert_eq!(input.axis("move"), 0.0);
+    assert_eq!(input.axis("unknown"), 0.0);
+
+    input.handle_event(&key(Key::W, Action::Press));
+    input.update();
+    assert_eq!(input.axis("move"), 1.0);
+
+    // Opposite buttons cancel out.
+    input.handle_event(&key(Key::S, Action::Press));
+    input.update();
+    assert_eq!(input.axis("move"), 0.0);
+
+    // The first cursor position only sets where the cursor starts.
+    input.handle_event(&key(Key::S, Action::Release));
+    input.handle_event(&WindowEvent::CursorPos(10.0, 10.0));
+    input.handle_event(&WindowEvent::CursorPos(10.0, 14.0));
+    input.update();
+    assert_eq!(input.axis("move"), 1.0 - 2.0);
+
+    // Mouse movement only lasts the frame it happened in.
+    input.update();
+    assert_eq!(input.axis("move"), 1.0);
+  }
+
+  #[test]
+  fn virtual_gamepads_drive_bindings() {
+    let mut bindings = Bindings::default();
+    bindings
+      .bind_action("jump", Source::GamepadButton(GamepadButton::ButtonA))
+      .bind_axis("turn", AxisBinding::Analog { source: Source::GamepadAxis(GamepadAxis::AxisLeftX), scale: 1.0 });
+    let mut input = Input::new(bindings);
+    let mut gamepad = VirtualGamepad::new("test pad");
+
+    gamepad.press(GamepadButton::ButtonA).set_stick(Stick::Left, 1.0, 0.0);
+    input.connect_virtual(0, &gamepad);
+    input.update();
+    assert!(input.is_pressed("jump"));
+    assert_eq!(input.axis("turn"), 1.0);
+
+    // Inside the dead zone the stick reads as centered.
+    gamepad.release(GamepadButton::ButtonA).set_stick(Stick::Left, 0.1, 0.0);
+    input.connect_virtual(0, &gamepad);
+    input.update();
+    assert!(input.is_released("jump"));
+    assert_eq!(input.axis("turn"), 0.0);
+
+    input.disconnect_gamepad(0);
+    assert!(input.gamepad(0).is_none());
+  }
+}
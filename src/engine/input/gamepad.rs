@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+
+use glfw::{GamepadAxis, GamepadButton};
+
+const BUTTON_COUNT: usize = 15;
+const AXIS_COUNT: usize = 6;
+
+/// Raw readings of a gamepad in the standard (Xbox-style) layout, as GLFW
+/// reports them once a controller is matched by a mapping: sticks go from
+/// -1 to 1 with down being positive, triggers rest at -1 and go up to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GamepadState {
+  buttons: [bool; BUTTON_COUNT],
+  axes: [f32; AXIS_COUNT],
+}
+
+impl GamepadState {
+  pub fn button(&self, button: GamepadButton) -> bool {
+    self.buttons[button as usize]
+  }
+
+  pub fn axis(&self, axis: GamepadAxis) -> f32 {
+    self.axes[axis as usize]
+  }
+
+  pub fn set_button(&mut self, button: GamepadButton, down: bool) -> () {
+    self.buttons[button as usize] = down;
+  }
+
+  pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) -> () {
+    self.axes[axis as usize] = value.clamp(-1.0, 1.0);
+  }
+}
+
+impl Default for GamepadState {
+  /// Nothing pressed, sticks centered and triggers released.
+  fn default() -> Self {
+    let mut axes = [0.0; AXIS_COUNT];
+    axes[GamepadAxis::AxisLeftTrigger as usize] = -1.0;
+    axes[GamepadAxis::AxisRightTrigger as usize] = -1.0;
+    GamepadState { buttons: [false; BUTTON_COUNT], axes }
+  }
+}
+
+impl From<glfw::GamepadState> for GamepadState {
+  fn from(glfw_state: glfw::GamepadState) -> Self {
+    let mut state = GamepadState::default();
+    for button in (0..BUTTON_COUNT as i32).filter_map(GamepadButton::from_i32) {
+      state.set_button(button, glfw_state.get_button_state(button) == glfw::Action::Press);
+    }
+    for axis in (0..AXIS_COUNT as i32).filter_map(GamepadAxis::from_i32) {
+      state.set_axis(axis, glfw_state.get_axis(axis));
+    }
+    state
+  }
+}
+
+/// How much of a stick's or trigger's travel is ignored. Worn sticks don't
+/// return exactly to the center, which would otherwise read as a slow
+/// drift.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DeadZones {
+  /// Radius around the center of each stick, from 0 to 1.
+  pub stick: f32,
+  /// Part of each trigger's travel, from 0 to 1.
+  pub trigger: f32,
+}
+
+impl Default for DeadZones {
+  fn default() -> Self {
+    DeadZones { stick: 0.15, trigger: 0.05 }
+  }
+}
+
+/// Which stick of a gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stick {
+  Left,
+  Right,
+}
+
+/// Which trigger of a gamepad.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trigger {
+  Left,
+  Right,
+}
+
+/// A connected gamepad as the game sees it: readings with dead zones
+/// applied and button edges between frames.
+#[derive(Clone, Debug)]
+pub struct Gamepad {
+  name: String,
+  state: GamepadState,
+  previous: GamepadState,
+  incoming: GamepadState,
+  pub dead_zones: DeadZones,
+}
+
+impl Gamepad {
+  pub(crate) fn new(name: &str, dead_zones: DeadZones) -> Self {
+    Gamepad {
+      name: name.into(),
+      state: GamepadState::default(),
+      previous: GamepadState::default(),
+      incoming: GamepadState::default(),
+      dead_zones,
+    }
+  }
+
+  /// The name of the mapping the controller was matched with.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  /// The readings this frame, before dead zones.
+  pub fn raw_state(&self) -> &GamepadState {
+    &self.state
+  }
+
+  pub(crate) fn set_incoming(&mut self, name: &str, state: GamepadState) -> () {
+    if self.name != name {
+      self.name = name.into();
+    }
+    self.incoming = state;
+  }
+
+  pub(crate) fn update(&mut self) -> () {
+    self.previous = self.state;
+    self.state = self.incoming;
+  }
+
+  pub fn is_button_down(&self, button: GamepadButton) -> bool {
+    self.state.button(button)
+  }
+
+  /// Whether the button went down this frame.
+  pub fn is_button_pressed(&self, button: GamepadButton) -> bool {
+    self.state.button(button) && !self.previous.button(button)
+  }
+
+  /// Whether the button was let go this frame.
+  pub fn is_button_released(&self, button: GamepadButton) -> bool {
+    !self.state.button(button) && self.previous.button(button)
+  }
+
+  /// Stick position with the dead zone removed, each axis from -1 to 1.
+  /// Up is positive, unlike in the raw state.
+  ///
+  /// The dead zone is radial, so diagonals aren't snapped to the axes, and
+  /// the rest of the range is stretched so the stick still reaches 1.
+  pub fn stick(&self, stick: Stick) -> (f32, f32) {
+    let (x_axis, y_axis) = match stick {
+      Stick::Left => (GamepadAxis::AxisLeftX, GamepadAxis::AxisLeftY),
+      Stick::Right => (GamepadAxis::AxisRightX, GamepadAxis::AxisRightY),
+    };
+    let (x, y) = (self.state.axis(x_axis), -self.state.axis(y_axis));
+
+    let length = (x * x + y * y).sqrt();
+    let dead_zone = self.dead_zones.stick;
+    if length <= dead_zone {
+      return (0.0, 0.0);
+    }
+
+    let scale = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0) / length;
+    (x * scale, y * scale)
+  }
+
+  /// How far the trigger is pulled, from 0 to 1, with the dead zone removed.
+  pub fn trigger(&self, trigger: Trigger) -> f32 {
+    let axis = match trigger {
+      Trigger::Left => GamepadAxis::AxisLeftTrigger,
+      Trigger::Right => GamepadAxis::AxisRightTrigger,
+    };
+    let pulled = (self.state.axis(axis) + 1.0) / 2.0;
+
+    let dead_zone = self.dead_zones.trigger;
+    if pulled <= dead_zone {
+      0.0
+    } else {
+      ((pulled - dead_zone) / (1.0 - dead_zone)).min(1.0)
+    }
+  }
+
+  /// One axis with dead zones applied, in the raw state's directions:
+  /// sticks from -1 to 1 with down positive, triggers from 0 to 1.
+  pub fn axis(&self, axis: GamepadAxis) -> f32 {
+    match axis {
+      GamepadAxis::AxisLeftX => self.stick(Stick::Left).0,
+      GamepadAxis::AxisLeftY => -self.stick(Stick::Left).1,
+      GamepadAxis::AxisRightX => self.stick(Stick::Right).0,
+      GamepadAxis::AxisRightY => -self.stick(Stick::Right).1,
+      GamepadAxis::AxisLeftTrigger => self.trigger(Trigger::Left),
+      GamepadAxis::AxisRightTrigger => self.trigger(Trigger::Right),
+    }
+  }
+}
+
+/// A gamepad driven from code instead of hardware, for tests, demos and
+/// replaying recorded input. Plug it in with `Input::connect_virtual`.
+#[derive(Clone, Debug)]
+pub struct VirtualGamepad {
+  pub name: String,
+  pub state: GamepadState,
+}
+
+impl VirtualGamepad {
+  pub fn new(name: &str) -> Self {
+    VirtualGamepad { name: name.into(), state: GamepadState::default() }
+  }
+
+  pub fn press(&mut self, button: GamepadButton) -> &mut Self {
+    self.state.set_button(button, true);
+    self
+  }
+
+  pub fn release(&mut self, button: GamepadButton) -> &mut Self {
+    self.state.set_button(button, false);
+    self
+  }
+
+  /// Moves a stick, with up being positive `y`.
+  pub fn set_stick(&mut self, stick: Stick, x: f32, y: f32) -> &mut Self {
+    let (x_axis, y_axis) = match stick {
+      Stick::Left => (GamepadAxis::AxisLeftX, GamepadAxis::AxisLeftY),
+      Stick::Right => (GamepadAxis::AxisRightX, GamepadAxis::AxisRightY),
+    };
+    self.state.set_axis(x_axis, x);
+    self.state.set_axis(y_axis, -y);
+    self
+  }
+
+  /// Pulls a trigger from 0 (released) to 1 (fully pulled).
+  pub fn set_trigger(&mut self, trigger: Trigger, pulled: f32) -> &mut Self {
+    let axis = match trigger {
+      Trigger::Left => GamepadAxis::AxisLeftTrigger,
+      Trigger::Right => GamepadAxis::AxisRightTrigger,
+    };
+    self.state.set_axis(axis, pulled * 2.0 - 1.0);
+    self
+  }
+}
@@ -0,0 +1,384 @@
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Zero};
+use serde::{Deserialize, Serialize};
+
+use super::rendering::{RenderDevice, Uniform};
+use super::types::{Point3, Vec3};
+
+/// How many point lights the lit shaders take (`NR_POINT_LIGHTS` in
+/// `cube.frag`).
+pub const MAX_POINT_LIGHTS: usize = 4;
+
+/// How a light fades with distance: `1 / (constant + linear * d + quadratic * d²)`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Attenuation {
+  pub constant: f32,
+  pub linear: f32,
+  pub quadratic: f32,
+}
+
+impl Attenuation {
+  /// Attenuation that has faded to almost nothing at `range` units.
+  pub fn for_range(range: f32) -> Self {
+    let range = range.max(f32::EPSILON);
+    Attenuation { constant: 1.0, linear: 4.5 / range, quadratic: 75.0 / (range * range) }
+  }
+
+  pub fn at(&self, distance: f32) -> f32 {
+    1.0 / (self.constant + self.linear * distance + self.quadratic * distance * distance)
+  }
+
+  /// Distance where the light has faded below 1/256, i.e. stops showing in
+  /// 8-bit color. `None` if it never fades that far.
+  pub fn reach(&self) -> Option<f32> {
+    // Solves quadratic * d² + linear * d + constant = 256.
+    let c = self.constant - 256.0;
+    if self.quadratic > 0.0 {
+      let discriminant = self.linear * self.linear - 4.0 * self.quadratic * c;
+      Some((-self.linear + discriminant.sqrt()) / (2.0 * self.quadratic))
+    } else if self.linear > 0.0 {
+      Some(-c / self.linear)
+    } else {
+      None
+    }
+  }
+}
+
+impl Default for Attenuation {
+  /// Reaches about 50 units.
+  fn default() -> Self {
+    Attenuation { constant: 1.0, linear: 0.09, quadratic: 0.032 }
+  }
+}
+
+/// How a light casts shadows. Lights without settings cast none.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShadowSettings {
+  /// Width and height of the shadow map (of each face, for point lights).
+  pub resolution: u32,
+  /// Depth offset against surfaces shadowing themselves ("shadow acne"),
+  /// as a fraction of the shadow's depth range. It grows up to tenfold on
+  /// surfaces the light grazes.
+  pub bias: f32,
+  /// Shadow map texels averaged in each direction to soften the edges. 0
+  /// gives hard, blocky edges.
+  pub pcf_radius: u32,
+  /// Splits a directional light's shadow along the camera's view, so
+  /// nearby shadows stay sharp in large scenes. Without cascades the map
+  /// is stretched over everything that casts shadows. Other lights ignore
+  /// this.
+  pub cascades: Option<CascadeSettings>,
+}
+
+impl Default for ShadowSettings {
+  fn default() -> Self {
+    ShadowSettings { resolution: 1024, bias: 0.005, pcf_radius: 1, cascades: None }
+  }
+}
+
+/// The most cascades a directional shadow can have.
+pub const MAX_CASCADES: usize = 4;
+
+/// How the camera's view is split between the maps of a cascaded shadow.
+/// Each cascade gets a map of the full resolution.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CascadeSettings {
+  /// Number of cascades, up to `MAX_CASCADES`.
+  pub count: u32,
+  /// How far from the camera shadows reach, if the camera's far plane is
+  /// further.
+  pub distance: f32,
+  /// Where the splits go: 0 spaces them evenly, 1 spaces them
+  /// logarithmically, which gives near cascades more of the detail.
+  pub split: f32,
+  /// Fraction of each cascade over which it fades into the next one. The
+  /// last one fades out of shadow.
+  pub blend: f32,
+}
+
+impl Default for CascadeSettings {
+  fn default() -> Self {
+    CascadeSettings { count: 4, distance: 100.0, split: 0.75, blend: 0.1 }
+  }
+}
+
+impl CascadeSettings {
+  /// Distances from the camera where each cascade ends, for a view
+  /// starting at `near` and ending at `far`.
+  pub fn split_distances(&self, near: f32, far: f32) -> Vec<f32> {
+    let count = self.count.clamp(1, MAX_CASCADES as u32);
+    let far = far.min(self.distance);
+    // The logarithmic split has no use for a near plane at or behind 0.
+    let near = near.max(0.01).min(far);
+    (1..=count)
+      .map(|i| {
+        let fraction = i as f32 / count as f32;
+        let logarithmic = near * (far / near).powf(fraction);
+        let uniform = near + (far - near) * fraction;
+        self.split * logarithmic + (1.0 - self.split) * uniform
+      })
+      .collect()
+  }
+}
+
+/// Light from far away that hits everything from the same direction, like
+/// the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+  /// The way the light travels, from the light towards the scene.
+  pub direction: Vec3,
+  pub ambient: Vec3,
+  pub diffuse: Vec3,
+  pub specular: Vec3,
+  pub shadows: Option<ShadowSettings>,
+}
+
+impl DirectionalLight {
+  pub fn new(direction: Vec3, color: Vec3) -> Self {
+    DirectionalLight {
+      direction: direction.normalize(),
+      ambient: color * 0.05,
+      diffuse: color,
+      specular: color,
+      shadows: None,
+    }
+  }
+
+  pub fn with_shadows(mut self, shadows: ShadowSettings) -> Self {
+    self.shadows = Some(shadows);
+    self
+  }
+
+  /// A light that adds nothing, for filling an unused slot.
+  pub fn off() -> Self {
+    DirectionalLight::new(-Vec3::unit_y(), Vec3::zero())
+  }
+
+  /// Sets the fields of the shader struct called `name`, e.g. "dirLight".
+  pub fn set_uniforms_on<D: RenderDevice>(&self, device: &mut D, pipeline: &D::Pipeline, name: &str) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("{}.{}", name, field), value);
+    set("direction", Uniform::Vec3(self.direction));
+    set("ambient", Uniform::Vec3(self.ambient));
+    set("diffuse", Uniform::Vec3(self.diffuse));
+    set("specular", Uniform::Vec3(self.specular));
+  }
+}
+
+/// Light shining in every direction from a point, like a bulb.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+  pub position: Point3,
+  pub attenuation: Attenuation,
+  pub ambient: Vec3,
+  pub diffuse: Vec3,
+  pub specular: Vec3,
+  pub shadows: Option<ShadowSettings>,
+}
+
+impl PointLight {
+  pub fn new(position: Point3, color: Vec3, range: f32) -> Self {
+    PointLight {
+      position,
+      attenuation: Attenuation::for_range(range),
+      ambient: color * 0.05,
+      diffuse: color,
+      specular: color,
+      shadows: None,
+    }
+  }
+
+  pub fn off() -> Self {
+    PointLight {
+      position: Point3::origin(),
+      attenuation: Attenuation::default(),
+      ambient: Vec3::zero(),
+      diffuse: Vec3::zero(),
+      specular: Vec3::zero(),
+      shadows: None,
+    }
+  }
+
+  pub fn with_shadows(mut self, shadows: ShadowSettings) -> Self {
+    self.shadows = Some(shadows);
+    self
+  }
+
+  /// Sets the fields of the shader struct called `name`, e.g.
+  /// "pointLights[0]".
+  pub fn set_uniforms_on<D: RenderDevice>(&self, device: &mut D, pipeline: &D::Pipeline, name: &str) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("{}.{}", name, field), value);
+    set("position", Uniform::Vec3(self.position.to_vec()));
+    set("constant", Uniform::Float(self.attenuation.constant));
+    set("linear", Uniform::Float(self.attenuation.linear));
+    set("quadratic", Uniform::Float(self.attenuation.quadratic));
+    set("ambient", Uniform::Vec3(self.ambient));
+    set("diffuse", Uniform::Vec3(self.diffuse));
+    set("specular", Uniform::Vec3(self.specular));
+  }
+}
+
+/// Light shining in a cone from a point, like a flashlight. Inside
+/// `inner_angle` the light is at full strength; it fades out towards
+/// `outer_angle`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+  pub position: Point3,
+  pub direction: Vec3,
+  /// Half-angle of the fully lit cone, in degrees.
+  pub inner_angle: f32,
+  /// Half-angle where the light has faded out, in degrees.
+  pub outer_angle: f32,
+  pub attenuation: Attenuation,
+  pub ambient: Vec3,
+  pub diffuse: Vec3,
+  pub specular: Vec3,
+  pub shadows: Option<ShadowSettings>,
+}
+
+impl SpotLight {
+  pub fn new(position: Point3, direction: Vec3, color: Vec3, inner_angle: f32, outer_angle: f32) -> Self {
+    SpotLight {
+      position,
+      direction: direction.normalize(),
+      inner_angle,
+      outer_angle,
+      attenuation: Attenuation::default(),
+      ambient: Vec3::zero(),
+      diffuse: color,
+      specular: color,
+      shadows: None,
+    }
+  }
+
+  pub fn off() -> Self {
+    SpotLight::new(Point3::origin(), -Vec3::unit_z(), Vec3::zero(), 0.0, 0.0)
+  }
+
+  pub fn with_shadows(mut self, shadows: ShadowSettings) -> Self {
+    self.shadows = Some(shadows);
+    self
+  }
+
+  /// Sets the fields of the shader struct called `name`, e.g. "spotLight".
+  /// The angles go to the shader as cosines.
+  pub fn set_uniforms_on<D: RenderDevice>(&self, device: &mut D, pipeline: &D::Pipeline, name: &str) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("{}.{}", name, field), value);
+    set("position", Uniform::Vec3(self.position.to_vec()));
+    set("direction", Uniform::Vec3(self.direction));
+    set("cutOff", Uniform::Float(self.inner_angle.to_radians().cos()));
+    // The shader divides by the difference, so keep the cones apart.
+    set("outerCutOff", Uniform::Float(self.outer_angle.max(self.inner_angle + 0.01).to_radians().cos()));
+    set("constant", Uniform::Float(self.attenuation.constant));
+    set("linear", Uniform::Float(self.attenuation.linear));
+    set("quadratic", Uniform::Float(self.attenuation.quadratic));
+    set("ambient", Uniform::Vec3(self.ambient));
+    set("diffuse", Uniform::Vec3(self.diffuse));
+    set("specular", Uniform::Vec3(self.specular));
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Light {
+  Directional(DirectionalLight),
+  Point(PointLight),
+  Spot(SpotLight),
+}
+
+impl Light {
+  pub fn shadows(&self) -> Option<ShadowSettings> {
+    match self {
+      Light::Directional(light) => light.shadows,
+      Light::Point(light) => light.shadows,
+      Light::Spot(light) => light.shadows,
+    }
+  }
+}
+
+/// Which lights the shader gets for one object: the lit shaders have one
+/// directional light, `MAX_POINT_LIGHTS` point lights and one spot light.
+/// Indices refer to the light list the selection was made from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LightSelection {
+  pub directional: Option<usize>,
+  pub points: Vec<usize>,
+  pub spot: Option<usize>,
+}
+
+impl LightSelection {
+  /// Picks the first directional light and the point and spot lights
+  /// closest to `position`.
+  pub fn closest(lights: &[Light], position: Point3) -> Self {
+    let mut selection = LightSelection::default();
+    let mut points: Vec<(f32, usize)> = Vec::new();
+    let mut spot: Option<(f32, usize)> = None;
+
+    for (i, light) in lights.iter().enumerate() {
+      match light {
+        Light::Directional(_) => {
+          selection.directional.get_or_insert(i);
+        }
+        Light::Point(point) => points.push((point.position.distance2(position), i)),
+        Light::Spot(light) => {
+          let distance = light.position.distance2(position);
+          if spot.is_none_or(|(closest, _)| distance < closest) {
+            spot = Some((distance, i));
+          }
+        }
+      }
+    }
+
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+    selection.points = points.into_iter().take(MAX_POINT_LIGHTS).map(|(_, i)| i).collect();
+    selection.spot = spot.map(|(_, i)| i);
+    selection
+  }
+
+  /// Uploads the selected lights to `dirLight`, `pointLights[..]` and
+  /// `spotLight`, switching off the slots nothing was selected for.
+  pub fn set_uniforms_on<D: RenderDevice>(&self, lights: &[Light], device: &mut D, pipeline: &D::Pipeline) -> () {
+    let directional = match self.directional.map(|i| &lights[i]) {
+      Some(Light::Directional(light)) => *light,
+      _ => DirectionalLight::off(),
+    };
+    directional.set_uniforms_on(device, pipeline, "dirLight");
+
+    for slot in 0..MAX_POINT_LIGHTS {
+      let point = match self.points.get(slot).map(|&i| &lights[i]) {
+        Some(Light::Point(light)) => *light,
+        _ => PointLight::off(),
+      };
+      point.set_uniforms_on(device, pipeline, &format!("pointLights[{}]", slot));
+    }
+
+    let spot = match self.spot.map(|i| &lights[i]) {
+      Some(Light::Spot(light)) => *light,
+      _ => SpotLight::off(),
+    };
+    spot.set_uniforms_on(device, pipeline, "spotLight");
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn cascade_splits_increase_up_to_the_shadow_distance() {
+    for split in [0.0, 0.5, 1.0] {
+      let settings = CascadeSettings { count: 4, distance: 50.0, split, ..Default::default() };
+      let splits = settings.split_distances(0.1, 1000.0);
+      assert_eq!(splits.len(), 4);
+      assert!(splits[0] > 0.1, "{:?}", splits);
+      assert!(splits.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", splits);
+      assert!((splits[3] - 50.0).abs() < 1e-3, "{:?}", splits);
+    }
+  }
+
+  #[test]
+  fn cascades_end_at_the_far_plane_when_it_is_nearer() {
+    let settings = CascadeSettings { count: 3, distance: 50.0, ..Default::default() };
+    let splits = settings.split_distances(0.1, 20.0);
+    assert!((splits[2] - 20.0).abs() < 1e-3, "{:?}", splits);
+  }
+}
@@ -1,17 +1,412 @@
+use std::rc::Rc;
+
+use super::rendering::{GlDevice, PixelFormat, RenderDevice, SamplerDesc, TextureDesc, TextureKind, Uniform};
+use super::{Shader, Texture};
+
 pub type Vector3 = cgmath::Vector3<f32>;
 
-#[derive(Clone, Copy, Debug)]
-pub struct Material {
+/// Texture units the maps of a material are bound to. Both kinds of
+/// material share the units of the maps they have in common.
+pub const DIFFUSE_MAP_UNIT: u32 = 0;
+pub const SPECULAR_MAP_UNIT: u32 = 1;
+pub const NORMAL_MAP_UNIT: u32 = 2;
+pub const EMISSIVE_MAP_UNIT: u32 = 3;
+pub const BASE_COLOR_MAP_UNIT: u32 = DIFFUSE_MAP_UNIT;
+pub const METALLIC_MAP_UNIT: u32 = 4;
+pub const ROUGHNESS_MAP_UNIT: u32 = 5;
+pub const AO_MAP_UNIT: u32 = 6;
+
+/// Which lighting a material is made for. Each needs its own shader.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadingModel {
+  /// Ambient/diffuse/specular lighting, `shaders/cube.frag`.
+  Phong,
+  /// Metallic/roughness lighting, `shaders/pbr.frag`.
+  Pbr,
+}
+
+/// How a surface reacts to light.
+pub enum Material<D: RenderDevice = GlDevice> {
+  Phong(PhongMaterial<D>),
+  Pbr(PbrMaterial<D>),
+}
+
+impl<D: RenderDevice> Clone for Material<D> {
+  fn clone(&self) -> Self {
+    match self {
+      Material::Phong(material) => Material::Phong(material.clone()),
+      Material::Pbr(material) => Material::Pbr(material.clone()),
+    }
+  }
+}
+
+impl<D: RenderDevice> Default for Material<D> {
+  /// A plain white Phong material.
+  fn default() -> Self {
+    let white = Vector3::new(1.0, 1.0, 1.0);
+    Material::Phong(PhongMaterial::new(white, white, Vector3::new(0.5, 0.5, 0.5), 32.0))
+  }
+}
+
+impl<D: RenderDevice> From<PhongMaterial<D>> for Material<D> {
+  fn from(material: PhongMaterial<D>) -> Self {
+    Material::Phong(material)
+  }
+}
+
+impl<D: RenderDevice> From<PbrMaterial<D>> for Material<D> {
+  fn from(material: PbrMaterial<D>) -> Self {
+    Material::Pbr(material)
+  }
+}
+
+impl Material {
+  /// Uploads the material to `shader`, which has to be active. `fallback`
+  /// is bound in place of the maps the material doesn't have, e.g.
+  /// `AppContext::fallback_texture`.
+  pub fn apply(&self, shader: &mut Shader, fallback: &Texture) -> () {
+    self.apply_on(&mut GlDevice, shader, fallback);
+  }
+}
+
+impl<D: RenderDevice> Material<D> {
+  /// A plain textured Phong surface, see `PhongMaterial::textured`.
+  pub fn textured(diffuse_map: Rc<D::Texture>) -> Self {
+    Material::Phong(PhongMaterial::textured(diffuse_map))
+  }
+
+  pub fn shading_model(&self) -> ShadingModel {
+    match self {
+      Material::Phong(_) => ShadingModel::Phong,
+      Material::Pbr(_) => ShadingModel::Pbr,
+    }
+  }
+
+  /// Sets the uniforms of the material's own shader, see `PhongMaterial` and
+  /// `PbrMaterial`.
+  pub fn apply_on(&self, device: &mut D, pipeline: &D::Pipeline, fallback: &D::Texture) -> () {
+    match self {
+      Material::Phong(material) => material.apply_on(device, pipeline, fallback),
+      Material::Pbr(material) => material.apply_on(device, pipeline, fallback),
+    }
+  }
+
+  /// Sets the uniforms of a Phong shader, approximating PBR materials. For
+  /// drawing with a shader that only knows Phong materials.
+  pub fn apply_as_phong_on(&self, device: &mut D, pipeline: &D::Pipeline, fallback: &D::Texture) -> () {
+    match self {
+      Material::Phong(material) => material.apply_on(device, pipeline, fallback),
+      Material::Pbr(material) => PhongMaterial::from(material).apply_on(device, pipeline, fallback),
+    }
+  }
+
+  /// Sets the uniforms of a PBR shader, approximating Phong materials. For
+  /// drawing with a shader that only knows PBR materials.
+  pub fn apply_as_pbr_on(&self, device: &mut D, pipeline: &D::Pipeline, fallback: &D::Texture) -> () {
+    match self {
+      Material::Phong(material) => PbrMaterial::from(material).apply_on(device, pipeline, fallback),
+      Material::Pbr(material) => material.apply_on(device, pipeline, fallback),
+    }
+  }
+}
+
+/// Maps are multiplied by their color, so a textured material usually has
+/// white colors, and a missing map counts as white (see
+/// `fallback_texture_on`). The normal map is only used when there is one.
+///
+/// In a shader the material is the `material` struct declared in
+/// `shaders/cube.frag`.
+pub struct PhongMaterial<D: RenderDevice = GlDevice> {
   pub ambient: Vector3,
   pub diffuse: Vector3,
   pub specular: Vector3,
-  pub shininess: f32
+  /// Light given off by the surface itself, even in the dark.
+  pub emissive: Vector3,
+  /// Exponent of the specular highlight; higher is smaller and sharper.
+  pub shininess: f32,
+  pub diffuse_map: Option<Rc<D::Texture>>,
+  pub specular_map: Option<Rc<D::Texture>>,
+  /// Tangent-space normals.
+  pub normal_map: Option<Rc<D::Texture>>,
+  pub emissive_map: Option<Rc<D::Texture>>,
 }
 
-impl Material {
+impl<D: RenderDevice> Clone for PhongMaterial<D> {
+  fn clone(&self) -> Self {
+    PhongMaterial {
+      ambient: self.ambient,
+      diffuse: self.diffuse,
+      specular: self.specular,
+      emissive: self.emissive,
+      shininess: self.shininess,
+      diffuse_map: self.diffuse_map.clone(),
+      specular_map: self.specular_map.clone(),
+      normal_map: self.normal_map.clone(),
+      emissive_map: self.emissive_map.clone(),
+    }
+  }
+}
+
+impl<D: RenderDevice> PhongMaterial<D> {
+  /// An untextured material.
   pub const fn new(ambient: Vector3, diffuse: Vector3, specular: Vector3, shininess: f32) -> Self {
     Self {
-      ambient, diffuse, specular, shininess
+      ambient,
+      diffuse,
+      specular,
+      emissive: Vector3::new(0.0, 0.0, 0.0),
+      shininess,
+      diffuse_map: None,
+      specular_map: None,
+      normal_map: None,
+      emissive_map: None,
     }
   }
-}
\ No newline at end of file
+
+  /// A plain textured surface with a soft highlight.
+  pub fn textured(diffuse_map: Rc<D::Texture>) -> Self {
+    let white = Vector3::new(1.0, 1.0, 1.0);
+    Self::new(white, white, Vector3::new(0.5, 0.5, 0.5), 32.0).with_diffuse_map(diffuse_map)
+  }
+
+  pub fn with_diffuse_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.diffuse_map = Some(map);
+    self
+  }
+
+  pub fn with_specular_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.specular_map = Some(map);
+    self
+  }
+
+  pub fn with_normal_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.normal_map = Some(map);
+    self
+  }
+
+  /// Adds an emissive map. The emissive color is turned up to white if it
+  /// was black, as the map would show nothing otherwise.
+  pub fn with_emissive_map(mut self, map: Rc<D::Texture>) -> Self {
+    if self.emissive == Vector3::new(0.0, 0.0, 0.0) {
+      self.emissive = Vector3::new(1.0, 1.0, 1.0);
+    }
+    self.emissive_map = Some(map);
+    self
+  }
+
+  /// Sets the `material` uniforms and binds the maps, with `fallback` bound
+  /// in place of the missing ones.
+  pub fn apply_on(&self, device: &mut D, pipeline: &D::Pipeline, fallback: &D::Texture) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("material.{}", field), value);
+    set("ambient", Uniform::Vec3(self.ambient));
+    set("diffuse", Uniform::Vec3(self.diffuse));
+    set("specular", Uniform::Vec3(self.specular));
+    set("emissive", Uniform::Vec3(self.emissive));
+    set("shininess", Uniform::Float(self.shininess));
+    set("hasNormalMap", Uniform::Bool(self.normal_map.is_some()));
+    set("diffuseMap", Uniform::Int(DIFFUSE_MAP_UNIT as i32));
+    set("specularMap", Uniform::Int(SPECULAR_MAP_UNIT as i32));
+    set("normalMap", Uniform::Int(NORMAL_MAP_UNIT as i32));
+    set("emissiveMap", Uniform::Int(EMISSIVE_MAP_UNIT as i32));
+
+    let maps = [
+      (DIFFUSE_MAP_UNIT, &self.diffuse_map),
+      (SPECULAR_MAP_UNIT, &self.specular_map),
+      (NORMAL_MAP_UNIT, &self.normal_map),
+      (EMISSIVE_MAP_UNIT, &self.emissive_map),
+    ];
+    bind_maps(device, &maps, fallback);
+  }
+}
+
+impl<D: RenderDevice> From<&PbrMaterial<D>> for PhongMaterial<D> {
+  /// A rough match: metals tint their highlight and lose their diffuse
+  /// color, and smoother surfaces get sharper highlights.
+  fn from(pbr: &PbrMaterial<D>) -> Self {
+    let dielectric = Vector3::new(0.04, 0.04, 0.04);
+    let diffuse = pbr.base_color * (1.0 - pbr.metallic);
+    let specular = dielectric + (pbr.base_color - dielectric) * pbr.metallic;
+    let smoothness = 1.0 - pbr.roughness.clamp(0.0, 1.0);
+    PhongMaterial {
+      ambient: diffuse * pbr.ao,
+      diffuse,
+      specular,
+      emissive: pbr.emissive,
+      shininess: 2.0 + 254.0 * smoothness * smoothness,
+      diffuse_map: pbr.base_color_map.clone(),
+      specular_map: None,
+      normal_map: pbr.normal_map.clone(),
+      emissive_map: pbr.emissive_map.clone(),
+    }
+  }
+}
+
+impl<D: RenderDevice> From<&PhongMaterial<D>> for PbrMaterial<D> {
+  /// The reverse of the match above: a non-metal with the diffuse color,
+  /// rougher the wider its highlight. The specular color and map are lost.
+  fn from(phong: &PhongMaterial<D>) -> Self {
+    let smoothness = ((phong.shininess - 2.0) / 254.0).clamp(0.0, 1.0).sqrt();
+    PbrMaterial {
+      base_color: phong.diffuse,
+      metallic: 0.0,
+      roughness: 1.0 - smoothness,
+      ao: 1.0,
+      emissive: phong.emissive,
+      base_color_map: phong.diffuse_map.clone(),
+      metallic_map: None,
+      roughness_map: None,
+      ao_map: None,
+      normal_map: phong.normal_map.clone(),
+      emissive_map: phong.emissive_map.clone(),
+    }
+  }
+}
+
+/// A metallic/roughness material for physically based lighting. Maps are
+/// multiplied by their factor like the colors of a `PhongMaterial`; the
+/// metallic, roughness and occlusion maps are read from their red channel.
+///
+/// In a shader the material is the `material` struct declared in
+/// `shaders/pbr.frag`.
+pub struct PbrMaterial<D: RenderDevice = GlDevice> {
+  /// Albedo of non-metals and the reflected color of metals, in linear
+  /// space.
+  pub base_color: Vector3,
+  /// 0 for non-metals, 1 for metals. Values in between are for blending at
+  /// the edges of the two.
+  pub metallic: f32,
+  /// 0 is a perfect mirror, 1 is fully diffuse.
+  pub roughness: f32,
+  /// How much ambient light reaches the surface, from 0 to 1.
+  pub ao: f32,
+  pub emissive: Vector3,
+  /// Sampled as linear color, so sRGB images (most color textures) have to
+  /// be loaded with `TextureDescriptor::srgb` set.
+  pub base_color_map: Option<Rc<D::Texture>>,
+  pub metallic_map: Option<Rc<D::Texture>>,
+  pub roughness_map: Option<Rc<D::Texture>>,
+  pub ao_map: Option<Rc<D::Texture>>,
+  /// Tangent-space normals.
+  pub normal_map: Option<Rc<D::Texture>>,
+  /// Sampled as linear color, like `base_color_map`.
+  pub emissive_map: Option<Rc<D::Texture>>,
+}
+
+impl<D: RenderDevice> Clone for PbrMaterial<D> {
+  fn clone(&self) -> Self {
+    PbrMaterial {
+      base_color: self.base_color,
+      metallic: self.metallic,
+      roughness: self.roughness,
+      ao: self.ao,
+      emissive: self.emissive,
+      base_color_map: self.base_color_map.clone(),
+      metallic_map: self.metallic_map.clone(),
+      roughness_map: self.roughness_map.clone(),
+      ao_map: self.ao_map.clone(),
+      normal_map: self.normal_map.clone(),
+      emissive_map: self.emissive_map.clone(),
+    }
+  }
+}
+
+impl<D: RenderDevice> PbrMaterial<D> {
+  /// An untextured material with nothing occluded.
+  pub const fn new(base_color: Vector3, metallic: f32, roughness: f32) -> Self {
+    Self {
+      base_color,
+      metallic,
+      roughness,
+      ao: 1.0,
+      emissive: Vector3::new(0.0, 0.0, 0.0),
+      base_color_map: None,
+      metallic_map: None,
+      roughness_map: None,
+      ao_map: None,
+      normal_map: None,
+      emissive_map: None,
+    }
+  }
+
+  pub fn with_base_color_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.base_color_map = Some(map);
+    self
+  }
+
+  pub fn with_metallic_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.metallic_map = Some(map);
+    self
+  }
+
+  pub fn with_roughness_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.roughness_map = Some(map);
+    self
+  }
+
+  pub fn with_ao_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.ao_map = Some(map);
+    self
+  }
+
+  pub fn with_normal_map(mut self, map: Rc<D::Texture>) -> Self {
+    self.normal_map = Some(map);
+    self
+  }
+
+  /// Adds an emissive map, turning the emissive color up to white if it was
+  /// black like `PhongMaterial::with_emissive_map`.
+  pub fn with_emissive_map(mut self, map: Rc<D::Texture>) -> Self {
+    if self.emissive == Vector3::new(0.0, 0.0, 0.0) {
+      self.emissive = Vector3::new(1.0, 1.0, 1.0);
+    }
+    self.emissive_map = Some(map);
+    self
+  }
+
+  /// Sets the `material` uniforms and binds the maps, with `fallback` bound
+  /// in place of the missing ones.
+  pub fn apply_on(&self, device: &mut D, pipeline: &D::Pipeline, fallback: &D::Texture) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("material.{}", field), value);
+    set("baseColor", Uniform::Vec3(self.base_color));
+    set("metallic", Uniform::Float(self.metallic));
+    set("roughness", Uniform::Float(self.roughness));
+    set("ao", Uniform::Float(self.ao));
+    set("emissive", Uniform::Vec3(self.emissive));
+    set("hasNormalMap", Uniform::Bool(self.normal_map.is_some()));
+    set("baseColorMap", Uniform::Int(BASE_COLOR_MAP_UNIT as i32));
+    set("metallicMap", Uniform::Int(METALLIC_MAP_UNIT as i32));
+    set("roughnessMap", Uniform::Int(ROUGHNESS_MAP_UNIT as i32));
+    set("aoMap", Uniform::Int(AO_MAP_UNIT as i32));
+    set("normalMap", Uniform::Int(NORMAL_MAP_UNIT as i32));
+    set("emissiveMap", Uniform::Int(EMISSIVE_MAP_UNIT as i32));
+
+    let maps = [
+      (BASE_COLOR_MAP_UNIT, &self.base_color_map),
+      (METALLIC_MAP_UNIT, &self.metallic_map),
+      (ROUGHNESS_MAP_UNIT, &self.roughness_map),
+      (AO_MAP_UNIT, &self.ao_map),
+      (NORMAL_MAP_UNIT, &self.normal_map),
+      (EMISSIVE_MAP_UNIT, &self.emissive_map),
+    ];
+    bind_maps(device, &maps, fallback);
+  }
+}
+
+fn bind_maps<D: RenderDevice>(device: &mut D, maps: &[(u32, &Option<Rc<D::Texture>>)], fallback: &D::Texture) -> () {
+  for (unit, map) in maps {
+    device.bind_texture(*unit, map.as_deref().unwrap_or(fallback), TextureKind::Texture2D);
+  }
+}
+
+/// A 1x1 white texture, bound in place of maps a material doesn't have.
+pub fn fallback_texture_on<D: RenderDevice>(device: &mut D) -> D::Texture {
+  let desc = TextureDesc {
+    kind: TextureKind::Texture2D,
+    width: 1,
+    height: 1,
+    format: PixelFormat::Rgba8,
+    sampler: SamplerDesc::default(),
+    mipmaps: false,
+  };
+  device.create_texture(&desc, &[&[255, 255, 255, 255]])
+}
@@ -0,0 +1,276 @@
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Matrix, Transform as _};
+
+use super::types::{Mat4, Point3, Vec3, Vec4};
+
+/// A half-line starting at `origin`.
+///
+/// Distances along the ray are multiples of `direction`. `new` normalizes
+/// it, so they come out in world units; a ray moved into an object's space
+/// with `transform` keeps the same distances as the original.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+  pub origin: Point3,
+  pub direction: Vec3,
+}
+
+impl Ray {
+  pub fn new(origin: Point3, direction: Vec3) -> Self {
+    Ray { origin, direction: direction.normalize() }
+  }
+
+  /// The point `distance` along the ray.
+  pub fn at(&self, distance: f32) -> Point3 {
+    self.origin + self.direction * distance
+  }
+
+  /// The ray with `matrix` applied, e.g. the inverse of a model matrix to
+  /// test against the model's vertices. The direction isn't normalized.
+  pub fn transform(&self, matrix: &Mat4) -> Self {
+    Ray { origin: matrix.transform_point(self.origin), direction: matrix.transform_vector(self.direction) }
+  }
+
+  /// Distance to where the ray enters the box, 0 if it starts inside.
+  pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+    let mut near = 0.0_f32;
+    let mut far = f32::INFINITY;
+    for axis in 0..3 {
+      // Dividing by a zero direction gives infinities, which rules the axis
+      // in or out as it should.
+      let inverse = 1.0 / self.direction[axis];
+      let t1 = (aabb.min[axis] - self.origin[axis]) * inverse;
+      let t2 = (aabb.max[axis] - self.origin[axis]) * inverse;
+      near = near.max(t1.min(t2));
+      far = far.min(t1.max(t2));
+    }
+    (near <= far).then_some(near)
+  }
+
+  /// Distance to where the ray crosses the triangle, from either side.
+  pub fn intersect_triangle(&self, a: Point3, b: Point3, c: Point3) -> Option<f32> {
+    // Möller-Trumbore.
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = self.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-12 {
+      // The ray runs along the triangle's plane.
+      return None;
+    }
+
+    let inverse = 1.0 / determinant;
+    let s = self.origin - a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+    let q = s.cross(edge1);
+    let v = self.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+      return None;
+    }
+
+    let distance = edge2.dot(q) * inverse;
+    (distance >= 0.0).then_some(distance)
+  }
+}
+
+/// An axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+  pub min: Point3,
+  pub max: Point3,
+}
+
+impl Aabb {
+  pub fn new(min: Point3, max: Point3) -> Self {
+    Aabb { min, max }
+  }
+
+  /// The smallest box around the points, or `None` if there are none.
+  pub fn from_points<I: IntoIterator<Item = Point3>>(points: I) -> Option<Self> {
+    let mut points = points.into_iter();
+    let first = points.next()?;
+    Some(points.fold(Aabb::new(first, first), |aabb, p| aabb.grow(p)))
+  }
+
+  pub fn center(&self) -> Point3 {
+    self.min.midpoint(self.max)
+  }
+
+  /// Half the size of the box along each axis.
+  pub fn half_extents(&self) -> Vec3 {
+    (self.max - self.min) / 2.0
+  }
+
+  pub fn grow(&self, point: Point3) -> Self {
+    Aabb {
+      min: Point3::new(self.min.x.min(point.x), self.min.y.min(point.y), self.min.z.min(point.z)),
+      max: Point3::new(self.max.x.max(point.x), self.max.y.max(point.y), self.max.z.max(point.z)),
+    }
+  }
+
+  pub fn union(&self, other: &Aabb) -> Self {
+    self.grow(other.min).grow(other.max)
+  }
+
+  /// The box around this one after it has been transformed. It fits the
+  /// transformed corners, so it can be looser than a box computed from the
+  /// transformed points.
+  pub fn transform(&self, matrix: &Mat4) -> Self {
+    let center = matrix.transform_point(self.center());
+    let extents = self.half_extents();
+    // Each axis of the result gets the absolute contribution of every axis
+    // of the source box.
+    let abs_row = |row: usize| vec3(matrix.x[row].abs(), matrix.y[row].abs(), matrix.z[row].abs());
+    let half = vec3(abs_row(0).dot(extents), abs_row(1).dot(extents), abs_row(2).dot(extents));
+    Aabb { min: center - half, max: center + half }
+  }
+}
+
+/// A bounding sphere.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sphere {
+  pub center: Point3,
+  pub radius: f32,
+}
+
+impl Sphere {
+  pub fn new(center: Point3, radius: f32) -> Self {
+    Sphere { center, radius }
+  }
+
+  /// The sphere through the corners of the box.
+  pub fn from_aabb(aabb: &Aabb) -> Self {
+    Sphere { center: aabb.center(), radius: aabb.half_extents().magnitude() }
+  }
+
+  /// The sphere after it has been transformed. Uneven scaling makes it grow
+  /// by the largest factor.
+  pub fn transform(&self, matrix: &Mat4) -> Self {
+    let scale = matrix.x.truncate().magnitude()
+      .max(matrix.y.truncate().magnitude())
+      .max(matrix.z.truncate().magnitude());
+    Sphere { center: matrix.transform_point(self.center), radius: self.radius * scale }
+  }
+}
+
+/// A plane made of the points `p` for which `normal.dot(p) + distance` is 0.
+/// The normal points to the inside.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plane {
+  pub normal: Vec3,
+  pub distance: f32,
+}
+
+impl Plane {
+  /// Builds a plane from the coefficients of `ax + by + cz + d = 0`,
+  /// normalizing them so distances come out in world units.
+  pub fn from_coefficients(coefficients: Vec4) -> Self {
+    let normal = coefficients.truncate();
+    let length = normal.magnitude();
+    // A plane at infinity has no normal; it's left as is and everything is
+    // in front of it.
+    let length = if length > 0.0 { length } else { 1.0 };
+    Plane { normal: normal / length, distance: coefficients.w / length }
+  }
+
+  /// Positive in front of the plane, negative behind it.
+  pub fn signed_distance(&self, point: Point3) -> f32 {
+    self.normal.dot(point.to_vec()) + self.distance
+  }
+}
+
+/// The volume a camera can see, as six inward-facing planes: left, right,
+/// bottom, top and the two depth planes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+  pub planes: [Plane; 6],
+}
+
+impl Frustum {
+  /// Extracts the planes from a view-projection matrix with OpenGL's depth
+  /// range of -1 to 1.
+  pub fn from_matrix(matrix: &Mat4) -> Self {
+    Self::from_rows(matrix, false)
+  }
+
+  /// Extracts the planes from a view-projection matrix with a depth range
+  /// of 0 to 1, as used with reversed depth.
+  pub fn from_matrix_zero_to_one(matrix: &Mat4) -> Self {
+    Self::from_rows(matrix, true)
+  }
+
+  fn from_rows(matrix: &Mat4, zero_to_one: bool) -> Self {
+    let row = |i: usize| matrix.row(i);
+    let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+    let near = if zero_to_one { z } else { w + z };
+    Frustum {
+      planes: [w + x, w - x, w + y, w - y, near, w - z].map(Plane::from_coefficients),
+    }
+  }
+
+  pub fn contains_point(&self, point: Point3) -> bool {
+    self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+  }
+
+  /// Whether any part of the sphere may be inside.
+  pub fn intersects_sphere(&self, sphere: &Sphere) -> bool {
+    self.planes.iter().all(|plane| plane.signed_distance(sphere.center) >= -sphere.radius)
+  }
+
+  /// Whether any part of the box may be inside. Boxes near a corner of the
+  /// frustum can pass without being visible, which only costs a draw.
+  pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+    let center = aabb.center();
+    let extents = aabb.half_extents();
+    self.planes.iter().all(|plane| {
+      let n = plane.normal;
+      let radius = extents.x * n.x.abs() + extents.y * n.y.abs() + extents.z * n.z.abs();
+      plane.signed_distance(center) >= -radius
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::point3;
+
+  use super::*;
+
+  fn unit_box() -> Aabb {
+    Aabb::new(point3(-1.0, -1.0, -1.0), point3(1.0, 1.0, 1.0))
+  }
+
+  #[test]
+  fn rays_enter_boxes_they_point_at() {
+    let ray = Ray::new(point3(0.0, 0.0, 5.0), vec3(0.0, 0.0, -1.0));
+    assert_eq!(ray.intersect_aabb(&unit_box()), Some(4.0));
+
+    let past = Ray::new(point3(2.0, 0.0, 5.0), vec3(0.0, 0.0, -1.0));
+    assert_eq!(past.intersect_aabb(&unit_box()), None);
+    let away = Ray::new(point3(0.0, 0.0, 5.0), vec3(0.0, 0.0, 1.0));
+    assert_eq!(away.intersect_aabb(&unit_box()), None);
+  }
+
+  #[test]
+  fn rays_starting_inside_a_box_hit_it_at_once() {
+    let ray = Ray::new(point3(0.5, 0.0, 0.0), vec3(1.0, 1.0, 0.0));
+    assert_eq!(ray.intersect_aabb(&unit_box()), Some(0.0));
+  }
+
+  #[test]
+  fn triangles_are_hit_from_both_sides() {
+    // Wound counter-clockwise seen from +Z.
+    let (a, b, c) = (point3(-1.0, -1.0, 0.0), point3(1.0, -1.0, 0.0), point3(0.0, 1.0, 0.0));
+
+    let front = Ray::new(point3(0.0, 0.0, 2.0), vec3(0.0, 0.0, -1.0));
+    assert_eq!(front.intersect_triangle(a, b, c), Some(2.0));
+    let back = Ray::new(point3(0.0, 0.0, -3.0), vec3(0.0, 0.0, 1.0));
+    assert_eq!(back.intersect_triangle(a, b, c), Some(3.0));
+
+    let beside = Ray::new(point3(1.0, 1.0, 2.0), vec3(0.0, 0.0, -1.0));
+    assert_eq!(beside.intersect_triangle(a, b, c), None);
+    let behind = Ray::new(point3(0.0, 0.0, -3.0), vec3(0.0, 0.0, -1.0));
+    assert_eq!(behind.intersect_triangle(a, b, c), None);
+  }
+}
@@ -1,11 +1,15 @@
-use std::mem::{self, offset_of};
-use std::{ffi::CString};
+use std::rc::Rc;
 
 use cgmath::*;
 
+use super::material::Material;
+use super::math::Aabb;
+use super::rendering::{
+  as_bytes, Geometry, GlDevice, IndexType, RenderDevice, TextureKind, Uniform, VertexLayout,
+};
 use super::Shader;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 #[repr(C)]
 pub struct Vertex {
   pub position: Vector3<f32>,
@@ -13,120 +17,130 @@ pub struct Vertex {
   pub tex_coords: Vector2<f32>
 }
 
-#[derive(Clone, Debug)]
-pub struct Texture {
-  pub id: u32,
+impl Vertex {
+  pub fn layout() -> VertexLayout {
+    VertexLayout::position_normal_tex_coords()
+  }
+}
+
+/// A texture used by a mesh, tagged with the sampler it should be bound to.
+/// Cloning only bumps the reference count; meshes of the same model share
+/// the underlying GL texture.
+pub struct Texture<D: RenderDevice = GlDevice> {
+  pub texture: Rc<D::Texture>,
   pub tex_type: String,
   pub path: String
 }
 
-#[derive(Debug)]
-pub struct Mesh {
+impl<D: RenderDevice> Clone for Texture<D> {
+  fn clone(&self) -> Self {
+    Texture {
+      texture: Rc::clone(&self.texture),
+      tex_type: self.tex_type.clone(),
+      path: self.path.clone()
+    }
+  }
+}
+
+pub struct Mesh<D: RenderDevice = GlDevice> {
   pub vertices: Vec<Vertex>,
   pub indices: Vec<u32>,
-  pub textures: Vec<Texture>,
-  vao: u32,
-  vbo: u32,
-  ebo: u32
+  pub textures: Vec<Texture<D>>,
+  /// What the mesh looks like when drawn in a `Scene`. `draw` binds
+  /// `textures` instead.
+  pub material: Material<D>,
+  geometry: Geometry<D>,
+  bounds: Option<Aabb>
 }
 
 impl Mesh {
   pub fn new(vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture>) -> Self {
-    let mut instance = Self {
+    Self::new_on(&mut GlDevice, vertices, indices, textures)
+  }
+
+  pub fn draw(&self, shader: &Shader) -> () {
+    self.draw_on(&mut GlDevice, shader);
+  }
+}
+
+impl<D: RenderDevice> Mesh<D> {
+  pub fn new_on(
+    device: &mut D, vertices: Vec<Vertex>, indices: Vec<u32>, textures: Vec<Texture<D>>
+  ) -> Self {
+    let geometry = Geometry::new_indexed(
+      device,
+      as_bytes(&vertices),
+      &Vertex::layout(),
+      as_bytes(&indices),
+      IndexType::U32,
+      indices.len() as i32,
+    );
+
+    let bounds = Aabb::from_points(vertices.iter().map(|v| Point3::from_vec(v.position)));
+
+    Self {
       vertices,
       indices,
       textures,
-      vao: 0,
-      vbo: 0,
-      ebo: 0
-    };
+      material: Material::default(),
+      geometry,
+      bounds
+    }
+  }
 
-    instance.setup_mesh();
+  pub fn with_material(mut self, material: Material<D>) -> Self {
+    self.material = material;
+    self
+  }
 
-    instance
+  /// Box around the vertices, worked out when the mesh is created.
+  pub fn bounds(&self) -> Option<Aabb> {
+    self.bounds
   }
 
-  fn setup_mesh(&mut self) -> () {
-    unsafe {
-      gl::GenVertexArrays(1, &mut self.vao);
-      gl::GenBuffers(1, &mut self.vbo);
-      gl::GenBuffers(1, &mut self.ebo);
-
-      gl::BindVertexArray(self.vao);
-
-      gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
-      gl::BufferData(
-        gl::ARRAY_BUFFER,
-        (mem::size_of::<Vertex>() * self.vertices.len()) as isize,
-        self.vertices.as_ptr().cast(),
-        gl::STATIC_DRAW
-      );
-
-      gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
-      gl::BufferData(
-        gl::ELEMENT_ARRAY_BUFFER,
-        (mem::size_of::<u32>() * self.indices.len()) as isize,
-        self.indices.as_ptr().cast(),
-        gl::STATIC_DRAW
-      );
-
-      let mem_size = mem::size_of::<Vertex>() as i32;
-      let stride = mem_size;
-      gl::EnableVertexAttribArray(0);
-      gl::VertexAttribPointer(
-        0, 3, gl::FLOAT, gl::FALSE, stride, offset_of!(Vertex, position) as *const _);
-  
-      gl::EnableVertexAttribArray(1);
-      gl::VertexAttribPointer(
-        1, 3, gl::FLOAT, gl::FALSE, stride, offset_of!(Vertex, normal) as *const _);
-  
-      gl::EnableVertexAttribArray(2);
-      gl::VertexAttribPointer(
-        2, 2, gl::FLOAT, gl::FALSE, stride, offset_of!(Vertex, tex_coords) as *const _);
-
-      gl::BindVertexArray(0);
-    }
+  /// Corners of every triangle.
+  pub fn triangles(&self) -> impl Iterator<Item = [Point3<f32>; 3]> + '_ {
+    self.indices.chunks_exact(3).map(|triangle| {
+      [0, 1, 2].map(|corner| Point3::from_vec(self.vertices[triangle[corner] as usize].position))
+    })
   }
 
-  pub fn draw(&self, shader: &Shader) -> () {
+  pub fn draw_on(&self, device: &mut D, shader: &D::Pipeline) -> () {
     let mut diffuse_nr = 0;
     let mut specular_nr = 0;
     let mut normal_nr = 0;
     let mut height_nr = 0;
-    unsafe {
-      for i in 0..self.textures.len() {
-        gl::ActiveTexture(gl::TEXTURE0 + (i as u32));
-        let name = &self.textures[i].tex_type;
-        let number = match name.as_str() {
-          "texture_diffuse" => {
-            diffuse_nr += 1;
-            diffuse_nr
-          }
-          "texture_specular" => {
-            specular_nr += 1;
-            specular_nr
-          }
-          "texture_normal" => {
-            normal_nr += 1;
-            normal_nr
-          }
-          "texture_height" => {
-            height_nr += 1;
-            height_nr
-          }
-          _ => panic!("Texture type could not be determined!")
-        };
-
-        gl::Uniform1i(
-          gl::GetUniformLocation(
-            shader.id,
-            CString::new(format!("{}{}", name, number)).unwrap().into_raw()), i as i32);
-        gl::BindTexture(gl::TEXTURE_2D, self.textures[i].id);
-      }
-      gl::BindVertexArray(self.vao);
-      gl::DrawElements(gl::TRIANGLES, self.indices.len().try_into().unwrap(), gl::UNSIGNED_INT, std::ptr::null());
-      gl::BindVertexArray(0);
-      gl::ActiveTexture(gl::TEXTURE0);
+    for (i, texture) in self.textures.iter().enumerate() {
+      let name = &texture.tex_type;
+      let number = match name.as_str() {
+        "texture_diffuse" => {
+          diffuse_nr += 1;
+          diffuse_nr
+        }
+        "texture_specular" => {
+          specular_nr += 1;
+          specular_nr
+        }
+        "texture_normal" => {
+          normal_nr += 1;
+          normal_nr
+        }
+        "texture_height" => {
+          height_nr += 1;
+          height_nr
+        }
+        _ => panic!("Texture type could not be determined!")
+      };
+
+      device.set_uniform(shader, &format!("{}{}", name, number), Uniform::Int(i as i32));
+      device.bind_texture(i as u32, &texture.texture, TextureKind::Texture2D);
     }
+    self.geometry.draw(device);
   }
-}
\ No newline at end of file
+
+  /// Draws the mesh without binding anything, leaving `material` to the
+  /// caller.
+  pub fn draw_geometry_on(&self, device: &mut D) -> () {
+    self.geometry.draw(device);
+  }
+}
@@ -1,3 +1,5 @@
+pub mod error;
+
 pub mod shader;
 pub use shader::*;
 
@@ -9,6 +11,11 @@ pub use camera::*;
 
 pub mod material;
 
+pub mod light;
+pub use light::*;
+
+pub mod shadow;
+
 pub mod mesh;
 
 pub mod model;
@@ -23,4 +30,24 @@ pub mod rendering;
 
 pub mod types;
 
-pub mod scene;
\ No newline at end of file
+pub mod math;
+pub use math::*;
+
+pub mod transform;
+pub use transform::*;
+
+pub mod scene;
+
+mod text_format;
+
+pub mod ecs;
+
+pub mod app;
+
+pub mod input;
+
+pub mod time;
+pub use time::*;
+
+#[cfg(feature = "headless")]
+pub mod headless;
@@ -1,48 +1,90 @@
-use image::{self, GenericImageView};
 use std::path::Path;
+use std::rc::Rc;
 use cgmath::{vec2, vec3};
 use tobj;
 
-use super::mesh::{self, Mesh, Vertex};
+use super::mesh::{Mesh, Vertex};
 use super::mesh::Texture;
+use super::texture::{self, TextureDescriptor};
+use super::error::{Error, Result};
+use super::material::{Material, PbrMaterial, PhongMaterial, Vector3};
+use super::math::Aabb;
+use super::rendering::{GlDevice, RenderDevice};
 use super::Shader;
 
-#[derive(Default)]
-pub struct Model {
-  pub meshes: Vec<Mesh>,
-  pub loaded_textures: Vec<Texture>,
+pub struct Model<D: RenderDevice = GlDevice> {
+  pub meshes: Vec<Mesh<D>>,
+  pub loaded_textures: Vec<Texture<D>>,
+  /// Whether the model shows up in shadow maps. On by default.
+  pub cast_shadows: bool,
+  /// Whether shadows fall on the model. On by default.
+  pub receive_shadows: bool,
+  /// How the model's textures were set up. `srgb` only applies to color
+  /// maps (diffuse and emissive), and PBR materials always decode theirs
+  /// since their shader lights in linear space. Data maps (normal,
+  /// specular, metallic, roughness) are loaded as linear.
+  pub texture_descriptor: TextureDescriptor,
   directory: String
 }
 
 impl Model {
-  pub fn new(path: &str) -> Self {
-    let mut instance = Model::default();
-
-    instance.load_model(path);
+  pub fn new(path: &str) -> Result<Self> {
+    Self::new_on(&mut GlDevice, path)
+  }
 
-    instance
+  pub fn with_descriptor(path: &str, descriptor: TextureDescriptor) -> Result<Self> {
+    Self::with_descriptor_on(&mut GlDevice, path, descriptor)
   }
 
   pub fn draw(&self, shader: &Shader) -> () {
+    self.draw_on(&mut GlDevice, shader);
+  }
+}
+
+impl<D: RenderDevice> Model<D> {
+  /// Loads the model with textures set up by the default
+  /// `TextureDescriptor`.
+  pub fn new_on(device: &mut D, path: &str) -> Result<Self> {
+    Self::with_descriptor_on(device, path, TextureDescriptor::default())
+  }
+
+  /// Loads the model with textures set up by `descriptor`.
+  pub fn with_descriptor_on(device: &mut D, path: &str, descriptor: TextureDescriptor) -> Result<Self> {
+    let mut instance = Model {
+      meshes: Vec::new(),
+      loaded_textures: Vec::new(),
+      cast_shadows: true,
+      receive_shadows: true,
+      texture_descriptor: descriptor,
+      directory: String::new()
+    };
+
+    instance.load_model(device, path)?;
+
+    Ok(instance)
+  }
+
+  /// Box around every mesh.
+  pub fn bounds(&self) -> Option<Aabb> {
+    self.meshes.iter().filter_map(|mesh| mesh.bounds()).reduce(|a, b| a.union(&b))
+  }
+
+  pub fn draw_on(&self, device: &mut D, shader: &D::Pipeline) -> () {
     for mesh in &self.meshes {
-      mesh.draw(shader);
+      mesh.draw_on(device, shader);
     }
   }
 
-  pub fn load_model(&mut self, path: &str) -> () {
+  pub fn load_model(&mut self, device: &mut D, path: &str) -> Result<()> {
     let path = Path::new(path);
 
     // If path parent doesn't exist, make a new Path that's empty.
     self.directory = path.parent()
     .unwrap_or_else(
       || Path::new("")).to_str().unwrap().into();
-    let loaded_obj = tobj::load_obj(path);
-
-    let (models, materials) = loaded_obj.unwrap();
-    if models.len() < 1 {
-      dbg!("No models found in loaded object!");
-      return;
-    }
+    let (models, materials) = tobj::load_obj(path)
+      .map_err(|source| Error::Obj { path: path.into(), source })?;
+    // A file without any objects loads as an empty model.
 
     for model in models {
       let mesh = &model.mesh;
@@ -53,96 +95,239 @@ impl Model {
 
       let (positions, normals, tex_coords) =
         (&mesh.positions, &mesh.normals, &mesh.texcoords);
+      // Files without `vn` or `vt` lines leave those empty; such vertices
+      // get zero normals and texture coordinates.
+      let normal = |i: usize| normals.get(i*3..i*3+3).map_or(vec3(0.0, 0.0, 0.0), |n| vec3(n[0], n[1], n[2]));
+      let tex_coord = |i: usize| tex_coords.get(i*2..i*2+2).map_or(vec2(0.0, 0.0), |t| vec2(t[0], t[1]));
       for i in 0..vertex_count {
         vertices.push(Vertex {
           position: vec3(positions[i*3], positions[i*3+1], positions[i*3+2]),
-          normal: vec3(normals[i*3], normals[i*3+1], normals[i*3+2]),
-          tex_coords: vec2(tex_coords[i*2], tex_coords[i*2+1])
+          normal: normal(i),
+          tex_coords: tex_coord(i)
         })
       }
 
-      let mut textures: Vec<Texture> = Vec::new();
+      let mut textures: Vec<Texture<D>> = Vec::new();
+      let mut mesh_material = Material::default();
       if let Some(material_id) = mesh.material_id {
         let material = &materials[material_id];
+        let pbr = is_pbr(material);
 
         if !material.diffuse_texture.is_empty() {
-          let texture = self.load_material_texture(&material.diffuse_texture, "texture_diffuse");
+          let texture = self.load_material_texture(device, &material.diffuse_texture, "texture_diffuse", pbr)?;
           textures.push(texture);
         }
         
         if !material.specular_texture.is_empty() {
-          let texture = self.load_material_texture(&material.specular_texture, "texture_specular");
-          textures.push(texture);
-        }
-        if !material.normal_texture.is_empty() {
-          let texture = self.load_material_texture(&material.normal_texture, "texture_normal");
+          let texture = self.load_material_texture(device, &material.specular_texture, "texture_specular", pbr)?;
           textures.push(texture);
         }
         
         // Todo: height maps?
+
+        mesh_material = self.load_material(device, material)?;
       }
 
-      self.meshes.push(Mesh::new(vertices, indices, textures));
+      self.meshes.push(Mesh::new_on(device, vertices, indices, textures).with_material(mesh_material));
     }
+
+    Ok(())
   }
 
-  fn load_material_texture(&mut self, path: &str, texture_type: &str) -> Texture {
-    let texture = self.loaded_textures.iter().find(|tex| tex.path == path);
+  fn load_material_texture(
+    &mut self, device: &mut D, path: &str, texture_type: &str, pbr: bool
+  ) -> Result<Texture<D>> {
+    // The same file can be used for more than one kind of map, but color
+    // and data maps are decoded differently.
+    let texture = self.loaded_textures.iter()
+      .find(|tex| tex.path == path && is_color_map(&tex.tex_type) == is_color_map(texture_type));
     if let Some(texture) = texture {
-      return texture.clone();
+      return Ok(Texture { tex_type: texture_type.into(), ..texture.clone() });
     } else {
+      let descriptor = TextureDescriptor {
+        srgb: is_color_map(texture_type) && (self.texture_descriptor.srgb || pbr),
+        ..self.texture_descriptor
+      };
       let texture = Texture {
-        id: unsafe { Self::texture_from_file(path, &self.directory) },
+        texture: Rc::new(self.texture_from_file(device, path, &descriptor)?),
         tex_type: texture_type.into(),
         path: path.into()
       };
       self.loaded_textures.push(texture.clone());
-      texture
+      Ok(texture)
     }
   }
 
-  unsafe fn texture_from_file(path: &str, dir: &str) ->  u32 {
-    let file_name = format!("{}/{}", dir, path);
-
-    let mut tex_id = 0;
-    gl::GenTextures(1, &mut tex_id);
-
-    let img = image::open(&Path::new(&file_name))
-      .expect("Issue with loading texture!");
-
-    // let img = img.flipv();
-    let format = match img {
-        image::DynamicImage::ImageLuma8(_) => gl::RED,
-        image::DynamicImage::ImageLumaA8(_) => gl::RG,
-        image::DynamicImage::ImageRgb8(_) => gl::RGB,
-        image::DynamicImage::ImageRgba8(_) => gl::RGBA,
-        image::DynamicImage::ImageLuma16(_) => todo!(),
-        image::DynamicImage::ImageLumaA16(_) => todo!(),
-        image::DynamicImage::ImageRgb16(_) => todo!(),
-        image::DynamicImage::ImageRgba16(_) => todo!(),
-        image::DynamicImage::ImageRgb32F(_) => todo!(),
-        image::DynamicImage::ImageRgba32F(_) => todo!(),
-        _ => todo!(),
-    };
+  // A PBR material if the MTL uses the PBR extension (`Pr`, `Pm` and their
+  // maps), a Phong one otherwise. tobj leaves the extension's keys, along
+  // with emission and bump maps, in `unknown_param`. Its `normal_texture` is
+  // `map_Ns`, a shininess map, so it isn't used for normals. MTL has no key
+  // for ambient occlusion maps.
+  fn load_material(&mut self, device: &mut D, mtl: &tobj::Material) -> Result<Material<D>> {
+    let param = |key: &str| mtl.unknown_param.get(key).map(String::as_str);
+    let path = |texture: &str| (!texture.is_empty()).then(|| texture.to_string());
+    let float = |key: &str| param(key).and_then(|value| value.trim().parse::<f32>().ok());
+
+    let emissive = param("Ke").and_then(parse_color);
+    let emissive_path = param("map_Ke").and_then(texture_file);
+    let normal_path = param("norm")
+      .or(param("map_Bump"))
+      .or(param("map_bump"))
+      .or(param("bump"))
+      .and_then(texture_file);
+
+    let pbr = is_pbr(mtl);
+    let normal_map = self.load_map(device, normal_path, "texture_normal", pbr)?;
+    let emissive_map = self.load_map(device, emissive_path, "texture_emissive", pbr)?;
+    let diffuse_map = self.load_map(device, path(&mtl.diffuse_texture), "texture_diffuse", pbr)?;
 
-    gl::BindTexture(gl::TEXTURE_2D, tex_id);
-    gl::TexImage2D(
-      gl::TEXTURE_2D,
-      0,
-      format as i32,
-      img.dimensions().0 as i32,
-      img.dimensions().1 as i32,
-      0,
-      format,
-      gl::UNSIGNED_BYTE,
-      img.as_bytes().as_ptr() as *const _
+    if pbr {
+      let metallic_path = param("map_Pm").and_then(texture_file);
+      let roughness_path = param("map_Pr").and_then(texture_file);
+      // A map on its own is used as is, so its factor defaults to 1.
+      let metallic = float("Pm").unwrap_or(if metallic_path.is_some() { 1.0 } else { 0.0 });
+      let roughness = float("Pr").unwrap_or(1.0);
+
+      let mut material = PbrMaterial::new(Vector3::from(mtl.diffuse), metallic, roughness);
+      material.emissive = emissive.unwrap_or(material.emissive);
+      material.base_color_map = diffuse_map;
+      material.metallic_map = self.load_map(device, metallic_path, "texture_metallic", pbr)?;
+      material.roughness_map = self.load_map(device, roughness_path, "texture_roughness", pbr)?;
+      material.normal_map = normal_map;
+      material.emissive_map = emissive_map;
+      Ok(material.into())
+    } else {
+      let mut material = PhongMaterial::new(
+        Vector3::from(mtl.ambient),
+        Vector3::from(mtl.diffuse),
+        Vector3::from(mtl.specular),
+        // 0 would light up the whole surface with the highlight.
+        mtl.shininess.max(1.0),
+      );
+      material.emissive = emissive.unwrap_or(material.emissive);
+      material.diffuse_map = diffuse_map;
+      material.specular_map = self.load_map(device, path(&mtl.specular_texture), "texture_specular", pbr)?;
+      material.normal_map = normal_map;
+      material.emissive_map = emissive_map;
+      Ok(material.into())
+    }
+  }
+
+  fn load_map(
+    &mut self, device: &mut D, path: Option<String>, texture_type: &str, pbr: bool
+  ) -> Result<Option<Rc<D::Texture>>> {
+    match path {
+      Some(path) => Ok(Some(self.load_material_texture(device, &path, texture_type, pbr)?.texture)),
+      None => Ok(None),
+    }
+  }
+
+  fn texture_from_file(&self, device: &mut D, path: &str, descriptor: &TextureDescriptor) -> Result<D::Texture> {
+    let file_name = format!("{}/{}", self.directory, path);
+    texture::load_texture_with(device, &file_name, descriptor)
+  }
+}
+
+// Whether the MTL material uses the PBR extension.
+fn is_pbr(mtl: &tobj::Material) -> bool {
+  ["Pr", "Pm", "map_Pr", "map_Pm"].iter().any(|key| mtl.unknown_param.contains_key(*key))
+}
+
+// Maps holding colors, as opposed to data like normals or roughness.
+fn is_color_map(texture_type: &str) -> bool {
+  matches!(texture_type, "texture_diffuse" | "texture_emissive")
+}
+
+// Texture statements can have options before the file name, e.g.
+// `map_Bump -bm 0.5 normal.png`.
+fn texture_file(value: &str) -> Option<String> {
+  value.split_whitespace().last().map(String::from)
+}
+
+// An `r g b` color, or a single value for gray.
+fn parse_color(value: &str) -> Option<Vector3> {
+  let values: Vec<f32> = value.split_whitespace().map(str::parse).collect::<std::result::Result<_, _>>().ok()?;
+  match values[..] {
+    [gray] => Some(Vector3::new(gray, gray, gray)),
+    [r, g, b] => Some(Vector3::new(r, g, b)),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::rendering::{Command, PixelFormat, RecordingDevice};
+
+  const OBJ: &str = "mtllib model.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 0 1\nvn 0 0 1\nusemtl surface\nf 1/1/1 2/2/1 3/3/1\n";
+
+  // Writes a one-triangle model whose material uses `mtl`, with every map
+  // it names as a 1x1 RGB image.
+  fn write_model(name: &str, mtl: &str, maps: &[&str]) -> String {
+    let directory = std::env::temp_dir().join(format!("cogwheel-model-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(directory.join("model.obj"), OBJ).unwrap();
+    std::fs::write(directory.join("model.mtl"), format!("newmtl surface\n{}", mtl)).unwrap();
+    for map in maps {
+      image::RgbImage::new(1, 1).save(directory.join(map)).unwrap();
+    }
+    directory.join("model.obj").to_str().unwrap().into()
+  }
+
+  fn texture_formats(device: &RecordingDevice) -> Vec<PixelFormat> {
+    device
+      .commands()
+      .iter()
+      .filter_map(|c| match c {
+        Command::CreateTexture { desc, .. } => Some(desc.format),
+        _ => None,
+      })
+      .collect()
+  }
+
+  #[test]
+  fn missing_normals_and_tex_coords_default_to_zero() {
+    let directory = std::env::temp_dir().join(format!("cogwheel-model-{}-bare", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let path = directory.join("bare.obj");
+    std::fs::write(&path, "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+
+    let mut device = RecordingDevice::new();
+    let model = Model::new_on(&mut device, path.to_str().unwrap()).unwrap();
+
+    assert_eq!(model.meshes.len(), 1);
+    assert!(model.meshes[0].vertices.iter().all(|v| v.normal == vec3(0.0, 0.0, 0.0) && v.tex_coords == vec2(0.0, 0.0)));
+  }
+
+  #[test]
+  fn pbr_color_maps_are_srgb_and_data_maps_linear() {
+    let path = write_model(
+      "pbr",
+      "Kd 1 1 1\nPr 0.5\nmap_Kd base.png\nmap_Ke glow.png\nnorm normal.png\nmap_Pr rough.png\n",
+      &["base.png", "glow.png", "normal.png", "rough.png"],
     );
-    gl::GenerateMipmap(gl::TEXTURE_2D);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER,gl::LINEAR_MIPMAP_LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    let mut device = RecordingDevice::new();
+    Model::new_on(&mut device, &path).unwrap();
 
-    tex_id
+    // Diffuse, normal, emissive, roughness: in the order they are loaded.
+    assert_eq!(
+      texture_formats(&device),
+      [PixelFormat::Srgb8, PixelFormat::Rgb8, PixelFormat::Srgb8, PixelFormat::Rgb8]
+    );
   }
-}
\ No newline at end of file
+
+  #[test]
+  fn srgb_descriptor_only_applies_to_color_maps() {
+    let path = write_model(
+      "phong",
+      "Kd 1 1 1\nmap_Kd base.png\nmap_Ks specular.png\nmap_Bump normal.png\n",
+      &["base.png", "specular.png", "normal.png"],
+    );
+    let mut device = RecordingDevice::new();
+    let descriptor = TextureDescriptor { srgb: true, ..Default::default() };
+    Model::with_descriptor_on(&mut device, &path, descriptor).unwrap();
+
+    // Diffuse, specular, normal.
+    assert_eq!(texture_formats(&device), [PixelFormat::Srgb8, PixelFormat::Rgb8, PixelFormat::Rgb8]);
+  }
+}
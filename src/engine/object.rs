@@ -1,4 +1,4 @@
-use std::mem;
+use std::rc::Rc;
 
 use crate::data;
 use crate::engine::Shader;
@@ -6,89 +6,117 @@ use cgmath::SquareMatrix;
 use data::VertexArray;
 
 use super::{
-  material::Vector3,
-  rendering::{VAO, VBO},
-  Texture,
+  error::Result,
+  material::{Material, Vector3},
+  math::Aabb,
+  rendering::{Geometry, GlDevice, RenderDevice, Uniform},
+  texture::load_texture,
+  Texture, Transform,
 };
 
 use super::types::Mat4;
 
-#[derive(Clone)]
-pub struct Object {
+/// A piece of geometry placed in the world, with the material it is drawn
+/// with. `D` is the device the
+/// object's resources live on; everything but tests uses the default.
+///
+/// If the vertex array carries indices the object is drawn with an element
+/// buffer, otherwise its vertices are drawn in order.
+pub struct Object<D: RenderDevice = GlDevice> {
   pub vertex_array: VertexArray,
-  pub vao: VAO,
-  pub vbo: VBO,
-  pub texture: Texture,
-  pub position: Vector3,
+  pub geometry: Geometry<D>,
+  pub material: Material<D>,
+  /// Placement of the object. When the object is part of a `Scene` this is
+  /// relative to the node that holds it.
+  pub transform: Transform,
+  /// Box around the vertices in the object's own space, worked out when the
+  /// object is created. Used for culling.
+  pub bounds: Option<Aabb>,
+  /// Whether the object shows up in shadow maps. On by default.
+  pub cast_shadows: bool,
+  /// Whether shadows fall on the object. On by default.
+  pub receive_shadows: bool,
 }
 
 impl Object {
-  pub fn new(vertex_array: VertexArray, texture_path: &str, position: Vector3) -> Self {
-    let instance = Object {
-      vertex_array,
-      vao: VAO::new(),
-      vbo: VBO::new(),
-      texture: Texture::new(texture_path),
-      position,
-    };
-
-    unsafe {
-      instance.vao.bind();
-      instance.vbo.buffer_data(&instance.vertex_array.vertices);
-
-      // Probably move this portion to the vbo struct
-      let s = instance.vertex_array.stride as i32;
-      let m = mem::size_of::<f32>();
-      gl::EnableVertexAttribArray(0);
-      gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, s, 0 as *const _);
-
-      if instance.vertex_array.has_normals {
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, s, (3 * m) as *const _);
-      }
-
-      if instance.vertex_array.has_tex_coords {
-        gl::EnableVertexAttribArray(2);
-        let mut offset = 3 * m;
-        if instance.vertex_array.has_normals {
-          offset = 6 * m;
-        }
-        gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, s, offset as *const _);
-      }
-
-      VAO::unbind();
-    }
+  pub fn new(vertex_array: VertexArray, texture_path: &str, position: Vector3) -> Result<Self> {
+    Self::new_on(&mut GlDevice, vertex_array, texture_path, position)
+  }
 
-    instance
+  /// Creates an object that shares an already loaded texture.
+  pub fn with_texture(vertex_array: VertexArray, texture: Rc<Texture>, position: Vector3) -> Self {
+    Self::with_texture_on(&mut GlDevice, vertex_array, texture, position)
   }
 
-  pub fn draw(&self, shader: &mut Shader) -> () {
-    unsafe {
-      self.vao.bind();
-      gl::BindTexture(gl::TEXTURE_2D, self.texture.id);
-      let mut model = Mat4::identity();
-      model = model * Mat4::from_translation(self.position);
-      shader.set_mat4("model", model);
-      gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_array.triangle_count);
-    }
+  pub fn with_material(vertex_array: VertexArray, material: Material, position: Vector3) -> Self {
+    Self::with_material_on(&mut GlDevice, vertex_array, material, position)
+  }
+
+  /// See `draw_on` for `fallback`.
+  pub fn draw(&self, shader: &mut Shader, fallback: &Texture) -> () {
+    self.material.apply(shader, fallback);
+    self.draw_with_parent_on(&mut GlDevice, shader, Mat4::identity());
   }
 
   pub fn draw_outlines(&self, shader: &mut Shader, size: f32) -> () {
-    unsafe {
-      self.vao.bind();
-      let mut model = Mat4::identity();
-      model = model * Mat4::from_translation(self.position);
-      model = model * Mat4::from_scale(size);
-      shader.set_mat4("model", model);
-      gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_array.triangle_count);
-    }
+    self.draw_outlines_on(&mut GlDevice, shader, size);
+  }
+}
+
+impl<D: RenderDevice> Object<D> {
+  pub fn new_on(
+    device: &mut D, vertex_array: VertexArray, texture_path: &str, position: Vector3
+  ) -> Result<Self> {
+    let texture = Rc::new(load_texture(device, texture_path)?);
+
+    Ok(Self::with_texture_on(device, vertex_array, texture, position))
   }
 
-  pub fn delete(&self) -> () {
-    unsafe {
-      self.vao.delete();
-      self.vbo.delete();
-      gl::DeleteTextures(1, &self.texture.id);
+  /// Creates an object drawn with `Material::textured`.
+  pub fn with_texture_on(
+    device: &mut D, vertex_array: VertexArray, texture: Rc<D::Texture>, position: Vector3
+  ) -> Self {
+    Self::with_material_on(device, vertex_array, Material::textured(texture), position)
+  }
+
+  pub fn with_material_on(
+    device: &mut D, vertex_array: VertexArray, material: Material<D>, position: Vector3
+  ) -> Self {
+    let geometry = Geometry::from_vertex_array(device, &vertex_array);
+    let bounds = vertex_array.bounds();
+
+    Object {
+      bounds,
+      vertex_array,
+      geometry,
+      material,
+      transform: Transform::from_translation(position),
+      cast_shadows: true,
+      receive_shadows: true,
     }
   }
+
+  pub fn model_matrix(&self) -> Mat4 {
+    self.transform.matrix()
+  }
+
+  /// Draws the object with its material. `fallback` is bound in place of
+  /// the maps the material doesn't have (see `fallback_texture_on`).
+  pub fn draw_on(&self, device: &mut D, shader: &D::Pipeline, fallback: &D::Texture) -> () {
+    self.material.apply_on(device, shader, fallback);
+    self.draw_with_parent_on(device, shader, Mat4::identity());
+  }
+
+  /// Draws the object with `parent` applied on top of its own transform,
+  /// leaving the material to the caller.
+  pub fn draw_with_parent_on(&self, device: &mut D, shader: &D::Pipeline, parent: Mat4) -> () {
+    device.set_uniform(shader, "model", Uniform::Mat4(parent * self.model_matrix()));
+    self.geometry.draw(device);
+  }
+
+  pub fn draw_outlines_on(&self, device: &mut D, shader: &D::Pipeline, size: f32) -> () {
+    let model = self.model_matrix() * Mat4::from_scale(size);
+    device.set_uniform(shader, "model", Uniform::Mat4(model));
+    self.geometry.draw(device);
+  }
 }
@@ -3,3 +3,24 @@ pub use vao::*;
 
 pub mod vbo;
 pub use vbo::*;
+
+pub mod ebo;
+pub use ebo::*;
+
+pub mod framebuffer;
+pub use framebuffer::*;
+
+pub mod layout;
+pub use layout::*;
+
+pub mod device;
+pub use device::*;
+
+pub mod gl_device;
+pub use gl_device::*;
+
+pub mod recording;
+pub use recording::*;
+
+pub mod geometry;
+pub use geometry::*;
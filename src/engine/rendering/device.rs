@@ -0,0 +1,296 @@
+use serde::{Deserialize, Serialize};
+
+use crate::engine::error::Result;
+use crate::engine::types::{Mat4, Vec3};
+
+use super::VertexLayout;
+
+/// Everything the engine needs from a graphics API.
+///
+/// Engine types (`Object`, `Mesh`, `Screen`, `Skybox`...) only talk to the
+/// GPU through this trait. The resources it hands out are associated types,
+/// so each backend decides how they are owned and released: `GlDevice`
+/// returns the RAII wrappers from this module, while `RecordingDevice`
+/// returns plain ids and keeps a log of everything that was asked of it.
+pub trait RenderDevice {
+  type Buffer;
+  type InputLayout;
+  type Texture;
+  type Pipeline;
+  type DepthTarget;
+
+  fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> Self::Buffer;
+
+  /// Describes how the vertex buffer is read. If an index buffer is given
+  /// it is bound to the layout as well, so indexed draws can use it.
+  fn create_vertex_layout(
+    &mut self,
+    vertex_buffer: &Self::Buffer,
+    index_buffer: Option<&Self::Buffer>,
+    layout: &VertexLayout,
+  ) -> Self::InputLayout;
+
+  /// `layers` holds one slice of pixels per layer: one for a 2D texture,
+  /// six (+X, -X, +Y, -Y, +Z, -Z) for a cubemap. With no layers the
+  /// texture is left uninitialized, e.g. to be rendered into.
+  fn create_texture(&mut self, desc: &TextureDesc, layers: &[&[u8]]) -> Self::Texture;
+
+  /// A 2D texture in a block-compressed `desc.format`, from its mip levels,
+  /// largest first. The texture has exactly the levels given; compressed
+  /// mipmaps aren't generated, so `desc.mipmaps` is ignored.
+  fn create_compressed_texture(&mut self, desc: &TextureDesc, levels: &[&[u8]]) -> Self::Texture;
+
+  /// Whether textures in `format` can be created. Every uncompressed format
+  /// can; some compressed ones depend on the GPU.
+  fn supports_format(&self, _format: PixelFormat) -> bool {
+    true
+  }
+
+  /// A target that only draws depth, into a `PixelFormat::Depth32F`
+  /// texture described by `desc`. `layer` is the cubemap face, in the same
+  /// order as in `create_texture`, and 0 for 2D textures. Fails if the
+  /// GPU can't render to it, e.g. when it is larger than textures can be.
+  fn create_depth_target(&mut self, texture: &Self::Texture, desc: &TextureDesc, layer: u32) -> Result<Self::DepthTarget>;
+
+  /// Sends draws to `target` until `end_depth_pass`, with the viewport
+  /// covering it and its depth cleared to 1.
+  fn begin_depth_pass(&mut self, target: &Self::DepthTarget);
+
+  /// Sends draws back to where they went before `begin_depth_pass`, with
+  /// the viewport they had.
+  fn end_depth_pass(&mut self, target: &Self::DepthTarget);
+
+  fn create_pipeline(&mut self, vertex_shader: &str, fragment_shader: &str) -> Result<Self::Pipeline>;
+
+  fn use_pipeline(&mut self, pipeline: &Self::Pipeline);
+
+  fn set_uniform(&mut self, pipeline: &Self::Pipeline, name: &str, value: Uniform);
+
+  fn bind_texture(&mut self, unit: u32, texture: &Self::Texture, kind: TextureKind);
+
+  fn set_depth_func(&mut self, func: DepthFunc);
+
+  /// Switches between the usual depth range and reversed depth going from
+  /// 1 (near) to 0 (far), as used by `Projection::InfinitePerspective`. The
+  /// depth clear value follows, so call this before clearing. Backends that
+  /// can't change the depth range keep the usual one; reversed depth still
+  /// sorts correctly in it, with less precision.
+  fn set_reverse_z(&mut self, enabled: bool);
+
+  fn draw(&mut self, layout: &Self::InputLayout, call: DrawCall);
+}
+
+/// Reinterprets a slice of plain data (floats, indices, vertex structs) as
+/// bytes for `RenderDevice::create_buffer`.
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+  unsafe { std::slice::from_raw_parts(data.as_ptr().cast(), std::mem::size_of_val(data)) }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferKind {
+  Vertex,
+  Index,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureKind {
+  Texture2D,
+  CubeMap,
+  /// Layers of 2D images of the same size, sampled by index.
+  Texture2DArray(u32),
+}
+
+impl TextureKind {
+  /// How many images the texture is made of.
+  pub fn layers(&self) -> u32 {
+    match *self {
+      TextureKind::Texture2D => 1,
+      TextureKind::CubeMap => 6,
+      TextureKind::Texture2DArray(layers) => layers,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+  R8,
+  Rg8,
+  Rgb8,
+  Rgba8,
+  /// Color stored in sRGB, decoded to linear when sampled.
+  Srgb8,
+  Srgba8,
+  /// Gray, sampled as the same value in red, green and blue.
+  Luma8,
+  /// Gray and alpha, sampled as gray in red, green and blue.
+  LumaAlpha8,
+  Luma16,
+  LumaAlpha16,
+  Rgb16,
+  Rgba16,
+  Rgb32F,
+  Rgba32F,
+  /// 32-bit float depth, for shadow maps. Can't be uploaded to.
+  Depth32F,
+  /// Block-compressed formats, only for `create_compressed_texture`. BC1
+  /// is RGB with 1-bit alpha, BC2 and BC3 add sharp and smooth alpha, BC4
+  /// and BC5 hold one and two channels, BC6H holds HDR color and BC7 is
+  /// high quality RGBA.
+  Bc1,
+  Bc1Srgb,
+  Bc2,
+  Bc2Srgb,
+  Bc3,
+  Bc3Srgb,
+  Bc4,
+  Bc5,
+  Bc6hUfloat,
+  Bc6hSfloat,
+  Bc7,
+  Bc7Srgb,
+}
+
+impl PixelFormat {
+  /// Bytes in each 4x4 block of a compressed format, or `None` for formats
+  /// that aren't compressed.
+  pub fn block_size(&self) -> Option<usize> {
+    match self {
+      PixelFormat::Bc1 | PixelFormat::Bc1Srgb | PixelFormat::Bc4 => Some(8),
+      PixelFormat::Bc2 | PixelFormat::Bc2Srgb | PixelFormat::Bc3 | PixelFormat::Bc3Srgb | PixelFormat::Bc5
+        | PixelFormat::Bc6hUfloat | PixelFormat::Bc6hSfloat | PixelFormat::Bc7 | PixelFormat::Bc7Srgb => Some(16),
+      _ => None,
+    }
+  }
+
+  /// The sRGB counterpart of an 8-bit color format. Formats without one
+  /// are returned as they are.
+  pub fn to_srgb(self) -> Self {
+    match self {
+      PixelFormat::Rgb8 => PixelFormat::Srgb8,
+      PixelFormat::Rgba8 => PixelFormat::Srgba8,
+      PixelFormat::Bc1 => PixelFormat::Bc1Srgb,
+      PixelFormat::Bc2 => PixelFormat::Bc2Srgb,
+      PixelFormat::Bc3 => PixelFormat::Bc3Srgb,
+      PixelFormat::Bc7 => PixelFormat::Bc7Srgb,
+      format => format,
+    }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureWrap {
+  Repeat,
+  MirroredRepeat,
+  ClampToEdge,
+  /// Outside the texture is the sampler's border color.
+  ClampToBorder,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextureFilter {
+  /// The closest texel; keeps pixel art sharp.
+  Nearest,
+  /// A blend of the closest texels.
+  Linear,
+}
+
+/// How a texture is sampled. The default repeats and filters linearly
+/// within and between mip levels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplerDesc {
+  pub wrap_s: TextureWrap,
+  pub wrap_t: TextureWrap,
+  /// Only used by cubemaps and arrays.
+  pub wrap_r: TextureWrap,
+  pub min_filter: TextureFilter,
+  pub mag_filter: TextureFilter,
+  /// How neighbouring mip levels are blended, for textures with mipmaps.
+  pub mip_filter: TextureFilter,
+  /// Samples taken along surfaces seen at an angle. 1 turns anisotropic
+  /// filtering off; more than the GPU supports is clamped.
+  pub anisotropy: f32,
+  /// RGBA color outside the texture with `TextureWrap::ClampToBorder`.
+  pub border_color: [f32; 4],
+}
+
+impl Default for SamplerDesc {
+  fn default() -> Self {
+    SamplerDesc {
+      wrap_s: TextureWrap::Repeat,
+      wrap_t: TextureWrap::Repeat,
+      wrap_r: TextureWrap::Repeat,
+      min_filter: TextureFilter::Linear,
+      mag_filter: TextureFilter::Linear,
+      mip_filter: TextureFilter::Linear,
+      anisotropy: 1.0,
+      border_color: [0.0; 4],
+    }
+  }
+}
+
+impl SamplerDesc {
+  /// The default sampler with `wrap` in every direction.
+  pub fn wrapping(wrap: TextureWrap) -> Self {
+    SamplerDesc { wrap_s: wrap, wrap_t: wrap, wrap_r: wrap, ..Default::default() }
+  }
+
+  /// The sampler with `filter` for minifying, magnifying and mip levels.
+  pub fn filtered(self, filter: TextureFilter) -> Self {
+    SamplerDesc { min_filter: filter, mag_filter: filter, mip_filter: filter, ..self }
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureDesc {
+  pub kind: TextureKind,
+  pub width: u32,
+  pub height: u32,
+  pub format: PixelFormat,
+  pub sampler: SamplerDesc,
+  /// Whether mip levels are generated from the uploaded image.
+  pub mipmaps: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Uniform {
+  Bool(bool),
+  Int(i32),
+  Float(f32),
+  Vec3(Vec3),
+  Mat4(Mat4),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthFunc {
+  Less,
+  LessEqual,
+  Greater,
+  GreaterEqual,
+  Always,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexType {
+  U16,
+  U32,
+}
+
+/// What to draw from the currently described layout. `indices` is set for
+/// draws that read from the layout's index buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawCall {
+  pub first: i32,
+  pub count: i32,
+  pub indices: Option<IndexType>,
+}
+
+impl DrawCall {
+  pub fn arrays(first: i32, count: i32) -> Self {
+    DrawCall { first, count, indices: None }
+  }
+
+  pub fn elements(count: i32, index_type: IndexType) -> Self {
+    DrawCall { first: 0, count, indices: Some(index_type) }
+  }
+}
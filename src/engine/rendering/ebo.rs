@@ -0,0 +1,46 @@
+use std::mem;
+
+/// Owns an OpenGL element (index) buffer. The element buffer binding is
+/// part of VAO state, so binding the buffer (which `buffer_data` does)
+/// attaches it to whichever VAO is bound. Unbind the VAO before uploading,
+/// and bind the buffer with its own VAO bound to attach it there.
+pub struct EBO {
+  id: u32,
+}
+
+impl EBO {
+  pub fn new() -> Self {
+    let mut id = 0;
+    unsafe {
+      gl::GenBuffers(1, &mut id);
+    }
+    EBO { id }
+  }
+
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
+  pub unsafe fn bind(&self) {
+    gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.id);
+  }
+
+  pub unsafe fn buffer_data<T>(&self, data: &[T]) {
+    self.bind();
+    let len = data.len() * mem::size_of::<T>();
+    gl::BufferData(
+      gl::ELEMENT_ARRAY_BUFFER,
+      len as isize,
+      data.as_ptr() as *const _,
+      gl::STATIC_DRAW,
+    );
+  }
+}
+
+impl Drop for EBO {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteBuffers(1, &self.id);
+    }
+  }
+}
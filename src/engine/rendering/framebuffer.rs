@@ -0,0 +1,176 @@
+use std::cell::Cell;
+
+use image::RgbaImage;
+
+use crate::engine::error::{Error, Result};
+use crate::engine::Texture;
+
+use super::{gl_device::layer_target, TextureKind};
+
+/// An offscreen render target with an RGBA8 color attachment and a combined
+/// depth/stencil attachment. Both renderbuffers and the framebuffer itself
+/// are released on drop.
+pub struct Framebuffer {
+  id: u32,
+  color_rbo: u32,
+  depth_rbo: u32,
+  width: u32,
+  height: u32,
+}
+
+impl Framebuffer {
+  pub fn new(width: u32, height: u32) -> Result<Self> {
+    let mut instance = Framebuffer { id: 0, color_rbo: 0, depth_rbo: 0, width, height };
+
+    unsafe {
+      gl::GenFramebuffers(1, &mut instance.id);
+      gl::BindFramebuffer(gl::FRAMEBUFFER, instance.id);
+
+      gl::GenRenderbuffers(1, &mut instance.color_rbo);
+      gl::BindRenderbuffer(gl::RENDERBUFFER, instance.color_rbo);
+      gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width as i32, height as i32);
+      gl::FramebufferRenderbuffer(
+        gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, instance.color_rbo);
+
+      gl::GenRenderbuffers(1, &mut instance.depth_rbo);
+      gl::BindRenderbuffer(gl::RENDERBUFFER, instance.depth_rbo);
+      gl::RenderbufferStorage(
+        gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width as i32, height as i32);
+      gl::FramebufferRenderbuffer(
+        gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, instance.depth_rbo);
+
+      gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+      let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+      Self::unbind();
+
+      if status != gl::FRAMEBUFFER_COMPLETE {
+        return Err(Error::IncompleteFramebuffer { status });
+      }
+    }
+
+    Ok(instance)
+  }
+
+  pub fn width(&self) -> u32 {
+    self.width
+  }
+
+  pub fn height(&self) -> u32 {
+    self.height
+  }
+
+  /// Binds the framebuffer and sets the viewport to cover all of it.
+  pub unsafe fn bind(&self) {
+    gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+    gl::Viewport(0, 0, self.width as i32, self.height as i32);
+  }
+
+  pub unsafe fn unbind() {
+    gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+  }
+
+  /// Reads the color attachment back into an image. OpenGL's origin is the
+  /// bottom-left corner, so the rows are flipped to match image space.
+  pub fn read_pixels(&self) -> RgbaImage {
+    let mut pixels: Vec<u8> = vec![0; (self.width * self.height * 4) as usize];
+
+    unsafe {
+      gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.id);
+      gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+      gl::ReadPixels(
+        0, 0, self.width as i32, self.height as i32,
+        gl::RGBA, gl::UNSIGNED_BYTE, pixels.as_mut_ptr().cast()
+      );
+      gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+    }
+
+    let img = RgbaImage::from_raw(self.width, self.height, pixels)
+      .expect("Pixel buffer size always matches the framebuffer size");
+    image::imageops::flip_vertical(&img)
+  }
+}
+
+impl Drop for Framebuffer {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteRenderbuffers(1, &self.depth_rbo);
+      gl::DeleteRenderbuffers(1, &self.color_rbo);
+      gl::DeleteFramebuffers(1, &self.id);
+    }
+  }
+}
+
+/// A framebuffer with a depth texture (or one face of a depth cubemap, or
+/// one layer of an array) as its only attachment, for shadow maps. The texture is borrowed, not
+/// owned; the framebuffer is released on drop.
+pub struct DepthTarget {
+  id: u32,
+  width: u32,
+  height: u32,
+  // Framebuffer and viewport from before `begin`, put back by `end`.
+  previous: Cell<(i32, [i32; 4])>,
+}
+
+impl DepthTarget {
+  /// `layer` is the cubemap face or array layer to draw into.
+  pub fn new(texture: &Texture, kind: TextureKind, layer: u32, width: u32, height: u32) -> Result<Self> {
+    let mut instance = DepthTarget { id: 0, width, height, previous: Cell::new((0, [0; 4])) };
+
+    unsafe {
+      let mut previous = 0;
+      gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut previous);
+
+      gl::GenFramebuffers(1, &mut instance.id);
+      gl::BindFramebuffer(gl::FRAMEBUFFER, instance.id);
+      match kind {
+        TextureKind::Texture2DArray(_) => {
+          gl::FramebufferTextureLayer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, texture.id(), 0, layer as i32);
+        }
+        _ => {
+          let target = layer_target(kind, layer);
+          gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, target, texture.id(), 0);
+        }
+      }
+      gl::DrawBuffer(gl::NONE);
+      gl::ReadBuffer(gl::NONE);
+
+      let status = gl::CheckFramebufferStatus(gl::FRAMEBUFFER);
+      gl::BindFramebuffer(gl::FRAMEBUFFER, previous as u32);
+
+      if status != gl::FRAMEBUFFER_COMPLETE {
+        return Err(Error::IncompleteFramebuffer { status });
+      }
+    }
+
+    Ok(instance)
+  }
+
+  /// Binds the target, covers it with the viewport and clears it.
+  pub unsafe fn begin(&self) {
+    let mut framebuffer = 0;
+    let mut viewport = [0; 4];
+    gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut framebuffer);
+    gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+    self.previous.set((framebuffer, viewport));
+
+    gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+    gl::Viewport(0, 0, self.width as i32, self.height as i32);
+    gl::ClearDepth(1.0);
+    gl::Clear(gl::DEPTH_BUFFER_BIT);
+  }
+
+  /// Goes back to the framebuffer and viewport from before `begin`.
+  pub unsafe fn end(&self) {
+    let (framebuffer, [x, y, width, height]) = self.previous.get();
+    gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer as u32);
+    gl::Viewport(x, y, width, height);
+  }
+}
+
+impl Drop for DepthTarget {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteFramebuffers(1, &self.id);
+    }
+  }
+}
@@ -0,0 +1,78 @@
+use crate::data::VertexArray;
+
+use super::{as_bytes, BufferKind, DrawCall, IndexType, RenderDevice, VertexLayout};
+
+/// Vertex data that has been uploaded to a device, with or without an index
+/// buffer, together with the draw call that renders all of it. Built-in
+/// primitives and model meshes both draw through this.
+pub struct Geometry<D: RenderDevice> {
+  pub layout: D::InputLayout,
+  // Only referenced through the layout, but they must live as long as it.
+  _vertex_buffer: D::Buffer,
+  _index_buffer: Option<D::Buffer>,
+  draw_call: DrawCall,
+}
+
+impl<D: RenderDevice> Geometry<D> {
+  /// Uploads non-indexed vertices; `vertex_count` of them are drawn.
+  pub fn new(device: &mut D, vertex_data: &[u8], layout: &VertexLayout, vertex_count: i32) -> Self {
+    let vertex_buffer = device.create_buffer(BufferKind::Vertex, vertex_data);
+    let input_layout = device.create_vertex_layout(&vertex_buffer, None, layout);
+
+    Geometry {
+      layout: input_layout,
+      _vertex_buffer: vertex_buffer,
+      _index_buffer: None,
+      draw_call: DrawCall::arrays(0, vertex_count),
+    }
+  }
+
+  /// Uploads vertices together with `index_count` indices of `index_type`.
+  pub fn new_indexed(
+    device: &mut D,
+    vertex_data: &[u8],
+    layout: &VertexLayout,
+    index_data: &[u8],
+    index_type: IndexType,
+    index_count: i32,
+  ) -> Self {
+    let vertex_buffer = device.create_buffer(BufferKind::Vertex, vertex_data);
+    let index_buffer = device.create_buffer(BufferKind::Index, index_data);
+    let input_layout = device.create_vertex_layout(&vertex_buffer, Some(&index_buffer), layout);
+
+    Geometry {
+      layout: input_layout,
+      _vertex_buffer: vertex_buffer,
+      _index_buffer: Some(index_buffer),
+      draw_call: DrawCall::elements(index_count, index_type),
+    }
+  }
+
+  /// Uploads a vertex array, using its indices if it has any.
+  pub fn from_vertex_array(device: &mut D, vertex_array: &VertexArray) -> Self {
+    let vertex_data = as_bytes(&vertex_array.vertices);
+    match &vertex_array.indices {
+      Some(indices) => Self::new_indexed(
+        device,
+        vertex_data,
+        &vertex_array.layout,
+        indices.as_bytes(),
+        indices.index_type(),
+        indices.len() as i32,
+      ),
+      None => Self::new(device, vertex_data, &vertex_array.layout, vertex_array.triangle_count),
+    }
+  }
+
+  pub fn draw_call(&self) -> DrawCall {
+    self.draw_call
+  }
+
+  pub fn is_indexed(&self) -> bool {
+    self.draw_call.indices.is_some()
+  }
+
+  pub fn draw(&self, device: &mut D) -> () {
+    device.draw(&self.layout, self.draw_call);
+  }
+}
@@ -0,0 +1,475 @@
+use std::cell::Cell;
+use std::ffi::CStr;
+
+use crate::engine::error::Result;
+use crate::engine::{Shader, Texture};
+
+use super::device::*;
+use super::{ComponentType, DepthTarget, VertexLayout, EBO, VAO, VBO};
+
+/// The OpenGL 3.3 core backend. OpenGL keeps its state in the context that
+/// is current on the calling thread, so the device itself holds nothing and
+/// every resource it creates releases itself on drop.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlDevice;
+
+/// Optional features of the OpenGL context current on this thread, beyond
+/// the 3.3 core the engine asks for. Until `detect` has run on the thread
+/// every feature reads as missing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GlCapabilities {
+  /// `glClipControl`, from GL 4.5 or ARB_clip_control. Reverse-Z needs it
+  /// for its extra precision.
+  pub clip_control: bool,
+  /// BC6H and BC7 textures, from GL 4.2 or ARB_texture_compression_bptc.
+  pub bptc: bool,
+}
+
+thread_local! {
+  static CAPABILITIES: Cell<GlCapabilities> = Cell::new(GlCapabilities::default());
+}
+
+impl GlCapabilities {
+  /// Queries the current context and remembers the result for this
+  /// thread. Call once after making a context current and loading the GL
+  /// functions.
+  pub fn detect() -> Self {
+    let (mut major, mut minor, mut count) = (0, 0, 0);
+    let mut extensions = Vec::new();
+    unsafe {
+      gl::GetIntegerv(gl::MAJOR_VERSION, &mut major);
+      gl::GetIntegerv(gl::MINOR_VERSION, &mut minor);
+      gl::GetIntegerv(gl::NUM_EXTENSIONS, &mut count);
+      for i in 0..count.max(0) as u32 {
+        let name = gl::GetStringi(gl::EXTENSIONS, i);
+        if !name.is_null() {
+          extensions.push(CStr::from_ptr(name.cast()).to_string_lossy().into_owned());
+        }
+      }
+    }
+
+    let version = (major, minor);
+    let has = |extension: &str| extensions.iter().any(|e| e == extension);
+    let capabilities = GlCapabilities {
+      clip_control: version >= (4, 5) || has("GL_ARB_clip_control"),
+      bptc: version >= (4, 2) || has("GL_ARB_texture_compression_bptc"),
+    };
+    CAPABILITIES.with(|c| c.set(capabilities));
+    capabilities
+  }
+
+  /// What `detect` found on this thread.
+  pub fn current() -> Self {
+    CAPABILITIES.with(|c| c.get())
+  }
+}
+
+pub enum GlBuffer {
+  Vertex(VBO),
+  Index(EBO),
+}
+
+impl RenderDevice for GlDevice {
+  type Buffer = GlBuffer;
+  type InputLayout = VAO;
+  type Texture = Texture;
+  type Pipeline = Shader;
+  type DepthTarget = DepthTarget;
+
+  fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> GlBuffer {
+    unsafe {
+      match kind {
+        BufferKind::Vertex => {
+          let vbo = VBO::new();
+          vbo.buffer_data(data);
+          VBO::unbind();
+          GlBuffer::Vertex(vbo)
+        }
+        BufferKind::Index => {
+          // Uploading binds the buffer, which would replace the element
+          // buffer of whichever VAO is bound. It is attached to its own VAO
+          // in `create_vertex_layout`.
+          VAO::unbind();
+          let ebo = EBO::new();
+          ebo.buffer_data(data);
+          GlBuffer::Index(ebo)
+        }
+      }
+    }
+  }
+
+  fn create_vertex_layout(
+    &mut self,
+    vertex_buffer: &GlBuffer,
+    index_buffer: Option<&GlBuffer>,
+    layout: &VertexLayout,
+  ) -> VAO {
+    let vao = VAO::new();
+
+    unsafe {
+      vao.bind();
+
+      if let GlBuffer::Vertex(vbo) = vertex_buffer {
+        vbo.bind();
+      }
+      let stride = layout.stride() as i32;
+      for (attribute, offset) in layout.offsets() {
+        let location = attribute.attribute.location();
+        let component_type = component_type(attribute.component_type);
+        gl::EnableVertexAttribArray(location);
+        if attribute.component_type.is_integer() && !attribute.normalized {
+          gl::VertexAttribIPointer(
+            location, attribute.components, component_type, stride, offset as *const _
+          );
+        } else {
+          gl::VertexAttribPointer(
+            location, attribute.components, component_type, attribute.normalized as u8,
+            stride, offset as *const _
+          );
+        }
+      }
+      if let Some(GlBuffer::Index(ebo)) = index_buffer {
+        ebo.bind();
+      }
+
+      VAO::unbind();
+      VBO::unbind();
+    }
+
+    vao
+  }
+
+  fn create_texture(&mut self, desc: &TextureDesc, layers: &[&[u8]]) -> Texture {
+    let target = texture_target(desc.kind);
+    let (internal_format, format, component_type) = pixel_format(desc.format);
+    let layer_count = desc.kind.layers() as usize;
+    let mut id = 0;
+
+    unsafe {
+      gl::GenTextures(1, &mut id);
+      gl::BindTexture(target, id);
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+      if let TextureKind::Texture2DArray(_) = desc.kind {
+        // Arrays are allocated in one go and filled layer by layer.
+        gl::TexImage3D(
+          target,
+          0,
+          internal_format as i32,
+          desc.width as i32,
+          desc.height as i32,
+          layer_count as i32,
+          0,
+          format, component_type,
+          std::ptr::null()
+        );
+      }
+
+      for i in 0..layer_count {
+        let pixels = match layers.get(i) {
+          Some(pixels) => pixels.as_ptr(),
+          None if layers.is_empty() => std::ptr::null(),
+          None => continue,
+        };
+        if let TextureKind::Texture2DArray(_) = desc.kind {
+          if !pixels.is_null() {
+            gl::TexSubImage3D(
+              target, 0, 0, 0, i as i32,
+              desc.width as i32, desc.height as i32, 1,
+              format, component_type,
+              pixels as *const _
+            );
+          }
+          continue;
+        }
+        gl::TexImage2D(
+          layer_target(desc.kind, i as u32),
+          0,
+          internal_format as i32,
+          desc.width as i32,
+          desc.height as i32,
+          0,
+          format, component_type,
+          pixels as *const _
+        );
+      }
+      gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+      if let Some(swizzle) = swizzle(desc.format) {
+        gl::TexParameteriv(target, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+      }
+      if desc.mipmaps {
+        gl::GenerateMipmap(target);
+      }
+      set_sampler(target, &desc.sampler, desc.mipmaps);
+    }
+
+    Texture::from_id(id)
+  }
+
+  fn create_compressed_texture(&mut self, desc: &TextureDesc, levels: &[&[u8]]) -> Texture {
+    let target = gl::TEXTURE_2D;
+    let (internal_format, _, _) = pixel_format(desc.format);
+    let mut id = 0;
+
+    unsafe {
+      gl::GenTextures(1, &mut id);
+      gl::BindTexture(target, id);
+
+      for (level, data) in levels.iter().enumerate() {
+        gl::CompressedTexImage2D(
+          target,
+          level as i32,
+          internal_format,
+          (desc.width >> level).max(1) as i32,
+          (desc.height >> level).max(1) as i32,
+          0,
+          data.len() as i32,
+          data.as_ptr() as *const _
+        );
+      }
+      // Otherwise the texture counts as incomplete without a full chain.
+      gl::TexParameteri(target, gl::TEXTURE_MAX_LEVEL, levels.len().saturating_sub(1) as i32);
+      if let Some(swizzle) = swizzle(desc.format) {
+        gl::TexParameteriv(target, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+      }
+      set_sampler(target, &desc.sampler, levels.len() > 1);
+    }
+
+    Texture::from_id(id)
+  }
+
+  fn supports_format(&self, format: PixelFormat) -> bool {
+    match format {
+      PixelFormat::Bc6hUfloat | PixelFormat::Bc6hSfloat | PixelFormat::Bc7 | PixelFormat::Bc7Srgb => {
+        GlCapabilities::current().bptc
+      }
+      _ => true,
+    }
+  }
+
+  fn create_depth_target(&mut self, texture: &Texture, desc: &TextureDesc, layer: u32) -> Result<DepthTarget> {
+    // Every GL 3.3 implementation renders to 32-bit float depth, so this
+    // only fails for sizes the texture itself couldn't be made in.
+    DepthTarget::new(texture, desc.kind, layer, desc.width, desc.height)
+  }
+
+  fn begin_depth_pass(&mut self, target: &DepthTarget) {
+    unsafe {
+      target.begin();
+    }
+  }
+
+  fn end_depth_pass(&mut self, target: &DepthTarget) {
+    unsafe {
+      target.end();
+    }
+  }
+
+  fn create_pipeline(&mut self, vertex_shader: &str, fragment_shader: &str) -> Result<Shader> {
+    Shader::new(vertex_shader, fragment_shader)
+  }
+
+  fn use_pipeline(&mut self, pipeline: &Shader) {
+    pipeline.activate();
+  }
+
+  fn set_uniform(&mut self, pipeline: &Shader, name: &str, value: Uniform) {
+    match value {
+      Uniform::Bool(v) => pipeline.set_bool(name, v),
+      Uniform::Int(v) => pipeline.set_int(name, v),
+      Uniform::Float(v) => pipeline.set_float(name, v),
+      Uniform::Vec3(v) => pipeline.set_vec3(name, &v),
+      Uniform::Mat4(v) => pipeline.set_mat4(name, v),
+    }
+  }
+
+  fn bind_texture(&mut self, unit: u32, texture: &Texture, kind: TextureKind) {
+    unsafe {
+      gl::ActiveTexture(gl::TEXTURE0 + unit);
+      gl::BindTexture(texture_target(kind), texture.id());
+    }
+  }
+
+  fn set_depth_func(&mut self, func: DepthFunc) {
+    let func = match func {
+      DepthFunc::Less => gl::LESS,
+      DepthFunc::LessEqual => gl::LEQUAL,
+      DepthFunc::Greater => gl::GREATER,
+      DepthFunc::GreaterEqual => gl::GEQUAL,
+      DepthFunc::Always => gl::ALWAYS,
+    };
+    unsafe {
+      gl::DepthFunc(func);
+    }
+  }
+
+  fn set_reverse_z(&mut self, enabled: bool) {
+    unsafe {
+      // Without clip control depth stays in -1 to 1. Reversed depth (1 to
+      // 0) still sorts correctly in it, only without the extra precision.
+      if GlCapabilities::current().clip_control {
+        gl::ClipControl(gl::LOWER_LEFT, if enabled { gl::ZERO_TO_ONE } else { gl::NEGATIVE_ONE_TO_ONE });
+      }
+      gl::ClearDepth(if enabled { 0.0 } else { 1.0 });
+    }
+  }
+
+  fn draw(&mut self, layout: &VAO, call: DrawCall) {
+    unsafe {
+      layout.bind();
+      match call.indices {
+        None => gl::DrawArrays(gl::TRIANGLES, call.first, call.count),
+        Some(index_type) => {
+          let (gl_type, size) = match index_type {
+            IndexType::U16 => (gl::UNSIGNED_SHORT, 2),
+            IndexType::U32 => (gl::UNSIGNED_INT, 4),
+          };
+          let offset = call.first as usize * size;
+          gl::DrawElements(gl::TRIANGLES, call.count, gl_type, offset as *const _);
+        }
+      }
+      VAO::unbind();
+    }
+  }
+}
+
+fn texture_target(kind: TextureKind) -> gl::types::GLenum {
+  match kind {
+    TextureKind::Texture2D => gl::TEXTURE_2D,
+    TextureKind::CubeMap => gl::TEXTURE_CUBE_MAP,
+    TextureKind::Texture2DArray(_) => gl::TEXTURE_2D_ARRAY,
+  }
+}
+
+// The target a single layer is uploaded to. Array layers have none of
+// their own.
+pub(super) fn layer_target(kind: TextureKind, layer: u32) -> gl::types::GLenum {
+  match kind {
+    TextureKind::Texture2D | TextureKind::Texture2DArray(_) => gl::TEXTURE_2D,
+    TextureKind::CubeMap => gl::TEXTURE_CUBE_MAP_POSITIVE_X + layer,
+  }
+}
+
+fn component_type(component_type: ComponentType) -> gl::types::GLenum {
+  match component_type {
+    ComponentType::F32 => gl::FLOAT,
+    ComponentType::U8 => gl::UNSIGNED_BYTE,
+    ComponentType::I8 => gl::BYTE,
+    ComponentType::U16 => gl::UNSIGNED_SHORT,
+    ComponentType::I16 => gl::SHORT,
+    ComponentType::U32 => gl::UNSIGNED_INT,
+  }
+}
+
+// Internal format, pixel format and component type.
+fn pixel_format(format: PixelFormat) -> (gl::types::GLenum, gl::types::GLenum, gl::types::GLenum) {
+  match format {
+    PixelFormat::R8 | PixelFormat::Luma8 => (gl::R8, gl::RED, gl::UNSIGNED_BYTE),
+    PixelFormat::Rg8 | PixelFormat::LumaAlpha8 => (gl::RG8, gl::RG, gl::UNSIGNED_BYTE),
+    PixelFormat::Rgb8 => (gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE),
+    PixelFormat::Rgba8 => (gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE),
+    PixelFormat::Srgb8 => (gl::SRGB8, gl::RGB, gl::UNSIGNED_BYTE),
+    PixelFormat::Srgba8 => (gl::SRGB8_ALPHA8, gl::RGBA, gl::UNSIGNED_BYTE),
+    PixelFormat::Luma16 => (gl::R16, gl::RED, gl::UNSIGNED_SHORT),
+    PixelFormat::LumaAlpha16 => (gl::RG16, gl::RG, gl::UNSIGNED_SHORT),
+    PixelFormat::Rgb16 => (gl::RGB16, gl::RGB, gl::UNSIGNED_SHORT),
+    PixelFormat::Rgba16 => (gl::RGBA16, gl::RGBA, gl::UNSIGNED_SHORT),
+    PixelFormat::Rgb32F => (gl::RGB32F, gl::RGB, gl::FLOAT),
+    PixelFormat::Rgba32F => (gl::RGBA32F, gl::RGBA, gl::FLOAT),
+    PixelFormat::Depth32F => (gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT),
+    // Compressed data is uploaded as it is, so only the internal format
+    // matters.
+    PixelFormat::Bc1 => (COMPRESSED_RGBA_S3TC_DXT1, 0, 0),
+    PixelFormat::Bc1Srgb => (COMPRESSED_SRGB_ALPHA_S3TC_DXT1, 0, 0),
+    PixelFormat::Bc2 => (COMPRESSED_RGBA_S3TC_DXT3, 0, 0),
+    PixelFormat::Bc2Srgb => (COMPRESSED_SRGB_ALPHA_S3TC_DXT3, 0, 0),
+    PixelFormat::Bc3 => (COMPRESSED_RGBA_S3TC_DXT5, 0, 0),
+    PixelFormat::Bc3Srgb => (COMPRESSED_SRGB_ALPHA_S3TC_DXT5, 0, 0),
+    PixelFormat::Bc4 => (gl::COMPRESSED_RED_RGTC1, 0, 0),
+    PixelFormat::Bc5 => (gl::COMPRESSED_RG_RGTC2, 0, 0),
+    PixelFormat::Bc6hUfloat => (gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT, 0, 0),
+    PixelFormat::Bc6hSfloat => (gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT, 0, 0),
+    PixelFormat::Bc7 => (gl::COMPRESSED_RGBA_BPTC_UNORM, 0, 0),
+    PixelFormat::Bc7Srgb => (gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM, 0, 0),
+  }
+}
+
+// From EXT_texture_compression_s3tc and EXT_texture_sRGB, which the
+// bindings leave out. Every desktop driver has them.
+const COMPRESSED_RGBA_S3TC_DXT1: gl::types::GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: gl::types::GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: gl::types::GLenum = 0x83F3;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT1: gl::types::GLenum = 0x8C4D;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT3: gl::types::GLenum = 0x8C4E;
+const COMPRESSED_SRGB_ALPHA_S3TC_DXT5: gl::types::GLenum = 0x8C4F;
+
+// From EXT_texture_filter_anisotropic, which the bindings leave out. Core
+// OpenGL 4.6 uses the same values.
+const TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: gl::types::GLenum = 0x84FF;
+
+// Sets the sampling parameters of the texture bound to `target`.
+unsafe fn set_sampler(target: gl::types::GLenum, sampler: &SamplerDesc, mipmaps: bool) {
+  let wrap = |wrap: TextureWrap| match wrap {
+    TextureWrap::Repeat => gl::REPEAT,
+    TextureWrap::MirroredRepeat => gl::MIRRORED_REPEAT,
+    TextureWrap::ClampToEdge => gl::CLAMP_TO_EDGE,
+    TextureWrap::ClampToBorder => gl::CLAMP_TO_BORDER,
+  } as i32;
+  gl::TexParameteri(target, gl::TEXTURE_WRAP_S, wrap(sampler.wrap_s));
+  gl::TexParameteri(target, gl::TEXTURE_WRAP_T, wrap(sampler.wrap_t));
+  gl::TexParameteri(target, gl::TEXTURE_WRAP_R, wrap(sampler.wrap_r));
+  gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, sampler.border_color.as_ptr());
+
+  let min_filter = match (sampler.min_filter, mipmaps.then_some(sampler.mip_filter)) {
+    (TextureFilter::Nearest, None) => gl::NEAREST,
+    (TextureFilter::Linear, None) => gl::LINEAR,
+    (TextureFilter::Nearest, Some(TextureFilter::Nearest)) => gl::NEAREST_MIPMAP_NEAREST,
+    (TextureFilter::Nearest, Some(TextureFilter::Linear)) => gl::NEAREST_MIPMAP_LINEAR,
+    (TextureFilter::Linear, Some(TextureFilter::Nearest)) => gl::LINEAR_MIPMAP_NEAREST,
+    (TextureFilter::Linear, Some(TextureFilter::Linear)) => gl::LINEAR_MIPMAP_LINEAR,
+  };
+  let mag_filter = match sampler.mag_filter {
+    TextureFilter::Nearest => gl::NEAREST,
+    TextureFilter::Linear => gl::LINEAR,
+  };
+  gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, min_filter as i32);
+  gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, mag_filter as i32);
+
+  if sampler.anisotropy > 1.0 {
+    // Without the extension the query fails and leaves the maximum at 0.
+    let mut max = 0.0;
+    gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+    if max >= 1.0 {
+      gl::TexParameterf(target, TEXTURE_MAX_ANISOTROPY, sampler.anisotropy.min(max));
+    } else {
+      gl::GetError();
+    }
+  }
+}
+
+// Where the channels of gray formats come from when sampled.
+fn swizzle(format: PixelFormat) -> Option<[gl::types::GLint; 4]> {
+  let (red, green, one) = (gl::RED as i32, gl::GREEN as i32, gl::ONE as i32);
+  match format {
+    // BC4 holds one channel, like gray images compressed from PNGs.
+    PixelFormat::Luma8 | PixelFormat::Luma16 | PixelFormat::Bc4 => Some([red, red, red, one]),
+    PixelFormat::LumaAlpha8 | PixelFormat::LumaAlpha16 => Some([red, red, red, green]),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn one_channel_formats_sample_as_gray() {
+    let gray = Some([gl::RED as i32, gl::RED as i32, gl::RED as i32, gl::ONE as i32]);
+    assert_eq!(swizzle(PixelFormat::Luma8), gray);
+    assert_eq!(swizzle(PixelFormat::Bc4), gray);
+    // Two-channel data like normal maps is left alone.
+    assert_eq!(swizzle(PixelFormat::Bc5), None);
+  }
+}
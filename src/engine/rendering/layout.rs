@@ -0,0 +1,160 @@
+use std::mem;
+
+/// What a vertex attribute means. Each one is bound to a fixed shader
+/// location, so every shader can rely on e.g. normals being at location 1
+/// regardless of which other attributes a mesh has.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Attribute {
+  Position,
+  Normal,
+  TexCoord0,
+  Tangent,
+  Color,
+  TexCoord1,
+  BoneIndices,
+  BoneWeights,
+  /// Anything else, at an explicit location (8 and up is free).
+  Custom(u32),
+}
+
+impl Attribute {
+  pub fn location(self) -> u32 {
+    match self {
+      Attribute::Position => 0,
+      Attribute::Normal => 1,
+      Attribute::TexCoord0 => 2,
+      Attribute::Tangent => 3,
+      Attribute::Color => 4,
+      Attribute::TexCoord1 => 5,
+      Attribute::BoneIndices => 6,
+      Attribute::BoneWeights => 7,
+      Attribute::Custom(location) => location,
+    }
+  }
+}
+
+/// Component type of an attribute as it is stored in the vertex buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComponentType {
+  F32,
+  U8,
+  I8,
+  U16,
+  I16,
+  U32,
+}
+
+impl ComponentType {
+  pub fn size(self) -> usize {
+    match self {
+      ComponentType::F32 | ComponentType::U32 => 4,
+      ComponentType::U16 | ComponentType::I16 => 2,
+      ComponentType::U8 | ComponentType::I8 => 1,
+    }
+  }
+
+  pub fn is_integer(self) -> bool {
+    self != ComponentType::F32
+  }
+}
+
+/// One attribute of an interleaved vertex.
+///
+/// Integer attributes with `normalized` set are read by the shader as floats
+/// in 0..1 (or -1..1 for signed types), e.g. `u8` vertex colors. Without it
+/// they stay integers, which is what bone indices want.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VertexAttribute {
+  pub attribute: Attribute,
+  pub components: i32,
+  pub component_type: ComponentType,
+  pub normalized: bool,
+}
+
+impl VertexAttribute {
+  pub const fn float(attribute: Attribute, components: i32) -> Self {
+    VertexAttribute { attribute, components, component_type: ComponentType::F32, normalized: false }
+  }
+
+  pub const fn normalized(attribute: Attribute, components: i32, component_type: ComponentType) -> Self {
+    VertexAttribute { attribute, components, component_type, normalized: true }
+  }
+
+  pub const fn integer(attribute: Attribute, components: i32, component_type: ComponentType) -> Self {
+    VertexAttribute { attribute, components, component_type, normalized: false }
+  }
+
+  pub fn size(&self) -> usize {
+    self.components as usize * self.component_type.size()
+  }
+}
+
+/// Describes an interleaved vertex: its attributes in the order they appear
+/// in memory, packed without padding. Offsets and stride are derived from
+/// the list, so adding an attribute only means adding it here.
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct VertexLayout {
+  attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+  pub fn new(attributes: &[VertexAttribute]) -> Self {
+    VertexLayout { attributes: attributes.to_vec() }
+  }
+
+  /// Appends an attribute after the existing ones.
+  pub fn with(mut self, attribute: VertexAttribute) -> Self {
+    self.attributes.push(attribute);
+    self
+  }
+
+  /// 3D positions only.
+  pub fn position() -> Self {
+    Self::new(&[VertexAttribute::float(Attribute::Position, 3)])
+  }
+
+  /// 3D positions and texture coordinates.
+  pub fn position_tex_coords() -> Self {
+    Self::position().with(VertexAttribute::float(Attribute::TexCoord0, 2))
+  }
+
+  /// 3D positions, normals and texture coordinates; the layout of
+  /// `mesh::Vertex` and of the lit built-in primitives.
+  pub fn position_normal_tex_coords() -> Self {
+    Self::position()
+      .with(VertexAttribute::float(Attribute::Normal, 3))
+      .with(VertexAttribute::float(Attribute::TexCoord0, 2))
+  }
+
+  pub fn attributes(&self) -> &[VertexAttribute] {
+    &self.attributes
+  }
+
+  pub fn has(&self, attribute: Attribute) -> bool {
+    self.attributes.iter().any(|a| a.attribute == attribute)
+  }
+
+  /// Size of one vertex in bytes.
+  pub fn stride(&self) -> usize {
+    self.attributes.iter().map(VertexAttribute::size).sum()
+  }
+
+  /// Size of one vertex in `f32`s, for layouts stored in float arrays.
+  pub fn float_stride(&self) -> usize {
+    self.stride() / mem::size_of::<f32>()
+  }
+
+  /// Byte offset of an attribute within a vertex.
+  pub fn offset_of(&self, attribute: Attribute) -> Option<usize> {
+    self.offsets().find(|(a, _)| a.attribute == attribute).map(|(_, offset)| offset)
+  }
+
+  /// Every attribute together with its byte offset.
+  pub fn offsets(&self) -> impl Iterator<Item = (&VertexAttribute, usize)> + '_ {
+    self.attributes.iter().scan(0, |offset, a| {
+      let current = *offset;
+      *offset += a.size();
+      Some((a, current))
+    })
+  }
+}
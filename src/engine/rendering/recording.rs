@@ -0,0 +1,173 @@
+use crate::engine::error::Result;
+
+use super::device::*;
+use super::VertexLayout;
+
+/// Id of a resource created through a `RecordingDevice`. Ids start at 1 and
+/// are never reused, so they can be compared across commands.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle(pub u32);
+
+/// One call made on a `RecordingDevice`, with the arguments it was given.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+  CreateBuffer { buffer: Handle, kind: BufferKind, size: usize },
+  CreateVertexLayout {
+    layout: Handle,
+    vertex_buffer: Handle,
+    index_buffer: Option<Handle>,
+    vertex_layout: VertexLayout,
+  },
+  CreateTexture { texture: Handle, desc: TextureDesc },
+  CreateCompressedTexture { texture: Handle, desc: TextureDesc, levels: usize },
+  CreateDepthTarget { target: Handle, texture: Handle, layer: u32 },
+  BeginDepthPass { target: Handle },
+  EndDepthPass { target: Handle },
+  CreatePipeline { pipeline: Handle, vertex_shader: String, fragment_shader: String },
+  UsePipeline { pipeline: Handle },
+  SetUniform { pipeline: Handle, name: String, value: Uniform },
+  BindTexture { unit: u32, texture: Handle, kind: TextureKind },
+  SetDepthFunc { func: DepthFunc },
+  SetReverseZ { enabled: bool },
+  Draw { layout: Handle, call: DrawCall },
+}
+
+/// A device that doesn't render anything. It hands out fresh ids for every
+/// resource and records each call, so draw submissions can be inspected in
+/// tests or when no GPU is available.
+#[derive(Debug, Default)]
+pub struct RecordingDevice {
+  commands: Vec<Command>,
+  next_handle: u32,
+}
+
+impl RecordingDevice {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn commands(&self) -> &[Command] {
+    &self.commands
+  }
+
+  /// Only the draw calls, in submission order.
+  pub fn draws(&self) -> Vec<(Handle, DrawCall)> {
+    self
+      .commands
+      .iter()
+      .filter_map(|c| match c {
+        Command::Draw { layout, call } => Some((*layout, *call)),
+        _ => None,
+      })
+      .collect()
+  }
+
+  /// The last value a uniform was set to on the given pipeline.
+  pub fn uniform(&self, pipeline: Handle, name: &str) -> Option<Uniform> {
+    self.commands.iter().rev().find_map(|c| match c {
+      Command::SetUniform { pipeline: p, name: n, value } if *p == pipeline && n == name => {
+        Some(*value)
+      }
+      _ => None,
+    })
+  }
+
+  pub fn clear(&mut self) -> () {
+    self.commands.clear();
+  }
+
+  fn next(&mut self) -> Handle {
+    self.next_handle += 1;
+    Handle(self.next_handle)
+  }
+}
+
+impl RenderDevice for RecordingDevice {
+  type Buffer = Handle;
+  type InputLayout = Handle;
+  type Texture = Handle;
+  type Pipeline = Handle;
+  type DepthTarget = Handle;
+
+  fn create_buffer(&mut self, kind: BufferKind, data: &[u8]) -> Handle {
+    let buffer = self.next();
+    self.commands.push(Command::CreateBuffer { buffer, kind, size: data.len() });
+    buffer
+  }
+
+  fn create_vertex_layout(
+    &mut self,
+    vertex_buffer: &Handle,
+    index_buffer: Option<&Handle>,
+    vertex_layout: &VertexLayout,
+  ) -> Handle {
+    let layout = self.next();
+    self.commands.push(Command::CreateVertexLayout {
+      layout,
+      vertex_buffer: *vertex_buffer,
+      index_buffer: index_buffer.copied(),
+      vertex_layout: vertex_layout.clone(),
+    });
+    layout
+  }
+
+  fn create_texture(&mut self, desc: &TextureDesc, _layers: &[&[u8]]) -> Handle {
+    let texture = self.next();
+    self.commands.push(Command::CreateTexture { texture, desc: *desc });
+    texture
+  }
+
+  fn create_compressed_texture(&mut self, desc: &TextureDesc, levels: &[&[u8]]) -> Handle {
+    let texture = self.next();
+    self.commands.push(Command::CreateCompressedTexture { texture, desc: *desc, levels: levels.len() });
+    texture
+  }
+
+  fn create_depth_target(&mut self, texture: &Handle, _desc: &TextureDesc, layer: u32) -> Result<Handle> {
+    let target = self.next();
+    self.commands.push(Command::CreateDepthTarget { target, texture: *texture, layer });
+    Ok(target)
+  }
+
+  fn begin_depth_pass(&mut self, target: &Handle) {
+    self.commands.push(Command::BeginDepthPass { target: *target });
+  }
+
+  fn end_depth_pass(&mut self, target: &Handle) {
+    self.commands.push(Command::EndDepthPass { target: *target });
+  }
+
+  fn create_pipeline(&mut self, vertex_shader: &str, fragment_shader: &str) -> Result<Handle> {
+    let pipeline = self.next();
+    self.commands.push(Command::CreatePipeline {
+      pipeline,
+      vertex_shader: vertex_shader.into(),
+      fragment_shader: fragment_shader.into(),
+    });
+    Ok(pipeline)
+  }
+
+  fn use_pipeline(&mut self, pipeline: &Handle) {
+    self.commands.push(Command::UsePipeline { pipeline: *pipeline });
+  }
+
+  fn set_uniform(&mut self, pipeline: &Handle, name: &str, value: Uniform) {
+    self.commands.push(Command::SetUniform { pipeline: *pipeline, name: name.into(), value });
+  }
+
+  fn bind_texture(&mut self, unit: u32, texture: &Handle, kind: TextureKind) {
+    self.commands.push(Command::BindTexture { unit, texture: *texture, kind });
+  }
+
+  fn set_depth_func(&mut self, func: DepthFunc) {
+    self.commands.push(Command::SetDepthFunc { func });
+  }
+
+  fn set_reverse_z(&mut self, enabled: bool) {
+    self.commands.push(Command::SetReverseZ { enabled });
+  }
+
+  fn draw(&mut self, layout: &Handle, call: DrawCall) {
+    self.commands.push(Command::Draw { layout: *layout, call });
+  }
+}
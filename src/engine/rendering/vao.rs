@@ -1,4 +1,5 @@
-#[derive(Clone)]
+/// Owns an OpenGL vertex array object. The name is released on drop, so a
+/// `VAO` can't be cloned; wrap it in an `Rc` if it needs to be shared.
 pub struct VAO {
   id: u32,
 }
@@ -12,6 +13,10 @@ impl VAO {
     VAO { id }
   }
 
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
   pub unsafe fn bind(&self) {
     gl::BindVertexArray(self.id);
   }
@@ -19,8 +24,12 @@ impl VAO {
   pub unsafe fn unbind() {
     gl::BindVertexArray(0);
   }
+}
 
-  pub unsafe fn delete(&self) {
-    gl::DeleteVertexArrays(1, &self.id);
+impl Drop for VAO {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteVertexArrays(1, &self.id);
+    }
   }
 }
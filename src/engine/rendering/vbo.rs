@@ -1,6 +1,7 @@
 use std::mem;
 
-#[derive(Clone)]
+/// Owns an OpenGL array buffer. The name is released on drop, so a `VBO`
+/// can't be cloned; wrap it in an `Rc` if it needs to be shared.
 pub struct VBO {
   id: u32,
 }
@@ -14,6 +15,10 @@ impl VBO {
     VBO { id }
   }
 
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
   pub unsafe fn bind(&self) {
     gl::BindBuffer(gl::ARRAY_BUFFER, self.id);
   }
@@ -32,8 +37,12 @@ impl VBO {
       gl::STATIC_DRAW,
     );
   }
+}
 
-  pub unsafe fn delete(&self) {
-    gl::DeleteBuffers(1, &self.id);
+impl Drop for VBO {
+  fn drop(&mut self) {
+    unsafe {
+      gl::DeleteBuffers(1, &self.id);
+    }
   }
 }
@@ -1,28 +1,854 @@
-use cgmath::vec3;
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Matrix, SquareMatrix, Transform as _};
 
-use super::{object::Object, types::Vec3, Texture};
+use super::light::{Light, LightSelection, MAX_POINT_LIGHTS};
+use super::material::{fallback_texture_on, Material, ShadingModel};
+use super::math::{Aabb, Frustum, Ray, Sphere};
+use super::model::Model;
+use super::rendering::{DepthFunc, GlDevice, RenderDevice, Uniform};
+use super::shadow::{
+  disable_shadow_on, set_shadow_units_on, ShadowMap, DIRECTIONAL_SHADOW_UNIT, POINT_SHADOW_UNIT, SPOT_SHADOW_UNIT,
+};
+use super::skybox::Skybox;
+use super::{object::Object, types::{Mat4, Point3, Vec3}, Camera, Shader, Transform};
+
+pub mod format;
+pub use format::*;
+
+/// Identifies a node of a `Scene`. Ids stay valid until the node is removed
+/// and are never handed out again afterwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+/// A point in the scene hierarchy. Its transform is relative to its parent;
+/// the world matrix is cached and only recomputed after the node or one of
+/// its ancestors has moved.
+pub struct Node<D: RenderDevice = GlDevice> {
+  pub name: String,
+  pub object: Option<Object<D>>,
+  pub model: Option<Model<D>>,
+  /// What `object` or `model` was loaded from. Only nodes that have one (or
+  /// hold nothing) can be saved to a scene file.
+  pub asset: Option<Asset>,
+  transform: Transform,
+  world_matrix: Mat4,
+  dirty: bool,
+  parent: Option<NodeId>,
+  children: Vec<NodeId>,
+}
+
+impl<D: RenderDevice> Node<D> {
+  pub fn transform(&self) -> &Transform {
+    &self.transform
+  }
+
+  pub fn parent(&self) -> Option<NodeId> {
+    self.parent
+  }
+
+  pub fn children(&self) -> &[NodeId] {
+    &self.children
+  }
+}
 
 /// A scene compasses all game objects in it, as well as settings
 /// such as skybox/bg color, general lighting and physics (todo).
-/// 
-/// It is a scene's responsibility to handle its children and 
+///
+/// It is a scene's responsibility to handle its children and
 /// render all of its contents.
-/// 
+///
+/// Objects live in a node hierarchy: attach a weapon node to a hand node and
+/// it follows the hand around. Nodes without an object work as pivots.
+///
+/// Scenes can be saved to and loaded from text files, see `format`.
+///
 /// TODO: It is possible to load multiple scenes at once.
-pub struct Scene {
-  pub objects: Vec<Object>,
-  pub has_skybox: bool,
-  pub skybox_tex: Option<Texture>,
-  pub bg_color: Vec3
+pub struct Scene<D: RenderDevice = GlDevice> {
+  nodes: Vec<Option<Node<D>>>,
+  roots: Vec<NodeId>,
+  /// Shader the nodes are drawn with by `draw`.
+  pub shader: Option<ShaderAsset<D>>,
+  /// Shader for nodes with PBR materials. Without one they are drawn with
+  /// `shader`, as a Phong material that looks about the same; without
+  /// `shader` it draws Phong materials the same way the other way round.
+  pub pbr_shader: Option<ShaderAsset<D>>,
+  /// Shader shadow maps are drawn with. Without one no shadows are drawn,
+  /// whatever the lights' settings.
+  pub shadow_shader: Option<ShaderAsset<D>>,
+  pub skybox: Option<SkyboxAsset<D>>,
+  pub bg_color: Vec3,
+  /// Every light in the scene. Each object is drawn with the ones closest
+  /// to it, as many as the shader takes (see `LightSelection`). Scenes
+  /// without lights leave the light uniforms alone.
+  pub lights: Vec<Light>,
+  /// Whether `draw` skips what is outside the camera's view. On by default.
+  pub culling: bool,
+  stats: RenderStats,
+  // One for each light, for the ones that cast shadows. Kept between
+  // frames.
+  shadow_maps: Vec<Option<ShadowMap<D>>>,
+  // Bound in place of missing material maps. Made on the first draw, as
+  // that's when the scene gets a device.
+  fallback_texture: Option<D::Texture>
+}
+
+/// What the last `draw` (or `render`) of a scene sent to the GPU. Meshes
+/// are only counted for models that weren't culled as a whole. A model
+/// with both Phong and PBR meshes counts once for each shader it's drawn
+/// with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RenderStats {
+  pub objects_drawn: u32,
+  pub objects_culled: u32,
+  pub meshes_drawn: u32,
+  pub meshes_culled: u32,
+}
+
+impl std::ops::AddAssign for RenderStats {
+  fn add_assign(&mut self, other: Self) -> () {
+    self.objects_drawn += other.objects_drawn;
+    self.objects_culled += other.objects_culled;
+    self.meshes_drawn += other.meshes_drawn;
+    self.meshes_culled += other.meshes_culled;
+  }
+}
+
+// Which materials a shader pass draws: only those of its own shading
+// model, or all of them with the others approximated.
+#[derive(Clone, Copy)]
+enum Pass {
+  Only(ShadingModel),
+  All(ShadingModel),
+}
+
+/// Where a ray cast into a scene first hit something.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RaycastHit {
+  pub node: NodeId,
+  /// Which mesh of the node's model was hit, or `None` for its object.
+  pub mesh: Option<usize>,
+  /// Distance along the ray.
+  pub distance: f32,
+  pub position: Point3,
+  /// Normal of the triangle that was hit, facing back along the ray.
+  pub normal: Vec3,
+}
+
+/// A shader program together with the files it was built from.
+pub struct ShaderAsset<D: RenderDevice = GlDevice> {
+  pub source: ShaderSource,
+  pub pipeline: D::Pipeline,
+}
+
+/// A skybox together with the cubemap faces it was built from.
+pub struct SkyboxAsset<D: RenderDevice = GlDevice> {
+  /// +X, -X, +Y, -Y, +Z, -Z.
+  pub faces: Vec<String>,
+  pub shader: ShaderAsset<D>,
+  pub skybox: Skybox<D>,
 }
 
 impl Scene {
   pub fn new() -> Self {
+    Self::empty()
+  }
+
+  /// Updates the world matrices and draws every object with the given
+  /// (already activated) shader.
+  pub fn render(&mut self, shader: &mut Shader) -> () {
+    self.render_on(&mut GlDevice, shader);
+  }
+
+  /// Draws the nodes with the scene's own shader, then the skybox.
+  pub fn draw(&mut self, camera: &Camera) -> () {
+    self.draw_on(&mut GlDevice, camera);
+  }
+}
+
+impl<D: RenderDevice> Scene<D> {
+  fn empty() -> Self {
     Scene {
-      objects: [].to_vec(),
-      has_skybox: false,
-      skybox_tex: None,
-      bg_color: vec3(0.5, 0.5, 0.5)
+      nodes: Vec::new(),
+      roots: Vec::new(),
+      shader: None,
+      pbr_shader: None,
+      shadow_shader: None,
+      skybox: None,
+      bg_color: vec3(0.5, 0.5, 0.5),
+      lights: Vec::new(),
+      culling: true,
+      stats: RenderStats::default(),
+      shadow_maps: Vec::new(),
+      fallback_texture: None
+    }
+  }
+
+  /// Adds an empty node, e.g. a pivot other nodes can be attached to.
+  pub fn add_node(&mut self, name: &str, transform: Transform, parent: Option<NodeId>) -> NodeId {
+    let id = NodeId(self.nodes.len());
+    self.nodes.push(Some(Node {
+      name: name.into(),
+      object: None,
+      model: None,
+      asset: None,
+      transform,
+      world_matrix: Mat4::identity(),
+      dirty: true,
+      parent: None,
+      children: Vec::new(),
+    }));
+    self.roots.push(id);
+
+    if parent.is_some() {
+      self.set_parent(id, parent);
+    }
+
+    id
+  }
+
+  /// Adds a node holding `object`. The object's own transform is kept as an
+  /// offset inside the node.
+  pub fn add_object(&mut self, name: &str, object: Object<D>, parent: Option<NodeId>) -> NodeId {
+    let id = self.add_node(name, Transform::default(), parent);
+    self.node_mut(id).object = Some(object);
+    id
+  }
+
+  /// Removes a node together with all of its descendants.
+  pub fn remove(&mut self, id: NodeId) -> () {
+    self.set_parent(id, None);
+    self.roots.retain(|&root| root != id);
+
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+      if let Some(node) = self.nodes[id.0].take() {
+        stack.extend(node.children);
+      }
+    }
+  }
+
+  pub fn contains(&self, id: NodeId) -> bool {
+    self.nodes.get(id.0).is_some_and(Option::is_some)
+  }
+
+  pub fn node(&self, id: NodeId) -> &Node<D> {
+    self.nodes[id.0].as_ref().expect("Node has been removed from the scene!")
+  }
+
+  pub fn node_mut(&mut self, id: NodeId) -> &mut Node<D> {
+    self.nodes[id.0].as_mut().expect("Node has been removed from the scene!")
+  }
+
+  pub fn find(&self, name: &str) -> Option<NodeId> {
+    self.ids().find(|&id| self.node(id).name == name)
+  }
+
+  /// Ids of every node, in insertion order.
+  pub fn ids(&self) -> impl Iterator<Item = NodeId> + '_ {
+    self.nodes.iter().enumerate().filter(|(_, n)| n.is_some()).map(|(i, _)| NodeId(i))
+  }
+
+  pub fn roots(&self) -> &[NodeId] {
+    &self.roots
+  }
+
+  pub fn objects(&self) -> impl Iterator<Item = &Object<D>> {
+    self.nodes.iter().flatten().filter_map(|n| n.object.as_ref())
+  }
+
+  pub fn transform(&self, id: NodeId) -> &Transform {
+    &self.node(id).transform
+  }
+
+  pub fn set_transform(&mut self, id: NodeId, transform: Transform) -> () {
+    self.node_mut(id).transform = transform;
+    self.mark_dirty(id);
+  }
+
+  /// Edits a node's transform in place. The node is flagged as moved even if
+  /// the closure ends up changing nothing.
+  pub fn update_transform<F: FnOnce(&mut Transform)>(&mut self, id: NodeId, f: F) -> () {
+    f(&mut self.node_mut(id).transform);
+    self.mark_dirty(id);
+  }
+
+  /// Moves `child` under `parent`, or to the top level with `None`. The
+  /// child keeps its local transform, so it may jump in world space.
+  ///
+  /// Returns false (and changes nothing) if `parent` is the child itself or
+  /// one of its descendants, as that would create a cycle.
+  pub fn set_parent(&mut self, child: NodeId, parent: Option<NodeId>) -> bool {
+    if let Some(parent) = parent {
+      if self.is_ancestor_or_self(child, parent) {
+        return false;
+      }
+    }
+
+    match self.node(child).parent {
+      Some(old) => self.node_mut(old).children.retain(|&c| c != child),
+      None => self.roots.retain(|&r| r != child),
+    }
+
+    match parent {
+      Some(parent) => self.node_mut(parent).children.push(child),
+      None => self.roots.push(child),
+    }
+    self.node_mut(child).parent = parent;
+    self.mark_dirty(child);
+
+    true
+  }
+
+  fn is_ancestor_or_self(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+    loop {
+      if id == ancestor {
+        return true;
+      }
+      match self.node(id).parent {
+        Some(parent) => id = parent,
+        None => return false,
+      }
+    }
+  }
+
+  // Flags the node and its whole subtree. A dirty node never has a clean
+  // descendant, so already dirty subtrees can be skipped.
+  fn mark_dirty(&mut self, id: NodeId) -> () {
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+      let node = self.node_mut(id);
+      if node.dirty {
+        continue;
+      }
+      node.dirty = true;
+      stack.extend(node.children.iter().copied());
+    }
+  }
+
+  /// The node's world matrix, recomputing it (and any stale ancestors) first.
+  pub fn world_matrix(&mut self, id: NodeId) -> Mat4 {
+    if self.node(id).dirty {
+      let parent = match self.node(id).parent {
+        Some(parent) => self.world_matrix(parent),
+        None => Mat4::identity(),
+      };
+      let node = self.node_mut(id);
+      node.world_matrix = parent * node.transform.matrix();
+      node.dirty = false;
+    }
+    self.node(id).world_matrix
+  }
+
+  /// Recomputes the world matrix of every node that has moved.
+  pub fn update_world_matrices(&mut self) -> () {
+    let mut stack: Vec<(NodeId, Mat4, bool)> =
+      self.roots.iter().rev().map(|&id| (id, Mat4::identity(), false)).collect();
+
+    while let Some((id, parent, parent_changed)) = stack.pop() {
+      let node = self.node_mut(id);
+      let changed = node.dirty || parent_changed;
+      if changed {
+        node.world_matrix = parent * node.transform.matrix();
+        node.dirty = false;
+      }
+      let world = node.world_matrix;
+      stack.extend(node.children.iter().rev().map(|&c| (c, world, changed)));
+    }
+  }
+
+  /// Visits nodes depth-first, parents before their children.
+  pub fn traverse<F: FnMut(NodeId, &Node<D>)>(&self, mut f: F) -> () {
+    let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+    while let Some(id) = stack.pop() {
+      let node = self.node(id);
+      f(id, node);
+      stack.extend(node.children.iter().rev());
+    }
+  }
+
+  /// The nearest object or model triangle the ray hits, e.g. under the
+  /// cursor with `Camera::screen_to_ray`. Triangles are hit from both sides.
+  pub fn raycast(&mut self, ray: &Ray) -> Option<RaycastHit> {
+    self.update_world_matrices();
+
+    let mut nearest: Option<RaycastHit> = None;
+    self.traverse(|id, node| {
+      let mut test = |matrix: Mat4, bounds, triangles: &mut dyn Iterator<Item = [Point3; 3]>, mesh| {
+        let max_distance = nearest.map_or(f32::INFINITY, |hit| hit.distance);
+        if let Some((distance, normal)) = raycast_triangles(ray, &matrix, bounds, triangles, max_distance) {
+          nearest = Some(RaycastHit { node: id, mesh, distance, position: ray.at(distance), normal });
+        }
+      };
+
+      if let Some(object) = &node.object {
+        let matrix = node.world_matrix * object.model_matrix();
+        test(matrix, object.bounds, &mut object.vertex_array.triangles(), None);
+      }
+      if let Some(model) = &node.model {
+        for (i, mesh) in model.meshes.iter().enumerate() {
+          test(node.world_matrix, mesh.bounds(), &mut mesh.triangles(), Some(i));
+        }
+      }
+    });
+
+    nearest
+  }
+
+  pub fn render_stats(&self) -> RenderStats {
+    self.stats
+  }
+
+  pub fn render_on(&mut self, device: &mut D, shader: &D::Pipeline) -> () {
+    self.update_world_matrices();
+    self.fallback_texture.get_or_insert_with(|| fallback_texture_on(device));
+    self.stats = self.draw_nodes(device, shader, None, Pass::All(ShadingModel::Phong));
+  }
+
+  pub fn draw_on(&mut self, device: &mut D, camera: &Camera) -> () {
+    self.update_world_matrices();
+    self.fallback_texture.get_or_insert_with(|| fallback_texture_on(device));
+    self.render_shadow_maps(device, camera);
+    device.set_depth_func(camera.depth_func());
+
+    let frustum = self.culling.then(|| camera.frustum());
+    // With both shaders each draws its own materials; a single one draws
+    // every material, approximating those of the other kind.
+    let pass = match (&self.shader, &self.pbr_shader) {
+      (Some(_), Some(_)) => Pass::Only,
+      _ => Pass::All,
+    };
+    let main_pass = self.shader.as_ref().map(|shader| (shader, pass(ShadingModel::Phong)));
+    let pbr_pass = self.pbr_shader.as_ref().map(|shader| (shader, pass(ShadingModel::Pbr)));
+    let mut stats = RenderStats::default();
+    for (shader, pass) in main_pass.into_iter().chain(pbr_pass) {
+      let pipeline = &shader.pipeline;
+      device.use_pipeline(pipeline);
+      device.set_uniform(pipeline, "cameraPos", Uniform::Vec3(camera.get_pos().to_vec()));
+      device.set_uniform(pipeline, "viewPos", Uniform::Vec3(camera.get_pos().to_vec()));
+      device.set_uniform(pipeline, "view", Uniform::Mat4(camera.get_view_matrix()));
+      device.set_uniform(pipeline, "projection", Uniform::Mat4(camera.get_projection_matrix()));
+      stats += self.draw_nodes(device, pipeline, frustum.as_ref(), pass);
+    }
+    self.stats = stats;
+
+    if let Some(skybox) = &self.skybox {
+      skybox.skybox.draw_on(device, &skybox.shader.pipeline, camera);
     }
   }
-}
\ No newline at end of file
+
+  // Draws a map for every light with shadow settings. Maps are only made
+  // again when a light's kind or resolution changes, and dropped when
+  // nothing casts shadows any more.
+  fn render_shadow_maps(&mut self, device: &mut D, camera: &Camera) -> () {
+    let mut maps = std::mem::take(&mut self.shadow_maps);
+    let shader = match &self.shadow_shader {
+      Some(shader) if self.lights.iter().any(|light| light.shadows().is_some()) => &shader.pipeline,
+      _ => return,
+    };
+    maps.resize_with(self.lights.len(), || None);
+    let reverse_z = camera.get_projection().is_reverse_z();
+
+    // Shadow maps use the usual depth range, whatever the camera does.
+    if reverse_z {
+      device.set_reverse_z(false);
+    }
+    device.set_depth_func(DepthFunc::Less);
+
+    let casters = self.caster_bounds().unwrap_or(Sphere::new(Point3::origin(), 1.0));
+    for (light, slot) in self.lights.iter().zip(maps.iter_mut()) {
+      let Some(settings) = light.shadows() else {
+        *slot = None;
+        continue;
+      };
+      let kind = ShadowMap::<D>::kind_for(light);
+      let map = match slot {
+        Some(map) if map.fits(kind, &settings) => map,
+        _ => match ShadowMap::new_on(device, kind, settings) {
+          Ok(map) => slot.insert(map),
+          // A resolution the GPU can't render at; the light shines
+          // without shadows instead.
+          Err(_) => {
+            *slot = None;
+            continue;
+          }
+        },
+      };
+      map.settings = settings;
+      map.aim(light, casters, camera);
+      map.render_on(device, shader, |device, frustum| self.draw_casters(device, shader, frustum));
+    }
+
+    if reverse_z {
+      device.set_reverse_z(true);
+    }
+    self.shadow_maps = maps;
+  }
+
+  // Sphere around everything that casts shadows.
+  fn caster_bounds(&self) -> Option<Sphere> {
+    let mut bounds: Option<Aabb> = None;
+    let mut grow = |aabb: Option<Aabb>, matrix: &Mat4| {
+      if let Some(aabb) = aabb.map(|aabb| aabb.transform(matrix)) {
+        bounds = Some(bounds.map_or(aabb, |bounds| bounds.union(&aabb)));
+      }
+    };
+    self.traverse(|_, node| {
+      if let Some(object) = node.object.as_ref().filter(|object| object.cast_shadows) {
+        grow(object.bounds, &(node.world_matrix * object.model_matrix()));
+      }
+      if let Some(model) = node.model.as_ref().filter(|model| model.cast_shadows) {
+        grow(model.bounds(), &node.world_matrix);
+      }
+    });
+    bounds.map(|bounds| Sphere::from_aabb(&bounds))
+  }
+
+  // Draws the geometry of every shadow caster in the frustum, setting only
+  // the model matrix.
+  fn draw_casters(&self, device: &mut D, shader: &D::Pipeline, frustum: &Frustum) -> () {
+    let visible = |bounds: Option<Aabb>, matrix: &Mat4| match bounds {
+      Some(bounds) => is_visible(frustum, &bounds, matrix),
+      None => true,
+    };
+    self.traverse(|_, node| {
+      if let Some(object) = node.object.as_ref().filter(|object| object.cast_shadows) {
+        if visible(object.bounds, &(node.world_matrix * object.model_matrix())) {
+          object.draw_with_parent_on(device, shader, node.world_matrix);
+        }
+      }
+      if let Some(model) = node.model.as_ref().filter(|model| model.cast_shadows) {
+        if visible(model.bounds(), &node.world_matrix) {
+          device.set_uniform(shader, "model", Uniform::Mat4(node.world_matrix));
+          for mesh in model.meshes.iter().filter(|mesh| visible(mesh.bounds(), &node.world_matrix)) {
+            mesh.draw_geometry_on(device);
+          }
+        }
+      }
+    });
+  }
+
+  // Uploads the shadows of the selected lights, in the same slots as the
+  // lights themselves.
+  fn set_shadow_uniforms(&self, device: &mut D, shader: &D::Pipeline, selection: &LightSelection) -> () {
+    let map = |index: Option<usize>| index.and_then(|i| self.shadow_maps.get(i)?.as_ref());
+    let mut set = |index: Option<usize>, name: &str, unit: u32| match map(index) {
+      Some(map) => map.set_uniforms_on(device, shader, name, unit),
+      None => disable_shadow_on(device, shader, name),
+    };
+    set(selection.directional, "dirShadow", DIRECTIONAL_SHADOW_UNIT);
+    set(selection.spot, "spotShadow", SPOT_SHADOW_UNIT);
+    for slot in 0..MAX_POINT_LIGHTS {
+      set(selection.points.get(slot).copied(), &format!("pointShadows[{}]", slot), POINT_SHADOW_UNIT + slot as u32);
+    }
+    if let Some(map) = map(selection.directional) {
+      map.set_cascade_uniforms_on(device, shader, "dirCascades");
+    }
+  }
+
+  // Expects the world matrices to be up to date and the fallback texture to
+  // be made. Without a frustum everything is drawn.
+  fn draw_nodes(&self, device: &mut D, shader: &D::Pipeline, frustum: Option<&Frustum>, pass: Pass) -> RenderStats {
+    let fallback = self.fallback_texture.as_ref().expect("the fallback texture is made before drawing");
+    let mut stats = RenderStats::default();
+    set_shadow_units_on(device, shader);
+    let in_pass = |material: &Material<D>| match pass {
+      Pass::Only(model) => material.shading_model() == model,
+      Pass::All(_) => true,
+    };
+    let apply = |device: &mut D, material: &Material<D>| match pass {
+      Pass::Only(_) => material.apply_on(device, shader, fallback),
+      Pass::All(ShadingModel::Phong) => material.apply_as_phong_on(device, shader, fallback),
+      Pass::All(ShadingModel::Pbr) => material.apply_as_pbr_on(device, shader, fallback),
+    };
+    let visible = |bounds: Option<Aabb>, matrix: &Mat4| match (frustum, bounds) {
+      (Some(frustum), Some(bounds)) => is_visible(frustum, &bounds, matrix),
+      _ => true,
+    };
+
+    // Neighbouring objects usually get the same lights, so they are only
+    // uploaded when the selection changes.
+    let mut current_lights: Option<LightSelection> = None;
+    let mut use_lights = |device: &mut D, bounds: Option<Aabb>, matrix: &Mat4| {
+      if self.lights.is_empty() {
+        return;
+      }
+      let position = match bounds {
+        Some(bounds) => matrix.transform_point(bounds.center()),
+        None => Point3::from_vec(matrix.w.truncate()),
+      };
+      let selection = LightSelection::closest(&self.lights, position);
+      if current_lights.as_ref() != Some(&selection) {
+        selection.set_uniforms_on(&self.lights, device, shader);
+        self.set_shadow_uniforms(device, shader, &selection);
+        current_lights = Some(selection);
+      }
+    };
+
+    self.traverse(|_, node| {
+      if let Some(object) = node.object.as_ref().filter(|object| in_pass(&object.material)) {
+        let matrix = node.world_matrix * object.model_matrix();
+        if visible(object.bounds, &matrix) {
+          use_lights(device, object.bounds, &matrix);
+          apply(device, &object.material);
+          device.set_uniform(shader, "receiveShadows", Uniform::Bool(object.receive_shadows));
+          object.draw_with_parent_on(device, shader, node.world_matrix);
+          stats.objects_drawn += 1;
+        } else {
+          stats.objects_culled += 1;
+        }
+      }
+      if let Some(model) = &node.model {
+        if !model.meshes.iter().any(|mesh| in_pass(&mesh.material)) {
+          return;
+        }
+        if !visible(model.bounds(), &node.world_matrix) {
+          stats.objects_culled += 1;
+          return;
+        }
+        stats.objects_drawn += 1;
+        use_lights(device, model.bounds(), &node.world_matrix);
+        device.set_uniform(shader, "model", Uniform::Mat4(node.world_matrix));
+        device.set_uniform(shader, "receiveShadows", Uniform::Bool(model.receive_shadows));
+        for mesh in model.meshes.iter().filter(|mesh| in_pass(&mesh.material)) {
+          if visible(mesh.bounds(), &node.world_matrix) {
+            apply(device, &mesh.material);
+            mesh.draw_geometry_on(device);
+            stats.meshes_drawn += 1;
+          } else {
+            stats.meshes_culled += 1;
+          }
+        }
+      }
+    });
+
+    stats
+  }
+}
+
+// Nearest triangle closer than `max_distance`, with its world-space normal.
+// The triangles are in the space `matrix` takes to the world; the ray is
+// moved there instead of moving every triangle out.
+fn raycast_triangles(
+  ray: &Ray,
+  matrix: &Mat4,
+  bounds: Option<Aabb>,
+  triangles: &mut dyn Iterator<Item = [Point3; 3]>,
+  max_distance: f32,
+) -> Option<(f32, Vec3)> {
+  let entry = ray.intersect_aabb(&bounds?.transform(matrix))?;
+  if entry >= max_distance {
+    return None;
+  }
+
+  let inverse = matrix.invert()?;
+  let local = ray.transform(&inverse);
+  let mut nearest = None;
+  let mut max_distance = max_distance;
+  for [a, b, c] in triangles {
+    if let Some(distance) = local.intersect_triangle(a, b, c) {
+      if distance < max_distance {
+        max_distance = distance;
+        nearest = Some((distance, (b - a).cross(c - a)));
+      }
+    }
+  }
+
+  let (distance, local_normal) = nearest?;
+  // Normals go to world space with the inverse transpose, so non-uniform
+  // scaling doesn't tilt them.
+  let normal = (inverse.transpose() * local_normal.extend(0.0)).truncate().normalize();
+  Some((distance, if normal.dot(ray.direction) > 0.0 { -normal } else { normal }))
+}
+
+// Tests the cheap bounding sphere first and only then the tighter box.
+fn is_visible(frustum: &Frustum, bounds: &Aabb, matrix: &Mat4) -> bool {
+  frustum.intersects_sphere(&Sphere::from_aabb(bounds).transform(matrix))
+    && frustum.intersects_aabb(&bounds.transform(matrix))
+}
+
+impl<D: RenderDevice> Default for Scene<D> {
+  fn default() -> Self {
+    Self::empty()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::{point3, vec3, Deg, Rotation3};
+
+  use super::*;
+  use crate::data::Vertices;
+  use crate::engine::light::PointLight;
+  use crate::engine::types::Quat;
+  use crate::engine::rendering::{Command, Handle, RecordingDevice};
+
+  // A scene with the given shader and a cube at each position, drawn by a
+  // camera at (0, 0, 3) looking down -Z.
+  fn scene_with_cubes(device: &mut RecordingDevice, positions: &[Vec3]) -> (Scene<RecordingDevice>, Handle) {
+    let mut scene = Scene::default();
+    let pipeline = device.create_pipeline("test.vert", "test.frag").unwrap();
+    let source = ShaderSource { vertex: "test.vert".into(), fragment: "test.frag".into() };
+    scene.shader = Some(ShaderAsset { source, pipeline });
+    for (i, &position) in positions.iter().enumerate() {
+      let object = Object::with_material_on(device, Vertices::Cube.get_vertices(), Material::default(), position);
+      scene.add_object(&format!("cube{}", i), object, None);
+    }
+    (scene, pipeline)
+  }
+
+  fn camera() -> Camera {
+    Camera::new(point3(0.0, 0.0, 3.0))
+  }
+
+  // The value `name` had on `pipeline` when each draw was made.
+  fn uniform_at_draws(device: &RecordingDevice, pipeline: Handle, name: &str) -> Vec<Option<Uniform>> {
+    let mut current = None;
+    let mut values = Vec::new();
+    for command in device.commands() {
+      match command {
+        Command::SetUniform { pipeline: p, name: n, value } if *p == pipeline && n == name => current = Some(*value),
+        Command::Draw { .. } => values.push(current),
+        _ => (),
+      }
+    }
+    values
+  }
+
+  #[test]
+  fn draw_sets_camera_uniforms_then_draws_each_object() {
+    let mut device = RecordingDevice::new();
+    let (mut scene, pipeline) = scene_with_cubes(&mut device, &[vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0)]);
+    let camera = camera();
+    device.clear();
+
+    scene.draw_on(&mut device, &camera);
+
+    let commands = device.commands();
+    let use_pipeline = commands.iter().position(|c| *c == Command::UsePipeline { pipeline }).unwrap();
+    let first_draw = commands.iter().position(|c| matches!(c, Command::Draw { .. })).unwrap();
+    assert!(use_pipeline < first_draw);
+    assert!(commands[..use_pipeline].contains(&Command::SetDepthFunc { func: DepthFunc::Less }));
+    assert_eq!(device.uniform(pipeline, "view"), Some(Uniform::Mat4(camera.get_view_matrix())));
+    assert_eq!(device.uniform(pipeline, "projection"), Some(Uniform::Mat4(camera.get_projection_matrix())));
+
+    assert_eq!(device.draws().len(), 2);
+    let models = uniform_at_draws(&device, pipeline, "model");
+    assert_eq!(models, vec![
+      Some(Uniform::Mat4(Mat4::from_translation(vec3(0.0, 0.0, 0.0)))),
+      Some(Uniform::Mat4(Mat4::from_translation(vec3(1.0, 0.0, 0.0)))),
+    ]);
+  }
+
+  #[test]
+  fn culling_skips_objects_behind_the_camera() {
+    let mut device = RecordingDevice::new();
+    let (mut scene, _) = scene_with_cubes(&mut device, &[vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, 10.0)]);
+    device.clear();
+
+    scene.draw_on(&mut device, &camera());
+    assert_eq!(device.draws().len(), 1);
+    assert_eq!(scene.render_stats(), RenderStats { objects_drawn: 1, objects_culled: 1, ..Default::default() });
+
+    scene.culling = false;
+    device.clear();
+    scene.draw_on(&mut device, &camera());
+    assert_eq!(device.draws().len(), 2);
+    assert_eq!(scene.render_stats(), RenderStats { objects_drawn: 2, ..Default::default() });
+  }
+
+  #[test]
+  fn objects_get_the_point_lights_closest_to_them() {
+    let mut device = RecordingDevice::new();
+    let (mut scene, pipeline) = scene_with_cubes(&mut device, &[vec3(-20.0, 0.0, -20.0), vec3(20.0, 0.0, -20.0)]);
+    scene.culling = false;
+    // Five lights around each cube, the farthest of which is left out.
+    for (x, distance) in [-20.0, 20.0].into_iter().flat_map(|x| (1..=5).map(move |d| (x, d as f32))) {
+      let light = PointLight::new(point3(x + distance, 0.0, -20.0), vec3(1.0, 1.0, 1.0), 10.0);
+      scene.lights.push(Light::Point(light));
+    }
+    device.clear();
+
+    scene.draw_on(&mut device, &camera());
+
+    let position = |x: f32| Some(Uniform::Vec3(vec3(x, 0.0, -20.0)));
+    for (slot, distance) in (1..=MAX_POINT_LIGHTS).enumerate() {
+      let name = format!("pointLights[{}].position", slot);
+      let values = uniform_at_draws(&device, pipeline, &name);
+      assert_eq!(values, vec![position(-20.0 + distance as f32), position(20.0 + distance as f32)]);
+    }
+  }
+
+  // Root, child and grandchild, each one unit further along X.
+  fn chain() -> (Scene<RecordingDevice>, [NodeId; 3]) {
+    let mut scene = Scene::default();
+    let step = Transform::from_translation(vec3(1.0, 0.0, 0.0));
+    let root = scene.add_node("root", step, None);
+    let child = scene.add_node("child", step, Some(root));
+    let grandchild = scene.add_node("grandchild", step, Some(child));
+    (scene, [root, child, grandchild])
+  }
+
+  #[test]
+  fn moving_a_parent_moves_its_grandchildren() {
+    let (mut scene, [root, child, grandchild]) = chain();
+    assert_eq!(scene.world_matrix(grandchild), Mat4::from_translation(vec3(3.0, 0.0, 0.0)));
+
+    scene.set_transform(root, Transform::from_translation(vec3(0.0, 2.0, 0.0)));
+    // Bringing the child up to date first must not leave the grandchild
+    // behind.
+    assert_eq!(scene.world_matrix(child), Mat4::from_translation(vec3(1.0, 2.0, 0.0)));
+    assert_eq!(scene.world_matrix(grandchild), Mat4::from_translation(vec3(2.0, 2.0, 0.0)));
+
+    scene.update_transform(root, |transform| transform.translation.z = 5.0);
+    scene.update_world_matrices();
+    assert_eq!(scene.node(grandchild).world_matrix, Mat4::from_translation(vec3(2.0, 2.0, 5.0)));
+  }
+
+  #[test]
+  fn set_parent_rejects_cycles() {
+    let (mut scene, [root, child, grandchild]) = chain();
+    assert!(!scene.set_parent(root, Some(grandchild)));
+    assert!(!scene.set_parent(child, Some(child)));
+    assert_eq!(scene.node(root).parent, None);
+    assert_eq!(scene.node(child).parent, Some(root));
+    assert_eq!(scene.roots, vec![root]);
+
+    assert!(scene.set_parent(grandchild, Some(root)));
+    assert_eq!(scene.node(root).children, vec![child, grandchild]);
+    assert!(scene.node(child).children.is_empty());
+  }
+
+  #[test]
+  fn raycasts_hit_the_nearest_transformed_object() {
+    let mut device = RecordingDevice::new();
+    let (mut scene, _) = scene_with_cubes(&mut device, &[vec3(0.0, 0.0, 0.0), vec3(0.0, 0.0, -5.0)]);
+    let near = scene.find("cube0").unwrap();
+    // Doubled and turned so an edge points at the camera; the ray hits the
+    // face right of it, which now faces 45 degrees to the right.
+    let rotation = Quat::from_angle_y(Deg(45.0));
+    scene.set_transform(near, Transform { rotation, scale: vec3(2.0, 2.0, 2.0), ..Default::default() });
+
+    let hit = scene.raycast(&Ray::new(point3(0.1, 0.0, 3.0), vec3(0.0, 0.0, -1.0))).unwrap();
+    assert_eq!(hit.node, near);
+    assert_eq!(hit.mesh, None);
+    let expected_distance = 3.0 - (2.0_f32.sqrt() - 0.1);
+    assert!((hit.distance - expected_distance).abs() < 1e-4, "hit at {}", hit.distance);
+    assert!((hit.normal - vec3(1.0, 0.0, 1.0).normalize()).magnitude() < 1e-4, "normal {:?}", hit.normal);
+
+    assert_eq!(scene.raycast(&Ray::new(point3(5.0, 0.0, 3.0), vec3(0.0, 0.0, -1.0))), None);
+  }
+
+  #[test]
+  fn a_lone_pbr_shader_draws_phong_materials_too() {
+    let mut device = RecordingDevice::new();
+    let (mut scene, pipeline) = scene_with_cubes(&mut device, &[vec3(0.0, 0.0, 0.0)]);
+    scene.pbr_shader = scene.shader.take();
+    device.clear();
+
+    scene.draw_on(&mut device, &camera());
+
+    assert_eq!(device.draws().len(), 1);
+    let white = Some(Uniform::Vec3(vec3(1.0, 1.0, 1.0)));
+    assert_eq!(uniform_at_draws(&device, pipeline, "material.baseColor"), vec![white]);
+    assert_eq!(device.uniform(pipeline, "material.metallic"), Some(Uniform::Float(0.0)));
+  }
+}
@@ -0,0 +1,637 @@
+//! The text format scenes are saved in.
+//!
+//! Scene files are written in [RON](https://github.com/ron-rs/ron), or in
+//! JSON if the file name ends in `.json`. Both describe the same structure:
+//!
+//! ```ron
+//! (
+//!   background: (0.9, 0.9, 0.9),
+//!   shader: (vertex: "shaders/cube.vert", fragment: "shaders/cube.frag"),
+//!   pbr_shader: (vertex: "shaders/cube.vert", fragment: "shaders/pbr.frag"),
+//!   shadow_shader: (vertex: "shaders/shadow.vert", fragment: "shaders/shadow.frag"),
+//!   skybox: (
+//!     faces: [
+//!       "assets/images/skybox/right.jpg", "assets/images/skybox/left.jpg",
+//!       "assets/images/skybox/top.jpg", "assets/images/skybox/bottom.jpg",
+//!       "assets/images/skybox/front.jpg", "assets/images/skybox/back.jpg",
+//!     ],
+//!     shader: (vertex: "shaders/skybox.vert", fragment: "shaders/skybox.frag"),
+//!   ),
+//!   lights: [
+//!     Directional(direction: (-0.2, -1.0, -0.3), diffuse: (0.8, 0.8, 0.8), shadows: ()),
+//!     Point(position: (1.0, 2.0, 0.0), attenuation: (constant: 1.0, linear: 0.09, quadratic: 0.032)),
+//!   ],
+//!   nodes: [
+//!     (
+//!       name: "table",
+//!       transform: (translation: (0.0, 0.0, -2.0), rotation: (0.0, 45.0, 0.0)),
+//!       asset: Primitive(shape: Cube, texture: "assets/images/container.jpg"),
+//!       children: [
+//!         (name: "backpack", asset: Model(path: "assets/models/backpack/backpack.obj")),
+//!       ],
+//!     ),
+//!   ],
+//! )
+//! ```
+//!
+//! * `background` is an RGB color, each channel from 0 to 1.
+//! * `shader` is the vertex/fragment pair nodes are drawn with.
+//! * `pbr_shader` is the pair for nodes with PBR materials, e.g. models
+//!   whose MTL files use the PBR extension (`Pr`, `Pm`, `map_Pr`, ...).
+//!   With only one of the two shaders every node is drawn with it, and
+//!   materials of the other kind are approximated.
+//! * `shadow_shader` is the pair shadow maps are drawn with. Without it
+//!   nothing casts shadows.
+//! * `skybox` lists the cubemap faces in +X, -X, +Y, -Y, +Z, -Z order and
+//!   the shader pair the skybox is drawn with.
+//! * `lights` are `Directional`, `Point` or `Spot` lights. Colors default to
+//!   a dim white ambient and white diffuse and specular (no ambient for spot
+//!   lights), attenuation to a reach of about 50 units and spot cones to
+//!   12.5 and 17.5 degrees. Lights with `shadows` cast them, e.g.
+//!   `shadows: (resolution: 2048, bias: 0.005, pcf_radius: 1)`. Directional
+//!   lights can split theirs into cascades along the camera's view, e.g.
+//!   `shadows: (cascades: (count: 4, distance: 100.0))`.
+//! * `nodes` are the top-level nodes. A node has a `name`, a `transform`
+//!   relative to its parent, an optional `asset` and its `children`.
+//!   `cast_shadows` and `receive_shadows` turn shadows off for the asset.
+//! * `translation` is in world units, `rotation` is in degrees around the
+//!   X, Y and Z axes and `scale` is per axis. Missing parts of a transform
+//!   default to no translation, no rotation and a scale of 1.
+//! * An `asset` is either one of the built-in `Primitive` shapes (`Cube`,
+//!   `CubeWithoutNormals`, `Plane`, `FlatImage`) with a texture, or an OBJ
+//!   `Model` whose materials point to its textures. Both take
+//!   `texture_options` on how their textures are sampled and loaded, e.g.
+//!   `texture_options: (sampler: (min_filter: Nearest, mag_filter: Nearest), mipmaps: false)`
+//!   for pixel art. Without them a primitive's texture is flipped if it is
+//!   a PNG and clamped if it has alpha, and a model's are used as they are.
+//!   Textures can also be `.dds` or `.ktx2` files in a BCn format, which
+//!   are uploaded compressed with their own mip levels.
+//!
+//! Everything but the node names can be left out. Paths are relative to the
+//! working directory, like everywhere else in the engine. In RON, optional
+//! fields are written without `Some(...)`.
+
+use std::rc::Rc;
+
+use cgmath::{Deg, Euler, InnerSpace, Quaternion, Rad};
+use serde::{Deserialize, Serialize};
+
+use crate::data::Vertices;
+use crate::engine::error::{Error, Result};
+use crate::engine::light::{Attenuation, DirectionalLight, Light, PointLight, ShadowSettings, SpotLight};
+use crate::engine::model::Model;
+use crate::engine::object::Object;
+use crate::engine::rendering::{GlDevice, RenderDevice};
+use crate::engine::skybox::Skybox;
+use crate::engine::text_format;
+use crate::engine::texture::{load_texture, load_texture_with, TextureDescriptor};
+use crate::engine::types::Vec3;
+use crate::engine::Transform;
+
+use super::{NodeId, Scene, ShaderAsset, SkyboxAsset};
+
+/// Everything a scene file holds, before any of it is loaded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SceneDescription {
+  #[serde(default = "default_background")]
+  pub background: [f32; 3],
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub shader: Option<ShaderSource>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub pbr_shader: Option<ShaderSource>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub shadow_shader: Option<ShaderSource>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub skybox: Option<SkyboxDescription>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub lights: Vec<LightDescription>,
+  #[serde(default)]
+  pub nodes: Vec<NodeDescription>,
+}
+
+/// The vertex and fragment shader files of a shader program.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShaderSource {
+  pub vertex: String,
+  pub fragment: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkyboxDescription {
+  /// Six paths: +X, -X, +Y, -Y, +Z, -Z.
+  pub faces: Vec<String>,
+  pub shader: ShaderSource,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NodeDescription {
+  pub name: String,
+  #[serde(default)]
+  pub transform: TransformDescription,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub asset: Option<Asset>,
+  /// Whether the node's asset shows up in shadow maps.
+  #[serde(default = "yes", skip_serializing_if = "is_yes")]
+  pub cast_shadows: bool,
+  /// Whether shadows fall on the node's asset.
+  #[serde(default = "yes", skip_serializing_if = "is_yes")]
+  pub receive_shadows: bool,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub children: Vec<NodeDescription>,
+}
+
+/// A transform as people write it: the rotation is in Euler angles
+/// (degrees) rather than a quaternion.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TransformDescription {
+  #[serde(default)]
+  pub translation: [f32; 3],
+  #[serde(default)]
+  pub rotation: [f32; 3],
+  #[serde(default = "default_scale")]
+  pub scale: [f32; 3],
+}
+
+/// A light as written in a scene file.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightDescription {
+  Directional {
+    direction: [f32; 3],
+    #[serde(default = "default_ambient")]
+    ambient: [f32; 3],
+    #[serde(default = "white")]
+    diffuse: [f32; 3],
+    #[serde(default = "white")]
+    specular: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadows: Option<ShadowSettings>,
+  },
+  Point {
+    position: [f32; 3],
+    #[serde(default)]
+    attenuation: Attenuation,
+    #[serde(default = "default_ambient")]
+    ambient: [f32; 3],
+    #[serde(default = "white")]
+    diffuse: [f32; 3],
+    #[serde(default = "white")]
+    specular: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadows: Option<ShadowSettings>,
+  },
+  Spot {
+    position: [f32; 3],
+    direction: [f32; 3],
+    #[serde(default = "default_inner_angle")]
+    inner_angle: f32,
+    #[serde(default = "default_outer_angle")]
+    outer_angle: f32,
+    #[serde(default)]
+    attenuation: Attenuation,
+    #[serde(default)]
+    ambient: [f32; 3],
+    #[serde(default = "white")]
+    diffuse: [f32; 3],
+    #[serde(default = "white")]
+    specular: [f32; 3],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shadows: Option<ShadowSettings>,
+  },
+}
+
+/// What a node displays.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Asset {
+  Primitive {
+    shape: Primitive,
+    texture: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    texture_options: Option<TextureDescriptor>,
+  },
+  Model {
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    texture_options: Option<TextureDescriptor>,
+  },
+}
+
+/// Built-in shapes from `data::Vertices` that make sense as scene objects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Primitive {
+  Cube,
+  CubeWithoutNormals,
+  Plane,
+  FlatImage,
+}
+
+impl Primitive {
+  pub fn vertices(self) -> Vertices {
+    match self {
+      Primitive::Cube => Vertices::Cube,
+      Primitive::CubeWithoutNormals => Vertices::CubeWithoutNormals,
+      Primitive::Plane => Vertices::PlaneWithoutNormals,
+      Primitive::FlatImage => Vertices::FlatImage,
+    }
+  }
+}
+
+fn default_background() -> [f32; 3] {
+  [0.5, 0.5, 0.5]
+}
+
+fn default_scale() -> [f32; 3] {
+  [1.0, 1.0, 1.0]
+}
+
+fn yes() -> bool {
+  true
+}
+
+fn is_yes(value: &bool) -> bool {
+  *value
+}
+
+fn white() -> [f32; 3] {
+  [1.0, 1.0, 1.0]
+}
+
+fn default_ambient() -> [f32; 3] {
+  [0.05, 0.05, 0.05]
+}
+
+fn default_inner_angle() -> f32 {
+  12.5
+}
+
+fn default_outer_angle() -> f32 {
+  17.5
+}
+
+impl From<Light> for LightDescription {
+  fn from(light: Light) -> Self {
+    match light {
+      Light::Directional(light) => LightDescription::Directional {
+        direction: light.direction.into(),
+        ambient: light.ambient.into(),
+        diffuse: light.diffuse.into(),
+        specular: light.specular.into(),
+        shadows: light.shadows,
+      },
+      Light::Point(light) => LightDescription::Point {
+        position: light.position.into(),
+        attenuation: light.attenuation,
+        ambient: light.ambient.into(),
+        diffuse: light.diffuse.into(),
+        specular: light.specular.into(),
+        shadows: light.shadows,
+      },
+      Light::Spot(light) => LightDescription::Spot {
+        position: light.position.into(),
+        direction: light.direction.into(),
+        inner_angle: light.inner_angle,
+        outer_angle: light.outer_angle,
+        attenuation: light.attenuation,
+        ambient: light.ambient.into(),
+        diffuse: light.diffuse.into(),
+        specular: light.specular.into(),
+        shadows: light.shadows,
+      },
+    }
+  }
+}
+
+impl From<LightDescription> for Light {
+  fn from(description: LightDescription) -> Self {
+    match description {
+      LightDescription::Directional { direction, ambient, diffuse, specular, shadows } => {
+        Light::Directional(DirectionalLight {
+          direction: Vec3::from(direction).normalize(),
+          ambient: ambient.into(),
+          diffuse: diffuse.into(),
+          specular: specular.into(),
+          shadows,
+        })
+      }
+      LightDescription::Point { position, attenuation, ambient, diffuse, specular, shadows } => {
+        Light::Point(PointLight {
+          position: position.into(),
+          attenuation,
+          ambient: ambient.into(),
+          diffuse: diffuse.into(),
+          specular: specular.into(),
+          shadows,
+        })
+      }
+      LightDescription::Spot {
+        position, direction, inner_angle, outer_angle, attenuation, ambient, diffuse, specular, shadows,
+      } => Light::Spot(SpotLight {
+        position: position.into(),
+        direction: Vec3::from(direction).normalize(),
+        inner_angle,
+        outer_angle,
+        attenuation,
+        ambient: ambient.into(),
+        diffuse: diffuse.into(),
+        specular: specular.into(),
+        shadows,
+      }),
+    }
+  }
+}
+
+impl Default for TransformDescription {
+  fn default() -> Self {
+    Transform::default().into()
+  }
+}
+
+impl From<Transform> for TransformDescription {
+  fn from(transform: Transform) -> Self {
+    let euler = Euler::from(transform.rotation);
+    TransformDescription {
+      translation: transform.translation.into(),
+      rotation: [Deg::from(euler.x).0, Deg::from(euler.y).0, Deg::from(euler.z).0],
+      scale: transform.scale.into(),
+    }
+  }
+}
+
+impl From<TransformDescription> for Transform {
+  fn from(description: TransformDescription) -> Self {
+    let [x, y, z] = description.rotation;
+    let euler = Euler::new(Rad::from(Deg(x)), Rad::from(Deg(y)), Rad::from(Deg(z)));
+    Transform {
+      translation: description.translation.into(),
+      rotation: Quaternion::from(euler),
+      scale: description.scale.into(),
+    }
+  }
+}
+
+impl SceneDescription {
+  /// Reads a scene file without loading any of the assets it references.
+  pub fn read(path: &str) -> Result<Self> {
+    let description: SceneDescription = text_format::read(path)?;
+
+    if let Some(skybox) = &description.skybox {
+      if skybox.faces.len() != 6 {
+        let message = format!("a skybox needs 6 faces, found {}", skybox.faces.len());
+        return Err(Error::Format { path: path.into(), message });
+      }
+    }
+
+    Ok(description)
+  }
+
+  pub fn write(&self, path: &str) -> Result<()> {
+    text_format::write(path, self)
+  }
+}
+
+impl Scene {
+  /// Loads a scene file and every asset it references.
+  pub fn load(path: &str) -> Result<Self> {
+    Self::load_on(&mut GlDevice, path)
+  }
+}
+
+impl<D: RenderDevice> Scene<D> {
+  pub fn load_on(device: &mut D, path: &str) -> Result<Self> {
+    Self::from_description_on(device, &SceneDescription::read(path)?)
+  }
+
+  /// Builds a scene from a description, loading its assets. Textures used
+  /// by several primitives are only loaded once.
+  pub fn from_description_on(device: &mut D, description: &SceneDescription) -> Result<Self> {
+    let mut scene = Scene::empty();
+    scene.bg_color = Vec3::from(description.background);
+    scene.lights = description.lights.iter().map(|&light| light.into()).collect();
+
+    if let Some(source) = &description.shader {
+      scene.shader = Some(ShaderAsset::load_on(device, source)?);
+    }
+    if let Some(source) = &description.pbr_shader {
+      scene.pbr_shader = Some(ShaderAsset::load_on(device, source)?);
+    }
+    if let Some(source) = &description.shadow_shader {
+      scene.shadow_shader = Some(ShaderAsset::load_on(device, source)?);
+    }
+
+    if let Some(skybox) = &description.skybox {
+      let faces: Vec<&str> = skybox.faces.iter().map(String::as_str).collect();
+      scene.skybox = Some(SkyboxAsset {
+        faces: skybox.faces.clone(),
+        shader: ShaderAsset::load_on(device, &skybox.shader)?,
+        skybox: Skybox::new_on(device, Vertices::get_indexed_vertices(Vertices::Skybox), &faces)?,
+      });
+    }
+
+    // Textures shared by primitives, with the options they were loaded with.
+    let mut textures: Vec<(&str, Option<TextureDescriptor>, Rc<D::Texture>)> = Vec::new();
+    let mut stack: Vec<(&NodeDescription, Option<NodeId>)> =
+      description.nodes.iter().rev().map(|node| (node, None)).collect();
+
+    while let Some((node, parent)) = stack.pop() {
+      let id = scene.add_node(&node.name, node.transform.into(), parent);
+
+      match &node.asset {
+        Some(Asset::Primitive { shape, texture, texture_options }) => {
+          let loaded = textures.iter().find(|(path, options, _)| path == texture && options == texture_options);
+          let texture = match loaded {
+            Some((_, _, loaded)) => Rc::clone(loaded),
+            None => {
+              let loaded = Rc::new(match texture_options {
+                Some(options) => load_texture_with(device, texture, options)?,
+                None => load_texture(device, texture)?,
+              });
+              textures.push((texture, *texture_options, Rc::clone(&loaded)));
+              loaded
+            }
+          };
+          let vertex_array = Vertices::get_indexed_vertices(shape.vertices());
+          let mut object = Object::with_texture_on(device, vertex_array, texture, Vec3::new(0.0, 0.0, 0.0));
+          object.cast_shadows = node.cast_shadows;
+          object.receive_shadows = node.receive_shadows;
+          scene.node_mut(id).object = Some(object);
+        }
+        Some(Asset::Model { path, texture_options }) => {
+          let mut model = Model::with_descriptor_on(device, path, texture_options.unwrap_or_default())?;
+          model.cast_shadows = node.cast_shadows;
+          model.receive_shadows = node.receive_shadows;
+          scene.node_mut(id).model = Some(model);
+        }
+        None => {}
+      }
+      scene.node_mut(id).asset = node.asset.clone();
+
+      stack.extend(node.children.iter().rev().map(|child| (child, Some(id))));
+    }
+
+    Ok(scene)
+  }
+
+  /// Writes the scene to `path`, as JSON if it ends in `.json` and as RON
+  /// otherwise.
+  ///
+  /// Only what can be written down is saved: node transforms, the assets
+  /// nodes were loaded from and the scene settings. An object's own
+  /// transform offset isn't part of the file.
+  pub fn save(&self, path: &str) -> Result<()> {
+    self
+      .to_description()
+      .map_err(|message| Error::Format { path: path.into(), message })?
+      .write(path)
+  }
+
+  /// Describes the scene for saving. Fails if a node holds an object or
+  /// model that was built in code rather than loaded from an asset.
+  pub fn to_description(&self) -> std::result::Result<SceneDescription, String> {
+    Ok(SceneDescription {
+      background: self.bg_color.into(),
+      shader: self.shader.as_ref().map(|shader| shader.source.clone()),
+      pbr_shader: self.pbr_shader.as_ref().map(|shader| shader.source.clone()),
+      shadow_shader: self.shadow_shader.as_ref().map(|shader| shader.source.clone()),
+      skybox: self.skybox.as_ref().map(|skybox| SkyboxDescription {
+        faces: skybox.faces.clone(),
+        shader: skybox.shader.source.clone(),
+      }),
+      lights: self.lights.iter().map(|&light| light.into()).collect(),
+      nodes: self.roots.iter().map(|&id| self.describe_node(id)).collect::<std::result::Result<_, _>>()?,
+    })
+  }
+
+  fn describe_node(&self, id: NodeId) -> std::result::Result<NodeDescription, String> {
+    let node = self.node(id);
+    if node.asset.is_none() && (node.object.is_some() || node.model.is_some()) {
+      return Err(format!("node '{}' holds an object that wasn't loaded from an asset", node.name));
+    }
+
+    let (cast_shadows, receive_shadows) = match (&node.object, &node.model) {
+      (Some(object), _) => (object.cast_shadows, object.receive_shadows),
+      (None, Some(model)) => (model.cast_shadows, model.receive_shadows),
+      (None, None) => (true, true),
+    };
+
+    Ok(NodeDescription {
+      name: node.name.clone(),
+      transform: node.transform.into(),
+      asset: node.asset.clone(),
+      cast_shadows,
+      receive_shadows,
+      children: node.children.iter().map(|&c| self.describe_node(c)).collect::<std::result::Result<_, _>>()?,
+    })
+  }
+}
+
+impl<D: RenderDevice> ShaderAsset<D> {
+  pub fn load_on(device: &mut D, source: &ShaderSource) -> Result<Self> {
+    let pipeline = device.create_pipeline(&source.vertex, &source.fragment)?;
+    Ok(ShaderAsset { source: source.clone(), pipeline })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::rendering::RecordingDevice;
+  use crate::engine::rendering::TextureFilter;
+
+  const SCENE: &str = r#"(
+    background: (0.1, 0.2, 0.3),
+    shader: (vertex: "shaders/cube.vert", fragment: "shaders/cube.frag"),
+    shadow_shader: (vertex: "shaders/shadow.vert", fragment: "shaders/shadow.frag"),
+    lights: [
+      Directional(direction: (0.0, -1.0, 0.0), shadows: (resolution: 512)),
+      Point(position: (1.0, 2.0, 3.0), diffuse: (0.5, 0.5, 0.5)),
+      Spot(position: (0.0, 4.0, 0.0), direction: (0.0, 0.0, -1.0), inner_angle: 10.0),
+    ],
+    nodes: [
+      (
+        name: "pivot",
+        transform: (translation: (1.0, 0.0, 0.0), rotation: (0.0, 45.0, 0.0)),
+        children: [
+          (
+            name: "crate",
+            transform: (translation: (0.0, 1.0, 0.0), scale: (2.0, 2.0, 2.0)),
+            asset: Primitive(shape: Cube, texture: "assets/images/awesomeface.png"),
+            cast_shadows: false,
+          ),
+          (
+            name: "sprite",
+            transform: (rotation: (10.0, 20.0, 30.0)),
+            asset: Primitive(
+              shape: FlatImage,
+              texture: "assets/images/awesomeface.png",
+              texture_options: (sampler: (min_filter: Nearest, mag_filter: Nearest), mipmaps: false),
+            ),
+          ),
+        ],
+      ),
+      (name: "empty"),
+    ],
+  )"#;
+
+  fn temp_path(extension: &str) -> String {
+    let name = format!("cogwheel-scene-{}.{}", std::process::id(), extension);
+    std::env::temp_dir().join(name).to_str().unwrap().into()
+  }
+
+  // Euler angles only come back from a quaternion up to rounding.
+  fn assert_nodes_match(saved: &[NodeDescription], loaded: &[NodeDescription]) -> () {
+    assert_eq!(saved.len(), loaded.len());
+    for (saved, loaded) in saved.iter().zip(loaded) {
+      let close = |a: [f32; 3], b: [f32; 3]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3);
+      assert!(close(saved.transform.translation, loaded.transform.translation), "{}", saved.name);
+      assert!(close(saved.transform.rotation, loaded.transform.rotation), "{}", saved.name);
+      assert!(close(saved.transform.scale, loaded.transform.scale), "{}", saved.name);
+      let strip = |node: &NodeDescription| NodeDescription {
+        transform: TransformDescription::default(),
+        children: Vec::new(),
+        ..node.clone()
+      };
+      assert_eq!(strip(saved), strip(loaded));
+      assert_nodes_match(&saved.children, &loaded.children);
+    }
+  }
+
+  #[test]
+  fn saved_scenes_load_back_the_same() {
+    let mut device = RecordingDevice::new();
+    let path = temp_path("original.ron");
+    std::fs::write(&path, SCENE).unwrap();
+    let scene = Scene::load_on(&mut device, &path);
+    std::fs::remove_file(&path).unwrap();
+    let scene = scene.unwrap();
+
+    let sprite = scene.find("sprite").unwrap();
+    let options = match &scene.node(sprite).asset {
+      Some(Asset::Primitive { texture_options: Some(options), .. }) => *options,
+      asset => panic!("unexpected asset {:?}", asset),
+    };
+    assert_eq!(options.sampler.min_filter, TextureFilter::Nearest);
+    assert_eq!(scene.node(sprite).parent(), scene.find("pivot"));
+
+    for extension in ["ron", "json"] {
+      let path = temp_path(extension);
+      scene.save(&path).unwrap();
+      let loaded = Scene::load_on(&mut device, &path);
+      std::fs::remove_file(&path).unwrap();
+
+      let saved = scene.to_description().unwrap();
+      let loaded = loaded.unwrap().to_description().unwrap();
+      assert_nodes_match(&saved.nodes, &loaded.nodes);
+      assert_eq!(SceneDescription { nodes: Vec::new(), ..saved }, SceneDescription { nodes: Vec::new(), ..loaded });
+    }
+  }
+
+  #[test]
+  fn objects_built_in_code_can_not_be_saved() {
+    let mut device = RecordingDevice::new();
+    let mut scene = Scene::<RecordingDevice>::default();
+    let vertices = Vertices::Cube.get_vertices();
+    let object = Object::with_material_on(&mut device, vertices, Default::default(), Vec3::new(0.0, 0.0, 0.0));
+    scene.add_object("handmade", object, None);
+
+    assert!(scene.to_description().unwrap_err().contains("handmade"));
+  }
+}
@@ -1,95 +1,51 @@
-use std::mem;
-
 use crate::engine::Shader;
 use crate::data;
-use cgmath::{vec3, SquareMatrix};
+use cgmath::SquareMatrix;
 use data::VertexArray;
 
-use super::{material::Vector3, Texture};
-
+use super::rendering::{Geometry, GlDevice, RenderDevice, Uniform};
 use super::types::Mat4;
 
-pub struct Screen {
+/// A full-screen quad in normalized device coordinates, used to draw the
+/// contents of a framebuffer. Its positions are 2D.
+pub struct Screen<D: RenderDevice = GlDevice> {
   pub vertex_array: VertexArray,
-  pub vao: u32,
-  pub vbo: u32,
+  pub geometry: Geometry<D>,
 }
 
 impl Screen {
   pub fn new(vertex_array: VertexArray) -> Self {
-    let mut instance = Screen {
-      vertex_array,
-      vao: 0,
-      vbo: 0,
-    };
-
-    unsafe {
-      gl::GenVertexArrays(1, &mut instance.vao);
-      gl::GenBuffers(1, &mut instance.vbo);
-      gl::BindVertexArray(instance.vao);
-      gl::BindBuffer(gl::ARRAY_BUFFER, instance.vbo);
-      let len = &instance.vertex_array.vertices.len() * mem::size_of::<f32>();
-      gl::BufferData(
-        gl::ARRAY_BUFFER,
-        len as isize,
-        instance.vertex_array.vertices.as_ptr().cast(),
-        gl::STATIC_DRAW
-      );
-
-      let s = instance.vertex_array.stride as i32;
-      let m = mem::size_of::<f32>();
-      gl::EnableVertexAttribArray(0);
-      gl::VertexAttribPointer(
-        0, 2, gl::FLOAT, gl::FALSE, s, 0 as *const _
-      );
-
-      if instance.vertex_array.has_normals {
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(
-          1, 3, gl::FLOAT, gl::FALSE, s, (2 * m) as *const _
-        );
-      }
-
-      if instance.vertex_array.has_tex_coords {
-        gl::EnableVertexAttribArray(2);
-        let mut offset = 2 * m;
-        if instance.vertex_array.has_normals {
-          offset = 4 * m;
-        }
-        gl::VertexAttribPointer(
-          2, 2, gl::FLOAT, gl::FALSE, s, offset as *const _
-        );
-      }
-
-      gl::BindVertexArray(0);
-    }
-
-    instance
+    Self::new_on(&mut GlDevice, vertex_array)
   }
 
   pub fn draw(&self, shader: &mut Shader) -> () {
-    unsafe {
-      gl::BindVertexArray(self.vao);
-      let model = Mat4::identity();
-      shader.set_mat4("model", model);
-      gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_array.triangle_count);
-    }
+    self.draw_on(&mut GlDevice, shader);
   }
 
   pub fn draw_outlines(&self, shader: &mut Shader, size: f32) -> () {
-    unsafe {
-      gl::BindVertexArray(self.vao);
-      let mut model = Mat4::identity();
-      model = model * Mat4::from_scale(size);
-      shader.set_mat4("model", model);
-      gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_array.triangle_count);
-    }
+    self.draw_outlines_on(&mut GlDevice, shader, size);
   }
+}
 
-  pub fn delete(&self) -> () {
-    unsafe {
-      gl::DeleteVertexArrays(1, &self.vao);
-      gl::DeleteBuffers(1, &self.vbo);
+impl<D: RenderDevice> Screen<D> {
+  pub fn new_on(device: &mut D, vertex_array: VertexArray) -> Self {
+    let geometry = Geometry::from_vertex_array(device, &vertex_array);
+
+    Screen {
+      vertex_array,
+      geometry,
     }
   }
-}
\ No newline at end of file
+
+  pub fn draw_on(&self, device: &mut D, shader: &D::Pipeline) -> () {
+    device.set_uniform(shader, "model", Uniform::Mat4(Mat4::identity()));
+    self.geometry.draw(device);
+  }
+
+  pub fn draw_outlines_on(&self, device: &mut D, shader: &D::Pipeline, size: f32) -> () {
+    let mut model = Mat4::identity();
+    model = model * Mat4::from_scale(size);
+    device.set_uniform(shader, "model", Uniform::Mat4(model));
+    self.geometry.draw(device);
+  }
+}
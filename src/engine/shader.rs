@@ -1,60 +1,93 @@
 use std::{ffi::CString, fs::read_to_string, ptr::{null, null_mut}};
 
 use cgmath::{Array, Matrix};
-use gl::types::GLenum;
 
+use super::error::{Error, Result, ShaderLogEntry, ShaderStage};
 use super::types::*;
 
+/// Owns an OpenGL program and deletes it on drop.
 #[derive(Debug)]
 pub struct Shader {
-  pub id: u32
+  id: u32
 }
 
 impl Shader {
-  pub fn initialize(&mut self, path_to_vertex_shader: &str, path_to_fragment_shader: &str) -> () {
-    // dbg!(path_to_vertex_shader);
-    let binding = read_to_string(path_to_vertex_shader)
-    .expect("Issue with reading vertex shader!");
-    let vert_shader = binding.as_str();
-    let binding = read_to_string(path_to_fragment_shader)
-    .expect("Issue with reading vertex shader!");
-    let frag_shader = binding.as_str();
-    // dbg!(vert_shader);
-    // dbg!(frag_shader);
-    let vert_id = self.create_shader(vert_shader, gl::VERTEX_SHADER);
-    let frag_id = self.create_shader(frag_shader, gl::FRAGMENT_SHADER);
-    self.id = self.create_program(vert_id, frag_id);
+  /// Takes ownership of a program name created elsewhere.
+  pub(crate) fn from_id(id: u32) -> Self {
+    Shader { id }
+  }
+
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
+  pub fn new(path_to_vertex_shader: &str, path_to_fragment_shader: &str) -> Result<Self> {
+    let mut instance = Shader::from_id(0);
+
+    instance.initialize(path_to_vertex_shader, path_to_fragment_shader)?;
+
+    Ok(instance)
+  }
+
+  pub fn initialize(
+    &mut self, path_to_vertex_shader: &str, path_to_fragment_shader: &str
+  ) -> Result<()> {
+    let vert_id = self.create_shader(path_to_vertex_shader, ShaderStage::Vertex)?;
+    let frag_id = match self.create_shader(path_to_fragment_shader, ShaderStage::Fragment) {
+      Ok(id) => id,
+      Err(e) => {
+        self.delete_shader(vert_id);
+        return Err(e);
+      }
+    };
+    let program = self.create_program(vert_id, frag_id);
     self.delete_shader(vert_id);
     self.delete_shader(frag_id);
+    let program = program?;
+    // Only replaced once the new program links, so a failed reload keeps
+    // the old one working.
+    if self.id != 0 {
+      unsafe {
+        gl::DeleteProgram(self.id);
+      }
+    }
+    self.id = program;
+    Ok(())
   }
 
-  fn create_shader(&mut self, shader: &str, shader_type: GLenum) -> u32 {
+  fn create_shader(&mut self, path: &str, stage: ShaderStage) -> Result<u32> {
+    let source = read_to_string(path)
+      .map_err(|source| Error::Io { path: path.into(), source })?;
+    // A NUL byte can never be valid GLSL, so let the compiler report it.
+    let c_str = CString::new(source.replace('\0', " ")).unwrap();
     let mut success: i32 = 0;
-    let mut info_log: [i8; 512] = [0; 512];
     let id;
 
     unsafe {
-      id = gl::CreateShader(shader_type);
-      let c_str = std::ffi::CString::new(shader).expect("Failed to create CString");
+      id = gl::CreateShader(stage.gl_enum());
       gl::ShaderSource(id, 1, &(c_str.as_ptr().cast()), null());
       gl::CompileShader(id);
       gl::GetShaderiv(id, gl::COMPILE_STATUS, &mut success);
       if success == 0 {
-        gl::GetShaderInfoLog(id, 512, null_mut(), info_log.as_mut_ptr());
-        println!("Error in initializing shader!");
-      let log_as_u8: Vec<u8> = info_log.iter().map(|&x| x as u8).collect();
-      println!("{}", std::str::from_utf8(&log_as_u8).unwrap());
-      } else {
-        println!("Succesfully generated shader. Shader ID is {}", id)
+        let mut len: i32 = 0;
+        gl::GetShaderiv(id, gl::INFO_LOG_LENGTH, &mut len);
+        let mut info_log: Vec<u8> = vec![0; len.max(1) as usize];
+        gl::GetShaderInfoLog(id, len, null_mut(), info_log.as_mut_ptr().cast());
+        gl::DeleteShader(id);
+        let log = String::from_utf8_lossy(&info_log);
+        return Err(Error::ShaderCompile {
+          path: path.into(),
+          stage,
+          log: ShaderLogEntry::parse_log(log.trim_end_matches('\0')),
+        });
       }
     }
 
-    return id
+    Ok(id)
   }
 
-  fn create_program(&mut self, vert_id: u32, frag_id: u32) -> u32 {
+  fn create_program(&mut self, vert_id: u32, frag_id: u32) -> Result<u32> {
     let mut success: i32 = 0;
-    let mut info_log: [i8; 512] = [0; 512];
     let program_id;
 
     unsafe {
@@ -64,66 +97,66 @@ impl Shader {
       gl::LinkProgram(program_id);
       gl::GetProgramiv(program_id, gl::LINK_STATUS, &mut success);
       if success == 0 {
-        gl::GetProgramInfoLog(program_id, 512, null_mut(),
-        info_log.as_mut_ptr());
-        println!("Error in initializing shader program!");
-        let log_as_u8: Vec<u8> = info_log.iter().map(|&x| x as u8).collect();
-        println!("{}", std::str::from_utf8(&log_as_u8).unwrap());
-      } else {
-        println!("Succesfully generated shader program and linked shaders to it. Program ID is {}", program_id)
+        let mut len: i32 = 0;
+        gl::GetProgramiv(program_id, gl::INFO_LOG_LENGTH, &mut len);
+        let mut info_log: Vec<u8> = vec![0; len.max(1) as usize];
+        gl::GetProgramInfoLog(program_id, len, null_mut(), info_log.as_mut_ptr().cast());
+        gl::DeleteProgram(program_id);
+        let log = String::from_utf8_lossy(&info_log);
+        return Err(Error::ProgramLink { log: log.trim_end_matches('\0').to_string() });
       }
     }
 
-    return program_id
+    Ok(program_id)
   }
 
-  pub fn activate(&mut self) -> () {
+  pub fn activate(&self) -> () {
     unsafe {
       gl::UseProgram(self.id);
     }
   }
 
-  pub fn set_bool(&mut self, name: &str, value: bool) -> () {
+  pub fn set_bool(&self, name: &str, value: bool) -> () {
     unsafe {
       gl::Uniform1i(
         gl::GetUniformLocation(
-          self.id, CString::new(name).unwrap().into_raw()),
+          self.id, CString::new(name).unwrap().as_ptr()),
           value as i32);
     }
   }
 
-  pub fn set_int(&mut self, name: &str, value: i32) -> () {
+  pub fn set_int(&self, name: &str, value: i32) -> () {
     unsafe {
       gl::Uniform1i(
         gl::GetUniformLocation(
-          self.id, CString::new(name).unwrap().into_raw()),
+          self.id, CString::new(name).unwrap().as_ptr()),
           value);
     }
   }
 
-  pub fn set_float(&mut self, name: &str, value: f32) -> () {
+  pub fn set_float(&self, name: &str, value: f32) -> () {
     unsafe {
       gl::Uniform1f(
         gl::GetUniformLocation(
-          self.id, CString::new(name).unwrap().into_raw()),
+          self.id, CString::new(name).unwrap().as_ptr()),
           value);
     }
   }
 
-  pub fn set_vec3(&mut self, name: &str, value: &Vec3) -> () {
+  pub fn set_vec3(&self, name: &str, value: &Vec3) -> () {
     unsafe {
       gl::Uniform3fv(
-        gl::GetUniformLocation(self.id, CString::new(name).unwrap().into_raw()),
+        gl::GetUniformLocation(self.id, CString::new(name).unwrap().as_ptr()),
         1, value.as_ptr()
       );
     }
   }
 
-  pub fn set_mat4(&mut self, name: &str, value: Mat4) -> () {
+  pub fn set_mat4(&self, name: &str, value: Mat4) -> () {
     unsafe {
       gl::UniformMatrix4fv(
         gl::GetUniformLocation(
-          self.id, CString::new(name).unwrap().into_raw()),
+          self.id, CString::new(name).unwrap().as_ptr()),
           1, gl::FALSE, value.as_ptr());
     }
   }
@@ -133,4 +166,14 @@ impl Shader {
       gl::DeleteShader(shader_id);
     }
   }
-}
\ No newline at end of file
+}
+
+impl Drop for Shader {
+  fn drop(&mut self) {
+    if self.id != 0 {
+      unsafe {
+        gl::DeleteProgram(self.id);
+      }
+    }
+  }
+}
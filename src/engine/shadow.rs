@@ -0,0 +1,279 @@
+use cgmath::{perspective, vec3, Deg, EuclideanSpace, InnerSpace, Matrix4, MetricSpace, SquareMatrix, Transform, Zero};
+
+use super::camera::Camera;
+use super::error::Result;
+use super::light::{Light, ShadowSettings, MAX_CASCADES, MAX_POINT_LIGHTS};
+use super::math::{Frustum, Sphere};
+use super::rendering::{GlDevice, PixelFormat, RenderDevice, SamplerDesc, TextureDesc, TextureFilter, TextureKind, TextureWrap, Uniform};
+use super::types::{Mat4, Point3, Vec3};
+
+/// Texture units shadow maps are bound to, after the material maps.
+pub const DIRECTIONAL_SHADOW_UNIT: u32 = 7;
+pub const SPOT_SHADOW_UNIT: u32 = 8;
+/// The first of `MAX_POINT_LIGHTS` units, one for each point light slot.
+pub const POINT_SHADOW_UNIT: u32 = 9;
+
+// Spot and point light shadows start this close to the light.
+const NEAR_PLANE: f32 = 0.05;
+
+// Used when a light's attenuation never fades out.
+const DEFAULT_FAR_PLANE: f32 = 100.0;
+
+/// What one light sees of the scene, as depth: an array with a layer per
+/// cascade for directional lights, a 2D map for spot lights and a cubemap
+/// for point lights. Maps are drawn with `shaders/shadow.vert` and
+/// `shaders/shadow.frag`.
+///
+/// Directional maps hold the depth in the light's clip space. Spot and
+/// point maps hold the distance from the light divided by `far`, so both
+/// are compared the same way and the bias means the same for both.
+pub struct ShadowMap<D: RenderDevice = GlDevice> {
+  pub texture: D::Texture,
+  pub kind: TextureKind,
+  pub settings: ShadowSettings,
+  /// World to the light's clip space, one for each face of a cubemap or
+  /// cascade.
+  pub view_projections: Vec<Mat4>,
+  /// Distances from the camera where each cascade ends.
+  pub cascade_ends: Vec<f32>,
+  /// Where spot and point lights are.
+  pub light_position: Point3,
+  /// Distance the map reaches from a spot or point light.
+  pub far: f32,
+  // Whether the map holds distances rather than clip space depth.
+  linear_depth: bool,
+  targets: Vec<D::DepthTarget>,
+}
+
+impl<D: RenderDevice> ShadowMap<D> {
+  /// Fails if the device can't render into a map of `settings.resolution`.
+  pub fn new_on(device: &mut D, kind: TextureKind, settings: ShadowSettings) -> Result<Self> {
+    let desc = TextureDesc {
+      kind,
+      width: settings.resolution,
+      height: settings.resolution,
+      format: PixelFormat::Depth32F,
+      // Depths are compared, not blended.
+      sampler: SamplerDesc::wrapping(TextureWrap::ClampToEdge).filtered(TextureFilter::Nearest),
+      mipmaps: false,
+    };
+    let texture = device.create_texture(&desc, &[]);
+    let layers = kind.layers();
+    let targets = (0..layers).map(|layer| device.create_depth_target(&texture, &desc, layer)).collect::<Result<_>>()?;
+
+    Ok(ShadowMap {
+      texture,
+      kind,
+      settings,
+      view_projections: vec![Mat4::identity(); layers as usize],
+      cascade_ends: vec![f32::MAX],
+      light_position: Point3::origin(),
+      far: DEFAULT_FAR_PLANE,
+      linear_depth: kind == TextureKind::CubeMap,
+      targets,
+    })
+  }
+
+  /// The kind of map `light` needs.
+  pub fn kind_for(light: &Light) -> TextureKind {
+    match light {
+      Light::Directional(light) => {
+        let cascades = light.shadows.and_then(|shadows| shadows.cascades);
+        TextureKind::Texture2DArray(cascades.map_or(1, |cascades| cascades.count.clamp(1, MAX_CASCADES as u32)))
+      }
+      Light::Spot(_) => TextureKind::Texture2D,
+      Light::Point(_) => TextureKind::CubeMap,
+    }
+  }
+
+  /// Whether the map can be reused for a light with these settings; only
+  /// the size of the texture matters.
+  pub fn fits(&self, kind: TextureKind, settings: &ShadowSettings) -> bool {
+    self.kind == kind && self.settings.resolution == settings.resolution
+  }
+
+  /// Points the map's views at where `light` shines. A directional light
+  /// has no position, so its view is fitted around `casters`, the bounds
+  /// of everything that casts shadows, or with cascades around slices of
+  /// what `camera` sees.
+  pub fn aim(&mut self, light: &Light, casters: Sphere, camera: &Camera) -> () {
+    match light {
+      Light::Directional(light) => {
+        match self.settings.cascades {
+          Some(cascades) => {
+            let (near, far) = camera.get_projection().depth_range();
+            let ends = cascades.split_distances(near, far.unwrap_or(cascades.distance));
+            let starts = std::iter::once(near).chain(ends.iter().copied());
+            self.view_projections = starts
+              .zip(&ends)
+              .map(|(start, &end)| {
+                let corners = camera.slice_corners(start, end);
+                fit_cascade(light.direction, &corners, casters, self.settings.resolution)
+              })
+              .collect();
+            self.cascade_ends = ends;
+          }
+          None => {
+            let radius = casters.radius.max(0.1);
+            let eye = casters.center - light.direction * radius;
+            let view = Mat4::look_at_rh(eye, casters.center, up_for(light.direction));
+            let projection = cgmath::ortho(-radius, radius, -radius, radius, 0.0, 2.0 * radius);
+            self.view_projections = vec![projection * view];
+            self.cascade_ends = vec![f32::MAX];
+          }
+        }
+        self.linear_depth = false;
+      }
+      Light::Spot(light) => {
+        self.light_position = light.position;
+        self.far = light.attenuation.reach().unwrap_or(DEFAULT_FAR_PLANE);
+        // A little wider than the cone, so PCF doesn't sample past its edge.
+        let fov = (2.0 * light.outer_angle + 2.0).min(170.0);
+        let view = Mat4::look_at_rh(light.position, light.position + light.direction, up_for(light.direction));
+        self.view_projections = vec![perspective(Deg(fov), 1.0, NEAR_PLANE, self.far) * view];
+        self.linear_depth = true;
+      }
+      Light::Point(light) => {
+        self.light_position = light.position;
+        self.far = light.attenuation.reach().unwrap_or(DEFAULT_FAR_PLANE);
+        let projection = perspective(Deg(90.0), 1.0, NEAR_PLANE, self.far);
+        // Cubemap faces are upside down compared to a camera image.
+        let faces = [
+          (Vec3::unit_x(), -Vec3::unit_y()),
+          (-Vec3::unit_x(), -Vec3::unit_y()),
+          (Vec3::unit_y(), Vec3::unit_z()),
+          (-Vec3::unit_y(), -Vec3::unit_z()),
+          (Vec3::unit_z(), -Vec3::unit_y()),
+          (-Vec3::unit_z(), -Vec3::unit_y()),
+        ];
+        self.view_projections = faces
+          .iter()
+          .map(|&(direction, up)| projection * Matrix4::look_at_rh(light.position, light.position + direction, up))
+          .collect();
+        self.linear_depth = true;
+      }
+    }
+  }
+
+  /// Renders every face of the map with `pipeline` (the shadow shader).
+  /// `draw_casters` draws the shadow casters, setting each one's `model`
+  /// uniform; it gets the face's frustum for culling.
+  pub fn render_on<F>(&self, device: &mut D, pipeline: &D::Pipeline, mut draw_casters: F) -> ()
+  where
+    F: FnMut(&mut D, &Frustum),
+  {
+    device.use_pipeline(pipeline);
+    device.set_uniform(pipeline, "linearDepth", Uniform::Bool(self.linear_depth));
+    device.set_uniform(pipeline, "lightPos", Uniform::Vec3(self.light_position.to_vec()));
+    device.set_uniform(pipeline, "farPlane", Uniform::Float(self.far));
+
+    for (target, view_projection) in self.targets.iter().zip(&self.view_projections) {
+      device.begin_depth_pass(target);
+      device.set_uniform(pipeline, "lightSpace", Uniform::Mat4(*view_projection));
+      draw_casters(device, &Frustum::from_matrix(view_projection));
+      device.end_depth_pass(target);
+    }
+  }
+
+  /// Sets the fields of the shader struct called `name`, e.g.
+  /// "pointShadows[0]", and binds the map to `unit`.
+  pub fn set_uniforms_on(&self, device: &mut D, pipeline: &D::Pipeline, name: &str, unit: u32) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("{}.{}", name, field), value);
+    set("enabled", Uniform::Bool(true));
+    set("lightSpace", Uniform::Mat4(self.view_projections[0]));
+    set("lightPos", Uniform::Vec3(self.light_position.to_vec()));
+    set("far", Uniform::Float(self.far));
+    set("bias", Uniform::Float(self.settings.bias));
+    set("pcfRadius", Uniform::Int(self.settings.pcf_radius as i32));
+    device.bind_texture(unit, &self.texture, self.kind);
+  }
+
+  /// Sets the cascades of a directional map on the shader struct called
+  /// `name`, e.g. "dirCascades". A map without cascades is a single one
+  /// that never ends.
+  pub fn set_cascade_uniforms_on(&self, device: &mut D, pipeline: &D::Pipeline, name: &str) -> () {
+    let mut set = |field: &str, value| device.set_uniform(pipeline, &format!("{}.{}", name, field), value);
+    set("count", Uniform::Int(self.cascade_ends.len() as i32));
+    set("blend", Uniform::Float(self.settings.cascades.map_or(0.0, |cascades| cascades.blend)));
+    for (i, (view_projection, end)) in self.view_projections.iter().zip(&self.cascade_ends).enumerate() {
+      set(&format!("lightSpaces[{}]", i), Uniform::Mat4(*view_projection));
+      set(&format!("ends[{}]", i), Uniform::Float(*end));
+    }
+  }
+}
+
+// An orthographic view along `direction` around a slice of the camera's
+// view. The slice is wrapped in a sphere, which keeps the same size however
+// the camera turns, and the view is moved in whole texels, so the edges of
+// shadows don't shimmer as the camera moves.
+fn fit_cascade(direction: Vec3, corners: &[Point3; 8], casters: Sphere, resolution: u32) -> Mat4 {
+  let center = Point3::from_vec(corners.iter().fold(Vec3::zero(), |sum, corner| sum + corner.to_vec()) / 8.0);
+  let radius = corners.iter().map(|corner| corner.distance(center)).fold(0.0, f32::max);
+  // Rounded up, so float error doesn't change the size from frame to frame.
+  let radius = (radius * 16.0).ceil() / 16.0;
+
+  let view = Mat4::look_at_rh(Point3::origin(), Point3::from_vec(direction), up_for(direction));
+  let texel = 2.0 * radius / resolution as f32;
+  let center = view.transform_point(center);
+  let (x, y) = ((center.x / texel).floor() * texel, (center.y / texel).floor() * texel);
+
+  // Casters between the light and the slice have to be in the map too.
+  let caster_depth = -view.transform_point(casters.center).z;
+  let near = (-center.z - radius).min(caster_depth - casters.radius);
+  let far = -center.z + radius;
+  cgmath::ortho(x - radius, x + radius, y - radius, y + radius, near, far) * view
+}
+
+/// Turns off the shadow of the shader struct called `name`.
+pub fn disable_shadow_on<D: RenderDevice>(device: &mut D, pipeline: &D::Pipeline, name: &str) -> () {
+  device.set_uniform(pipeline, &format!("{}.enabled", name), Uniform::Bool(false));
+}
+
+/// Points the lit shaders' shadow samplers at their units. They have to be
+/// set even without shadows: samplers of different types left on the same
+/// unit make draws fail.
+pub fn set_shadow_units_on<D: RenderDevice>(device: &mut D, pipeline: &D::Pipeline) -> () {
+  device.set_uniform(pipeline, "dirShadowMap", Uniform::Int(DIRECTIONAL_SHADOW_UNIT as i32));
+  device.set_uniform(pipeline, "spotShadowMap", Uniform::Int(SPOT_SHADOW_UNIT as i32));
+  for slot in 0..MAX_POINT_LIGHTS {
+    let unit = POINT_SHADOW_UNIT + slot as u32;
+    device.set_uniform(pipeline, &format!("pointShadowMaps[{}]", slot), Uniform::Int(unit as i32));
+  }
+}
+
+// Any vector that isn't parallel to `direction`.
+fn up_for(direction: Vec3) -> Vec3 {
+  if direction.normalize().y.abs() > 0.99 { vec3(0.0, 0.0, 1.0) } else { Vec3::unit_y() }
+}
+
+#[cfg(test)]
+mod tests {
+  use cgmath::point3;
+
+  use super::*;
+
+  #[test]
+  fn cascades_only_move_in_whole_texels() {
+    const RESOLUTION: u32 = 1024;
+    // The light looks the same way as the camera, so light space x and y
+    // are the world's.
+    let direction = vec3(0.0, 0.0, -1.0);
+    let casters = Sphere::new(point3(0.0, 0.0, -10.0), 1.0);
+    let fit_at = |x: f32, y: f32| {
+      let camera = Camera::new(point3(x, y, 0.0));
+      fit_cascade(direction, &camera.slice_corners(0.1, 20.0), casters, RESOLUTION)
+    };
+    // The projection spans 2 / m[0][0] in x.
+    let texel = 2.0 / fit_at(0.0, 0.0)[0][0] / RESOLUTION as f32;
+
+    // Starting half a texel in, so the steps stay inside the same texel.
+    let start = fit_at(0.5 * texel, 0.5 * texel);
+    assert_eq!(fit_at(0.75 * texel, 0.5 * texel), start);
+    assert_eq!(fit_at(0.9 * texel, 0.25 * texel), start);
+
+    let moved = fit_at(1.5 * texel, 0.5 * texel);
+    assert_ne!(moved, start);
+    let shift = (start[3][0] - moved[3][0]) / start[0][0];
+    assert!((shift - texel).abs() < texel * 1e-3, "moved {} for a texel of {}", shift, texel);
+  }
+}
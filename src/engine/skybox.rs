@@ -1,99 +1,82 @@
-use std::mem;
-use image::GenericImageView;
-
 use crate::data::VertexArray;
 
-use super::{types::Mat4, Camera, Shader};
+use super::error::{Error, Result};
+use super::rendering::{
+  DepthFunc, Geometry, GlDevice, PixelFormat, RenderDevice, SamplerDesc, TextureDesc, TextureKind, TextureWrap,
+  Uniform,
+};
+use super::{Camera, Shader};
 
-pub struct Skybox {
+pub struct Skybox<D: RenderDevice = GlDevice> {
   pub vertex_array: VertexArray,
-  pub vao: u32,
-  pub vbo: u32,
-  pub cubemap_texture: u32
+  pub geometry: Geometry<D>,
+  pub cubemap_texture: D::Texture
 }
 
 impl Skybox {
-  pub fn new(vertex_array: VertexArray, texture_paths: &[&str]) -> Self {
-    let mut instance = Skybox {
-      vertex_array,
-      vao: 0,
-      vbo: 0,
-      cubemap_texture: 0
-    };
-
-    unsafe {
-      gl::GenVertexArrays(1, &mut instance.vao);
-      gl::GenBuffers(1, &mut instance.vbo);
-      gl::BindVertexArray(instance.vao);
-      gl::BindBuffer(gl::ARRAY_BUFFER, instance.vbo);
-      let len = &instance.vertex_array.vertices.len() * mem::size_of::<f32>();
-      gl::BufferData(
-        gl::ARRAY_BUFFER,
-        len as isize,
-        instance.vertex_array.vertices.as_ptr().cast(),
-        gl::STATIC_DRAW
-      );
-
-      let s = instance.vertex_array.stride as i32;
-      let m = mem::size_of::<f32>();
-      gl::EnableVertexAttribArray(0);
-      gl::VertexAttribPointer(
-        0, 3, gl::FLOAT, gl::FALSE, s, 0 as *const _
-      );
-
-      gl::BindVertexArray(0);
-
-      instance.cubemap_texture = instance.load_cubemap(texture_paths);
-    }
-
-    instance
+  pub fn new(vertex_array: VertexArray, texture_paths: &[&str]) -> Result<Self> {
+    Self::new_on(&mut GlDevice, vertex_array, texture_paths)
   }
 
-  pub fn draw(&self, shader: &mut Shader, camera: &mut Camera, projection: Mat4) -> () {
-    unsafe {
-      gl::DepthFunc(gl::LEQUAL);
-      shader.activate();
-      let mut view = camera.get_view_matrix();
-      view.w[0] = 0.0;
-      view.w[1] = 0.0;
-      view.w[2] = 0.0;
-      shader.set_mat4("view", view);
-      shader.set_mat4("projection", projection);
-      gl::BindVertexArray(self.vao);
-      gl::ActiveTexture(gl::TEXTURE0);
-      gl::BindTexture(gl::TEXTURE_CUBE_MAP, self.cubemap_texture);
-      gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_array.triangle_count);
-      gl::DepthFunc(gl::LESS);
-    }
+  pub fn draw(&self, shader: &mut Shader, camera: &Camera) -> () {
+    self.draw_on(&mut GlDevice, shader, camera);
   }
+}
 
+impl<D: RenderDevice> Skybox<D> {
+  pub fn new_on(device: &mut D, vertex_array: VertexArray, texture_paths: &[&str]) -> Result<Self> {
+    let cubemap_texture = Self::load_cubemap(device, texture_paths)?;
+    let geometry = Geometry::from_vertex_array(device, &vertex_array);
 
-  unsafe fn load_cubemap(&self, texture_paths: &[&str]) -> u32 {
-    let mut tex_id = 0;
-    gl::GenTextures(1, &mut tex_id);
-    gl::BindTexture(gl::TEXTURE_CUBE_MAP, tex_id);
+    Ok(Skybox {
+      vertex_array,
+      geometry,
+      cubemap_texture
+    })
+  }
 
-    for (i, f) in texture_paths.iter().enumerate() {
-      let img = image::open(f).expect("Failed to load texture image");
+  pub fn draw_on(&self, device: &mut D, shader: &D::Pipeline, camera: &Camera) -> () {
+    // The skybox sits at the far plane, so it has to pass where the depth
+    // buffer was cleared.
+    let (depth_func, sky_depth_func) = if camera.get_projection().is_reverse_z() {
+      (DepthFunc::Greater, DepthFunc::GreaterEqual)
+    } else {
+      (DepthFunc::Less, DepthFunc::LessEqual)
+    };
+    device.set_depth_func(sky_depth_func);
+    device.use_pipeline(shader);
+    let mut view = camera.get_view_matrix();
+    view.w[0] = 0.0;
+    view.w[1] = 0.0;
+    view.w[2] = 0.0;
+    device.set_uniform(shader, "view", Uniform::Mat4(view));
+    device.set_uniform(shader, "projection", Uniform::Mat4(camera.get_projection_matrix()));
+    device.set_uniform(shader, "reverseZ", Uniform::Bool(camera.get_projection().is_reverse_z()));
+    device.bind_texture(0, &self.cubemap_texture, TextureKind::CubeMap);
+    self.geometry.draw(device);
+    device.set_depth_func(depth_func);
+  }
 
-      gl::TexImage2D(
-        gl::TEXTURE_CUBE_MAP_POSITIVE_X + i as u32,
-        0,
-        gl::RGB as i32,
-        img.dimensions().0 as i32,
-        img.dimensions().1 as i32,
-        0,
-        gl::RGB, gl::UNSIGNED_BYTE,
-        img.as_bytes().as_ptr() as *const _
-      );
+  fn load_cubemap(device: &mut D, texture_paths: &[&str]) -> Result<D::Texture> {
+    // Decode every face up front so a broken face doesn't leave a
+    // half-uploaded cubemap behind.
+    let mut faces = Vec::with_capacity(texture_paths.len());
+    for f in texture_paths {
+      let img = image::open(f).map_err(|source| Error::from_image(*f, source))?;
+      faces.push(img.into_rgb8());
     }
 
-    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-    gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as i32);
+    let (width, height) = faces.first().map_or((0, 0), |img| img.dimensions());
+    let desc = TextureDesc {
+      kind: TextureKind::CubeMap,
+      width,
+      height,
+      format: PixelFormat::Rgb8,
+      sampler: SamplerDesc::wrapping(TextureWrap::ClampToEdge),
+      mipmaps: false,
+    };
+    let layers: Vec<&[u8]> = faces.iter().map(|img| img.as_raw().as_slice()).collect();
 
-    tex_id
+    Ok(device.create_texture(&desc, &layers))
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,42 @@
+//! Reading and writing the engine's human-editable files (scenes, input
+//! bindings). Files are RON unless their name ends in `.json`.
+
+use std::fs;
+use std::path::Path;
+
+use ron::extensions::Extensions;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::error::{Error, Result};
+
+pub(crate) fn read<T: DeserializeOwned>(path: &str) -> Result<T> {
+  let text = fs::read_to_string(path).map_err(|source| Error::Io { path: path.into(), source })?;
+
+  let parsed = if is_json(path) {
+    serde_json::from_str(&text).map_err(|e| e.to_string())
+  } else {
+    ron_options().from_str(&text).map_err(|e| e.to_string())
+  };
+  parsed.map_err(|message| Error::Format { path: path.into(), message })
+}
+
+pub(crate) fn write<T: Serialize>(path: &str, value: &T) -> Result<()> {
+  let text = if is_json(path) {
+    serde_json::to_string_pretty(value).map_err(|e| e.to_string())
+  } else {
+    ron_options().to_string_pretty(value, ron::ser::PrettyConfig::new()).map_err(|e| e.to_string())
+  };
+  let text = text.map_err(|message| Error::Format { path: path.into(), message })?;
+
+  fs::write(path, text).map_err(|source| Error::Io { path: path.into(), source })
+}
+
+fn is_json(path: &str) -> bool {
+  Path::new(path).extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+// Optional fields can be written without `Some(...)`.
+fn ron_options() -> ron::Options {
+  ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME)
+}
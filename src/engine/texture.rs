@@ -1,79 +1,212 @@
-use image::{self, GenericImageView, ImageError};
+use image::{self, DynamicImage, GenericImageView};
 
-#[derive(Debug, Clone)]
+use super::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use super::rendering::{
+  GlDevice, PixelFormat, RenderDevice, SamplerDesc, TextureDesc, TextureFilter, TextureKind, TextureWrap,
+};
+
+pub mod bcn;
+
+pub mod container;
+pub use container::CompressedImage;
+
+/// Owns an OpenGL texture name (2D or cubemap) and deletes it on drop.
+/// Textures are shared between objects through `Rc<Texture>`.
+#[derive(Debug)]
 pub struct Texture {
-  pub id: u32
+  id: u32
 }
 
 impl Texture {
-  pub fn new(path_to_image_file: &str) -> Self {
-    let mut instance = Texture { id: 0 };
+  /// Takes ownership of a texture name created elsewhere.
+  pub(crate) fn from_id(id: u32) -> Self {
+    Texture { id }
+  }
 
-    let _ = instance.generate(path_to_image_file);
+  pub fn id(&self) -> u32 {
+    self.id
+  }
 
-    instance
+  pub fn new(path_to_image_file: &str) -> Result<Self> {
+    load_texture(&mut GlDevice, path_to_image_file)
   }
+}
 
-  pub fn generate(&mut self, path_to_image_file: &str) -> Result<(), ImageError> {
-    let mut img = image::open(path_to_image_file).unwrap();
-    
-    // Add handling here in case img is not properly loaded.
-    // println!("{:?}", img.dimensions());
+impl Drop for Texture {
+  fn drop(&mut self) {
+    if self.id != 0 {
+      unsafe {
+        gl::DeleteTextures(1, &self.id);
+      }
+    }
+  }
+}
 
-    let extension = path_to_image_file.rsplit('.').next();
-    
-    if extension == Some("png") {
-      img = img.flipv();
+/// How an image file is made into a texture: how it is sampled, whether it
+/// gets mipmaps, which way up it goes and its color space. Used by
+/// `load_texture_with` and `Model`, and written on assets in scene files.
+///
+/// The default repeats, filters trilinearly, generates mipmaps and loads
+/// the image as it is.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TextureDescriptor {
+  pub sampler: SamplerDesc,
+  /// Whether mip levels are generated.
+  pub mipmaps: bool,
+  /// Whether the image is turned upside down, so its first row ends up at
+  /// the bottom where OpenGL expects it.
+  pub flip_y: bool,
+  /// Whether the image holds sRGB color, which is decoded to linear when
+  /// sampled. Only 8-bit color has sRGB formats; gray, 16-bit and float
+  /// images are loaded as they are.
+  pub srgb: bool,
+}
+
+impl Default for TextureDescriptor {
+  fn default() -> Self {
+    TextureDescriptor { sampler: SamplerDesc::default(), mipmaps: true, flip_y: false, srgb: false }
+  }
+}
+
+impl TextureDescriptor {
+  /// Sharp texels without mipmaps, for pixel art.
+  pub fn pixel_art() -> Self {
+    TextureDescriptor {
+      sampler: SamplerDesc::default().filtered(TextureFilter::Nearest),
+      mipmaps: false,
+      ..Default::default()
     }
+  }
+}
 
-    let format = match img {
-      image::DynamicImage::ImageLuma8(_) => gl::RED,
-      image::DynamicImage::ImageLumaA8(_) => gl::RG,
-      image::DynamicImage::ImageRgb8(_) => gl::RGB,
-      image::DynamicImage::ImageRgba8(_) => gl::RGBA,
-      image::DynamicImage::ImageLuma16(_) => todo!(),
-      image::DynamicImage::ImageLumaA16(_) => todo!(),
-      image::DynamicImage::ImageRgb16(_) => todo!(),
-      image::DynamicImage::ImageRgba16(_) => todo!(),
-      image::DynamicImage::ImageRgb32F(_) => todo!(),
-      image::DynamicImage::ImageRgba32F(_) => todo!(),
-      _ => todo!(),
-    };
-
-    unsafe {
-      gl::GenTextures(1, &mut self.id);
-      gl::BindTexture(gl::TEXTURE_2D, self.id);
-      
-      gl::TexImage2D(
-        gl::TEXTURE_2D,
-        0,
-        format as i32,
-        img.dimensions().0 as i32,
-        img.dimensions().1 as i32,
-        0,
-        format, gl::UNSIGNED_BYTE,
-        img.as_bytes().as_ptr() as *const _
-      );
-      gl::GenerateMipmap(gl::TEXTURE_2D);
-
-      if format == gl::RGBA {
-        gl::TexParameteri(
-          gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
-        gl::TexParameteri(
-          gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
-      }
-      else {
-        gl::TexParameteri(
-          gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(
-          gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+/// Decodes an image file and uploads it as a mipmapped 2D texture. DDS and
+/// KTX2 files are uploaded compressed, with the default descriptor.
+///
+/// PNGs are flipped so their first row ends up at the bottom, where OpenGL
+/// expects it. Images with alpha are clamped at the edges, everything else
+/// repeats. Use `load_texture_with` to choose instead.
+pub fn load_texture<D: RenderDevice>(device: &mut D, path_to_image_file: &str) -> Result<D::Texture> {
+  if container::is_container(path_to_image_file) {
+    return load_texture_with(device, path_to_image_file, &TextureDescriptor::default());
+  }
+  let img = open_image(path_to_image_file)?;
+  let wrap = if img.color().has_alpha() { TextureWrap::ClampToEdge } else { TextureWrap::Repeat };
+  let descriptor = TextureDescriptor {
+    sampler: SamplerDesc::wrapping(wrap),
+    flip_y: path_to_image_file.rsplit('.').next() == Some("png"),
+    ..Default::default()
+  };
+
+  Ok(upload_image(device, img, &descriptor))
+}
+
+/// Decodes an image file, or reads a DDS or KTX2 file, and uploads it as a
+/// 2D texture set up by `descriptor`.
+pub fn load_texture_with<D: RenderDevice>(
+  device: &mut D, path_to_image_file: &str, descriptor: &TextureDescriptor
+) -> Result<D::Texture> {
+  if container::is_container(path_to_image_file) {
+    let image = CompressedImage::read(path_to_image_file)?;
+    if !device.supports_format(image.format) {
+      let message = format!("{:?} textures are not supported by this GPU", image.format);
+      return Err(Error::Format { path: path_to_image_file.into(), message });
+    }
+    return Ok(upload_compressed(device, &image, descriptor));
+  }
+  Ok(upload_image(device, open_image(path_to_image_file)?, descriptor))
+}
+
+/// Uploads a compressed image with the mip levels it comes with, or only
+/// the first without `descriptor.mipmaps`. `descriptor.srgb` turns linear
+/// color formats into sRGB ones, but `flip_y` is ignored: blocks can't be
+/// flipped cheaply, so compressed files have to be stored bottom row first
+/// (see the `compress_textures` tool).
+pub fn upload_compressed<D: RenderDevice>(
+  device: &mut D, image: &CompressedImage, descriptor: &TextureDescriptor
+) -> D::Texture {
+  let levels: Vec<&[u8]> = image.levels.iter().map(Vec::as_slice).collect();
+  let levels = if descriptor.mipmaps { &levels[..] } else { &levels[..levels.len().min(1)] };
+  let desc = TextureDesc {
+    kind: TextureKind::Texture2D,
+    width: image.width,
+    height: image.height,
+    format: if descriptor.srgb { image.format.to_srgb() } else { image.format },
+    sampler: descriptor.sampler,
+    mipmaps: levels.len() > 1,
+  };
+
+  device.create_compressed_texture(&desc, levels)
+}
+
+/// Uploads a decoded image as a 2D texture, keeping its values as they
+/// are: 16-bit images stay 16-bit and HDR images stay floating point.
+pub fn upload_image<D: RenderDevice>(device: &mut D, img: DynamicImage, descriptor: &TextureDescriptor) -> D::Texture {
+  let img = if descriptor.flip_y { img.flipv() } else { img };
+  let (format, img) = pixel_format(img, descriptor.srgb);
+  let desc = TextureDesc {
+    kind: TextureKind::Texture2D,
+    width: img.dimensions().0,
+    height: img.dimensions().1,
+    format,
+    sampler: descriptor.sampler,
+    mipmaps: descriptor.mipmaps,
+  };
+
+  device.create_texture(&desc, &[img.as_bytes()])
+}
+
+fn open_image(path: &str) -> Result<DynamicImage> {
+  image::open(path).map_err(|source| Error::from_image(path, source))
+}
+
+// The upload format for a decoded image, with `srgb` picking the sRGB
+// formats for 8-bit color. Layouts without a format of their own (ones
+// added to `image` later) are converted to 32-bit float RGBA.
+fn pixel_format(img: DynamicImage, srgb: bool) -> (PixelFormat, DynamicImage) {
+  let format = match img {
+    DynamicImage::ImageLuma8(_) => PixelFormat::Luma8,
+    DynamicImage::ImageLumaA8(_) => PixelFormat::LumaAlpha8,
+    DynamicImage::ImageRgb8(_) if srgb => PixelFormat::Srgb8,
+    DynamicImage::ImageRgb8(_) => PixelFormat::Rgb8,
+    DynamicImage::ImageRgba8(_) if srgb => PixelFormat::Srgba8,
+    DynamicImage::ImageRgba8(_) => PixelFormat::Rgba8,
+    DynamicImage::ImageLuma16(_) => PixelFormat::Luma16,
+    DynamicImage::ImageLumaA16(_) => PixelFormat::LumaAlpha16,
+    DynamicImage::ImageRgb16(_) => PixelFormat::Rgb16,
+    DynamicImage::ImageRgba16(_) => PixelFormat::Rgba16,
+    DynamicImage::ImageRgb32F(_) => PixelFormat::Rgb32F,
+    DynamicImage::ImageRgba32F(_) => PixelFormat::Rgba32F,
+    _ => return (PixelFormat::Rgba32F, DynamicImage::ImageRgba32F(img.to_rgba32f())),
+  };
+  (format, img)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::engine::rendering::{Command, RecordingDevice};
+
+  #[test]
+  fn gray_images_round_trip_through_dds_as_bc4() {
+    let gray = image::GrayImage::from_fn(8, 8, |x, y| image::Luma([(x * 30 + y) as u8]));
+    let compressed = bcn::compress(&DynamicImage::ImageLuma8(gray).to_rgba8(), PixelFormat::Bc4, true).unwrap();
+    let path = std::env::temp_dir().join(format!("cogwheel-gray-{}.dds", std::process::id()));
+    std::fs::write(&path, compressed.to_dds()).unwrap();
+
+    let mut device = RecordingDevice::new();
+    load_texture(&mut device, path.to_str().unwrap()).unwrap();
+
+    match device.commands() {
+      [Command::CreateCompressedTexture { desc, levels, .. }] => {
+        // GlDevice spreads BC4's one channel over red, green and blue.
+        assert_eq!(desc.format, PixelFormat::Bc4);
+        assert_eq!((desc.width, desc.height), (8, 8));
+        assert_eq!(*levels, compressed.levels.len());
       }
-      gl::TexParameteri(
-        gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER,
-        gl::LINEAR_MIPMAP_LINEAR as i32);
-      gl::TexParameteri(
-        gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+      commands => panic!("unexpected commands {:?}", commands),
     }
-    Ok(())
   }
-}
\ No newline at end of file
+}
@@ -0,0 +1,272 @@
+//! A simple BC1, BC3, BC4 and BC5 encoder, for compressing textures
+//! offline. It fits each block's endpoints along the main axis of its
+//! colors, which is fast and good enough for most game textures; BC2, BC6H
+//! and BC7 can be loaded but not encoded.
+
+use image::{imageops, RgbaImage};
+
+use crate::engine::rendering::PixelFormat;
+
+use super::container::CompressedImage;
+
+/// Compresses `image` into `format`, with a mip chain down to 1x1 when
+/// `mipmaps` is set. Returns `None` for formats this encoder can't write.
+///
+/// BC1 keeps no alpha, BC4 keeps red and BC5 keeps red and green, e.g. for
+/// normal maps.
+pub fn compress(image: &RgbaImage, format: PixelFormat, mipmaps: bool) -> Option<CompressedImage> {
+  let encode_block = match format {
+    PixelFormat::Bc1 | PixelFormat::Bc1Srgb => encode_bc1,
+    PixelFormat::Bc3 | PixelFormat::Bc3Srgb => encode_bc3,
+    PixelFormat::Bc4 => encode_bc4,
+    PixelFormat::Bc5 => encode_bc5,
+    _ => return None,
+  };
+
+  let (width, height) = image.dimensions();
+  let mut levels = vec![encode_level(image, encode_block)];
+  let mut level = image.clone();
+  while mipmaps && (level.width() > 1 || level.height() > 1) {
+    let (width, height) = ((level.width() / 2).max(1), (level.height() / 2).max(1));
+    level = imageops::resize(&level, width, height, imageops::FilterType::Triangle);
+    levels.push(encode_level(&level, encode_block));
+  }
+
+  Some(CompressedImage { format, width, height, levels })
+}
+
+// Encodes the 4x4 blocks of `image` row by row. Blocks past the edges
+// repeat the last row and column.
+fn encode_level(image: &RgbaImage, encode_block: fn(&[[u8; 4]; 16], &mut Vec<u8>)) -> Vec<u8> {
+  let (width, height) = image.dimensions();
+  let mut bytes = Vec::new();
+  for block_y in (0..height).step_by(4) {
+    for block_x in (0..width).step_by(4) {
+      let mut block = [[0; 4]; 16];
+      for (i, texel) in block.iter_mut().enumerate() {
+        let x = (block_x + i as u32 % 4).min(width - 1);
+        let y = (block_y + i as u32 / 4).min(height - 1);
+        *texel = image.get_pixel(x, y).0;
+      }
+      encode_block(&block, &mut bytes);
+    }
+  }
+  bytes
+}
+
+fn encode_bc1(block: &[[u8; 4]; 16], bytes: &mut Vec<u8>) -> () {
+  encode_color(block, bytes);
+}
+
+fn encode_bc3(block: &[[u8; 4]; 16], bytes: &mut Vec<u8>) -> () {
+  encode_channel(&block.map(|texel| texel[3]), bytes);
+  encode_color(block, bytes);
+}
+
+fn encode_bc4(block: &[[u8; 4]; 16], bytes: &mut Vec<u8>) -> () {
+  encode_channel(&block.map(|texel| texel[0]), bytes);
+}
+
+fn encode_bc5(block: &[[u8; 4]; 16], bytes: &mut Vec<u8>) -> () {
+  encode_channel(&block.map(|texel| texel[0]), bytes);
+  encode_channel(&block.map(|texel| texel[1]), bytes);
+}
+
+// A BC1 color block in its four-color mode: two RGB565 endpoints and a
+// 2-bit index per texel into them and the two colors between them.
+fn encode_color(block: &[[u8; 4]; 16], bytes: &mut Vec<u8>) -> () {
+  let colors = block.map(|texel| [texel[0] as f32, texel[1] as f32, texel[2] as f32]);
+  let mean = colors.iter().fold([0.0; 3], |sum, color| add(sum, *color)).map(|sum| sum / 16.0);
+
+  // The colors spread the most along the main eigenvector of their
+  // covariance, found by power iteration.
+  let mut covariance = [[0.0f32; 3]; 3];
+  for color in &colors {
+    let offset = sub(*color, mean);
+    for (row, covariance) in covariance.iter_mut().enumerate() {
+      for (column, value) in covariance.iter_mut().enumerate() {
+        *value += offset[row] * offset[column];
+      }
+    }
+  }
+  let mut axis = [1.0, 1.0, 1.0];
+  for _ in 0..8 {
+    let next = covariance.map(|row| dot(row, axis));
+    let length = dot(next, next).sqrt();
+    if length < f32::EPSILON {
+      break;
+    }
+    axis = next.map(|value| value / length);
+  }
+
+  let projections = colors.map(|color| dot(sub(color, mean), axis));
+  let min = projections.iter().copied().fold(f32::MAX, f32::min);
+  let max = projections.iter().copied().fold(f32::MIN, f32::max);
+  let mut start = to_565(add(mean, axis.map(|value| value * max)));
+  let mut end = to_565(add(mean, axis.map(|value| value * min)));
+  // The first endpoint has to be the larger one for four colors.
+  if start < end {
+    std::mem::swap(&mut start, &mut end);
+  }
+
+  let (first, second) = (from_565(start), from_565(end));
+  let palette = [
+    first,
+    second,
+    add(first.map(|value| value * 2.0), second).map(|value| value / 3.0),
+    add(first, second.map(|value| value * 2.0)).map(|value| value / 3.0),
+  ];
+  let indices = colors.map(|color| nearest(&palette, |entry| {
+    let difference = sub(color, *entry);
+    dot(difference, difference)
+  }));
+
+  bytes.extend_from_slice(&start.to_le_bytes());
+  bytes.extend_from_slice(&end.to_le_bytes());
+  for row in indices.chunks(4) {
+    bytes.push(row.iter().rev().fold(0, |packed, &index| packed << 2 | index as u8));
+  }
+}
+
+// A BC4 block: two 8-bit endpoints and a 3-bit index per texel into them
+// and the six values between them.
+fn encode_channel(values: &[u8; 16], bytes: &mut Vec<u8>) -> () {
+  let max = *values.iter().max().unwrap();
+  let min = *values.iter().min().unwrap();
+  let mut palette = [max as f32, min as f32, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+  for i in 1..7 {
+    palette[i + 1] = ((7 - i) as f32 * max as f32 + i as f32 * min as f32) / 7.0;
+  }
+  let indices = values.map(|value| nearest(&palette, |entry| (value as f32 - entry).abs()));
+
+  bytes.push(max);
+  bytes.push(min);
+  let packed = indices.iter().rev().fold(0u64, |packed, &index| packed << 3 | index as u64);
+  bytes.extend_from_slice(&packed.to_le_bytes()[..6]);
+}
+
+// Index of the palette entry with the smallest `distance`.
+fn nearest<T>(palette: &[T], distance: impl Fn(&T) -> f32) -> usize {
+  let distances = palette.iter().map(distance);
+  distances.enumerate().fold((0, f32::MAX), |best, (i, d)| if d < best.1 { (i, d) } else { best }).0
+}
+
+fn to_565(color: [f32; 3]) -> u16 {
+  let [r, g, b] = color.map(|value| value.clamp(0.0, 255.0));
+  let (r, g, b) = ((r * 31.0 / 255.0).round() as u16, (g * 63.0 / 255.0).round() as u16, (b * 31.0 / 255.0).round() as u16);
+  r << 11 | g << 5 | b
+}
+
+fn from_565(color: u16) -> [f32; 3] {
+  let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+  [r as f32 * 255.0 / 31.0, g as f32 * 255.0 / 63.0, b as f32 * 255.0 / 31.0]
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+  [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+  a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A diagonal gradient with a little pattern on top, like a typical
+  // texture.
+  fn test_image() -> RgbaImage {
+    RgbaImage::from_fn(16, 12, |x, y| {
+      let (t, pattern) = ((x + y) as u8, ((x * 7 + y * 13) % 5) as u8);
+      image::Rgba([t * 8 + pattern, 250 - t * 9, 40 + t * 5 - pattern, 255])
+    })
+  }
+
+  // The texels of a 4x4 block, row by row.
+  fn decode_bc1(block: &[u8]) -> [[f32; 3]; 16] {
+    let first = from_565(u16::from_le_bytes([block[0], block[1]]));
+    let second = from_565(u16::from_le_bytes([block[2], block[3]]));
+    let palette = [
+      first,
+      second,
+      add(first.map(|value| value * 2.0), second).map(|value| value / 3.0),
+      add(first, second.map(|value| value * 2.0)).map(|value| value / 3.0),
+    ];
+    std::array::from_fn(|i| palette[(block[4 + i / 4] >> (i % 4 * 2) & 3) as usize])
+  }
+
+  fn decode_bc4(block: &[u8]) -> [f32; 16] {
+    let (max, min) = (block[0] as f32, block[1] as f32);
+    let mut palette = [max, min, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    for i in 1..7 {
+      palette[i + 1] = ((7 - i) as f32 * max + i as f32 * min) / 7.0;
+    }
+    let mut packed = [0; 8];
+    packed[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(packed);
+    std::array::from_fn(|i| palette[(indices >> (i * 3) & 7) as usize])
+  }
+
+  // Calls `check` with each texel's original and its block's bytes and
+  // position in it.
+  fn for_each_texel(image: &RgbaImage, level: &[u8], block_size: usize, mut check: impl FnMut([u8; 4], &[u8], usize)) {
+    let blocks_wide = image.width().div_ceil(4);
+    for (index, block) in level.chunks(block_size).enumerate() {
+      let (block_x, block_y) = (index as u32 % blocks_wide * 4, index as u32 / blocks_wide * 4);
+      for i in 0..16 {
+        let (x, y) = (block_x + i as u32 % 4, block_y + i as u32 / 4);
+        if x < image.width() && y < image.height() {
+          check(image.get_pixel(x, y).0, block, i);
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn bc1_stays_close_to_the_original() {
+    let image = test_image();
+    let compressed = compress(&image, PixelFormat::Bc1, false).unwrap();
+    assert_eq!(compressed.levels[0].len(), 4 * 3 * 8);
+
+    let (mut total, mut worst, mut count) = (0.0, 0.0f32, 0);
+    for_each_texel(&image, &compressed.levels[0], 8, |original, block, i| {
+      let decoded = decode_bc1(block)[i];
+      for channel in 0..3 {
+        let error = (original[channel] as f32 - decoded[channel]).abs();
+        total += error;
+        worst = worst.max(error);
+        count += 1;
+      }
+    });
+
+    let mean = total / count as f32;
+    assert!(mean < 6.0, "mean error {}", mean);
+    assert!(worst < 16.0, "worst error {}", worst);
+  }
+
+  #[test]
+  fn bc4_is_within_half_a_step_of_the_original() {
+    let image = test_image();
+    let compressed = compress(&image, PixelFormat::Bc4, false).unwrap();
+
+    for_each_texel(&image, &compressed.levels[0], 8, |original, block, i| {
+      // Eight evenly spaced values between the block's extremes.
+      let step = (block[0] - block[1]) as f32 / 7.0;
+      let error = (original[0] as f32 - decode_bc4(block)[i]).abs();
+      assert!(error <= step / 2.0 + 0.001, "error {} with steps of {}", error, step);
+    });
+  }
+
+  #[test]
+  fn mip_chains_go_down_to_one_texel() {
+    let compressed = compress(&test_image(), PixelFormat::Bc4, true).unwrap();
+    // 16x12, 8x6, 4x3, 2x1 and 1x1, each at least one block.
+    let sizes: Vec<usize> = compressed.levels.iter().map(Vec::len).collect();
+    assert_eq!(sizes, [4 * 3 * 8, 2 * 2 * 8, 8, 8, 8]);
+  }
+}
@@ -0,0 +1,333 @@
+//! DDS and KTX2 files holding block-compressed 2D textures.
+//!
+//! Only what the engine uploads is read: one 2D image in a BC1 to BC7
+//! format with its mip chain. Cubemaps, arrays, volumes and supercompressed
+//! KTX2 files are rejected.
+
+use std::path::Path;
+
+use crate::engine::error::{Error, Result};
+use crate::engine::rendering::PixelFormat;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+
+// Sizes of the DDS header after the magic, and of the DX10 extension.
+const DDS_HEADER_SIZE: usize = 124;
+const DX10_HEADER_SIZE: usize = 20;
+
+/// A texture whose mip levels are already in a block-compressed format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressedImage {
+  pub format: PixelFormat,
+  pub width: u32,
+  pub height: u32,
+  /// Mip levels, largest first.
+  pub levels: Vec<Vec<u8>>,
+}
+
+/// Whether the file is a DDS or KTX2 container, by its extension.
+pub fn is_container(path: &str) -> bool {
+  let extension = Path::new(path).extension().and_then(|extension| extension.to_str());
+  matches!(extension.map(str::to_ascii_lowercase).as_deref(), Some("dds" | "ktx2"))
+}
+
+impl CompressedImage {
+  /// Reads a `.dds` or `.ktx2` file.
+  pub fn read(path: &str) -> Result<Self> {
+    let bytes = std::fs::read(path).map_err(|source| Error::Io { path: path.into(), source })?;
+    let parsed = if bytes.starts_with(&KTX2_IDENTIFIER) {
+      Self::from_ktx2(&bytes)
+    } else {
+      Self::from_dds(&bytes)
+    };
+    parsed.map_err(|message| Error::Format { path: path.into(), message })
+  }
+
+  pub fn from_dds(bytes: &[u8]) -> std::result::Result<Self, String> {
+    if !bytes.starts_with(DDS_MAGIC) {
+      return Err("not a DDS or KTX2 file".into());
+    }
+    let header = bytes.get(4..4 + DDS_HEADER_SIZE).ok_or("the DDS header is cut short")?;
+    let height = u32_at(header, 8);
+    let width = u32_at(header, 12);
+    let depth = u32_at(header, 20);
+    let level_count = u32_at(header, 24).max(1);
+    let four_cc = &header[80..84];
+    let caps2 = u32_at(header, 108);
+    if caps2 & 0x200 != 0 || depth > 1 {
+      return Err("only 2D textures are supported, not cubemaps or volumes".into());
+    }
+    check_size(width, height)?;
+    check_level_count(level_count)?;
+
+    let mut data_start = 4 + DDS_HEADER_SIZE;
+    let format = match four_cc {
+      b"DXT1" => PixelFormat::Bc1,
+      b"DXT2" | b"DXT3" => PixelFormat::Bc2,
+      b"DXT4" | b"DXT5" => PixelFormat::Bc3,
+      b"ATI1" | b"BC4U" => PixelFormat::Bc4,
+      b"ATI2" | b"BC5U" => PixelFormat::Bc5,
+      b"DX10" => {
+        let extension = bytes.get(data_start..data_start + DX10_HEADER_SIZE).ok_or("the DX10 header is cut short")?;
+        data_start += DX10_HEADER_SIZE;
+        if u32_at(extension, 4) != 3 || u32_at(extension, 12) > 1 {
+          return Err("only 2D textures are supported, not arrays".into());
+        }
+        dxgi_format(u32_at(extension, 0)).ok_or_else(|| format!("DXGI format {} is not block-compressed", u32_at(extension, 0)))?
+      }
+      _ => return Err(format!("pixel format {:?} is not block-compressed", String::from_utf8_lossy(four_cc))),
+    };
+
+    // Levels follow each other, largest first.
+    let mut levels = Vec::with_capacity(level_count as usize);
+    let mut offset = data_start;
+    for level in 0..level_count {
+      let end = level_size(format, width, height, level)
+        .and_then(|size| offset.checked_add(size))
+        .ok_or_else(|| format!("mip level {} is too large", level))?;
+      let data = bytes.get(offset..end).ok_or_else(|| format!("mip level {} is cut short", level))?;
+      levels.push(data.to_vec());
+      offset = end;
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+  }
+
+  pub fn from_ktx2(bytes: &[u8]) -> std::result::Result<Self, String> {
+    if !bytes.starts_with(&KTX2_IDENTIFIER) {
+      return Err("not a KTX2 file".into());
+    }
+    // The header and index up to the level index.
+    let header = bytes.get(..80).ok_or("the KTX2 header is cut short")?;
+    let vk_format = u32_at(header, 12);
+    let width = u32_at(header, 20);
+    let height = u32_at(header, 24);
+    let depth = u32_at(header, 28);
+    let layer_count = u32_at(header, 32);
+    let face_count = u32_at(header, 36);
+    // 0 asks the loader to generate mipmaps, which compressed data can't.
+    let level_count = u32_at(header, 40).max(1);
+    let supercompression = u32_at(header, 44);
+
+    if depth > 1 || layer_count > 1 || face_count != 1 {
+      return Err("only 2D textures are supported, not cubemaps, arrays or volumes".into());
+    }
+    if supercompression != 0 {
+      return Err(format!("supercompression scheme {} is not supported", supercompression));
+    }
+    check_size(width, height)?;
+    check_level_count(level_count)?;
+    let format = vk_format_to_pixel(vk_format).ok_or_else(|| format!("Vulkan format {} is not block-compressed", vk_format))?;
+
+    // Each level has an offset, a length and an uncompressed length.
+    let mut levels = Vec::with_capacity(level_count as usize);
+    for level in 0..level_count {
+      let entry = 80 + level as usize * 24;
+      let index = bytes.get(entry..entry + 24).ok_or("the level index is cut short")?;
+      let too_large = || format!("mip level {} is too large", level);
+      let offset = usize::try_from(u64_at(index, 0)).map_err(|_| too_large())?;
+      let length = usize::try_from(u64_at(index, 8)).map_err(|_| too_large())?;
+      if length < level_size(format, width, height, level).ok_or_else(too_large)? {
+        return Err(format!("mip level {} is too small", level));
+      }
+      let end = offset.checked_add(length).ok_or_else(too_large)?;
+      let data = bytes.get(offset..end).ok_or_else(|| format!("mip level {} is cut short", level))?;
+      levels.push(data.to_vec());
+    }
+
+    Ok(CompressedImage { format, width, height, levels })
+  }
+
+  /// The image as a DDS file with a DX10 header, which keeps sRGB formats
+  /// apart from linear ones.
+  pub fn to_dds(&self) -> Vec<u8> {
+    // Caps, height, width, pixel format, mip count and linear size.
+    let flags: u32 = 0x1 | 0x2 | 0x4 | 0x1000 | 0x20000 | 0x80000;
+    // A texture, with a mip chain when there is more than one level.
+    let caps: u32 = if self.levels.len() > 1 { 0x1000 | 0x8 | 0x400000 } else { 0x1000 };
+
+    let mut header = [0u32; DDS_HEADER_SIZE / 4];
+    header[0] = DDS_HEADER_SIZE as u32;
+    header[1] = flags;
+    header[2] = self.height;
+    header[3] = self.width;
+    header[4] = self.levels.first().map_or(0, Vec::len) as u32;
+    header[6] = self.levels.len() as u32;
+    // The pixel format: its size, "has a FourCC" and the FourCC.
+    header[18] = 32;
+    header[19] = 0x4;
+    header[20] = u32::from_le_bytes(*b"DX10");
+    header[26] = caps;
+    // DXGI format, 2D dimension, no flags, one array element.
+    let extension = [dxgi_for(self.format), 3, 0, 1, 0];
+
+    let mut bytes = DDS_MAGIC.to_vec();
+    bytes.extend(header.iter().chain(&extension).flat_map(|value| value.to_le_bytes()));
+    for level in &self.levels {
+      bytes.extend_from_slice(level);
+    }
+    bytes
+  }
+}
+
+/// Bytes in mip level `level` of a `width` by `height` image in a
+/// compressed format, or `None` if that doesn't fit in a `usize`. Partial
+/// blocks at the edges take a whole block.
+pub fn level_size(format: PixelFormat, width: u32, height: u32, level: u32) -> Option<usize> {
+  let blocks = |size: u32| size.checked_shr(level).unwrap_or(0).max(1).div_ceil(4) as usize;
+  blocks(width).checked_mul(blocks(height))?.checked_mul(format.block_size().unwrap_or(0))
+}
+
+fn check_size(width: u32, height: u32) -> std::result::Result<(), String> {
+  match (width, height) {
+    (0, _) | (_, 0) => Err(format!("a {}x{} image has no pixels", width, height)),
+    _ => Ok(()),
+  }
+}
+
+// A 32-bit size halves to 1 within 32 levels.
+fn check_level_count(level_count: u32) -> std::result::Result<(), String> {
+  match level_count {
+    0..=32 => Ok(()),
+    _ => Err(format!("{} mip levels is more than any image has", level_count)),
+  }
+}
+
+fn dxgi_format(format: u32) -> Option<PixelFormat> {
+  match format {
+    71 => Some(PixelFormat::Bc1),
+    72 => Some(PixelFormat::Bc1Srgb),
+    74 => Some(PixelFormat::Bc2),
+    75 => Some(PixelFormat::Bc2Srgb),
+    77 => Some(PixelFormat::Bc3),
+    78 => Some(PixelFormat::Bc3Srgb),
+    80 => Some(PixelFormat::Bc4),
+    83 => Some(PixelFormat::Bc5),
+    95 => Some(PixelFormat::Bc6hUfloat),
+    96 => Some(PixelFormat::Bc6hSfloat),
+    98 => Some(PixelFormat::Bc7),
+    99 => Some(PixelFormat::Bc7Srgb),
+    _ => None,
+  }
+}
+
+// The reverse of `dxgi_format`; 0 is DXGI's "unknown".
+fn dxgi_for(format: PixelFormat) -> u32 {
+  (0..=99).find(|&dxgi| dxgi_format(dxgi) == Some(format)).unwrap_or(0)
+}
+
+fn vk_format_to_pixel(format: u32) -> Option<PixelFormat> {
+  match format {
+    131 | 133 => Some(PixelFormat::Bc1),
+    132 | 134 => Some(PixelFormat::Bc1Srgb),
+    135 => Some(PixelFormat::Bc2),
+    136 => Some(PixelFormat::Bc2Srgb),
+    137 => Some(PixelFormat::Bc3),
+    138 => Some(PixelFormat::Bc3Srgb),
+    139 => Some(PixelFormat::Bc4),
+    141 => Some(PixelFormat::Bc5),
+    143 => Some(PixelFormat::Bc6hUfloat),
+    144 => Some(PixelFormat::Bc6hSfloat),
+    145 => Some(PixelFormat::Bc7),
+    146 => Some(PixelFormat::Bc7Srgb),
+    _ => None,
+  }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+  u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+  u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn image(format: PixelFormat, width: u32, height: u32, level_count: u32) -> CompressedImage {
+    let levels = (0..level_count)
+      .map(|level| {
+        let size = level_size(format, width, height, level).unwrap();
+        (0..size).map(|i| (i * 7 + level as usize) as u8).collect()
+      })
+      .collect();
+    CompressedImage { format, width, height, levels }
+  }
+
+  // A KTX2 file with a header, a level index and the levels after it.
+  fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+    let mut bytes = KTX2_IDENTIFIER.to_vec();
+    for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+      bytes.extend(value.to_le_bytes());
+    }
+    // No data format descriptor, key/values or supercompression data.
+    bytes.extend([0u8; 32]);
+    let mut offset = 80 + levels.len() * 24;
+    for level in levels {
+      for value in [offset, level.len(), level.len()] {
+        bytes.extend((value as u64).to_le_bytes());
+      }
+      offset += level.len();
+    }
+    for level in levels {
+      bytes.extend(level);
+    }
+    bytes
+  }
+
+  #[test]
+  fn dds_files_read_back_what_was_written() {
+    for format in [PixelFormat::Bc1, PixelFormat::Bc3Srgb, PixelFormat::Bc5, PixelFormat::Bc7] {
+      let written = image(format, 13, 6, 4);
+      assert_eq!(CompressedImage::from_dds(&written.to_dds()), Ok(written));
+    }
+  }
+
+  #[test]
+  fn ktx2_levels_are_read() {
+    let written = image(PixelFormat::Bc4, 8, 8, 2);
+    assert_eq!(CompressedImage::from_ktx2(&ktx2(139, 8, 8, &written.levels)), Ok(written));
+  }
+
+  #[test]
+  fn truncated_files_are_rejected() {
+    let dds = image(PixelFormat::Bc1, 8, 8, 2).to_dds();
+    assert_eq!(CompressedImage::from_dds(&dds[..64]), Err("the DDS header is cut short".into()));
+    assert_eq!(CompressedImage::from_dds(&dds[..4 + DDS_HEADER_SIZE + 4]), Err("the DX10 header is cut short".into()));
+    // 32 bytes for the first level, 8 for the second.
+    assert_eq!(CompressedImage::from_dds(&dds[..dds.len() - 40]), Err("mip level 0 is cut short".into()));
+    assert_eq!(CompressedImage::from_dds(&dds[..dds.len() - 1]), Err("mip level 1 is cut short".into()));
+
+    let ktx2 = ktx2(131, 8, 8, &image(PixelFormat::Bc1, 8, 8, 2).levels);
+    assert_eq!(CompressedImage::from_ktx2(&ktx2[..40]), Err("the KTX2 header is cut short".into()));
+    assert_eq!(CompressedImage::from_ktx2(&ktx2[..100]), Err("the level index is cut short".into()));
+    assert_eq!(CompressedImage::from_ktx2(&ktx2[..ktx2.len() - 1]), Err("mip level 1 is cut short".into()));
+  }
+
+  #[test]
+  fn sizes_that_overflow_are_rejected() {
+    let mut dds = image(PixelFormat::Bc7, 4, 4, 1).to_dds();
+    // Height and width.
+    dds[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    dds[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert_eq!(CompressedImage::from_dds(&dds), Err("mip level 0 is too large".into()));
+
+    let mut ktx2 = ktx2(131, 4, 4, &image(PixelFormat::Bc1, 4, 4, 1).levels);
+    // The level's offset.
+    ktx2[80..88].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert_eq!(CompressedImage::from_ktx2(&ktx2), Err("mip level 0 is too large".into()));
+  }
+
+  #[test]
+  fn empty_images_are_rejected() {
+    let mut dds = image(PixelFormat::Bc1, 4, 4, 1).to_dds();
+    dds[16..20].copy_from_slice(&0u32.to_le_bytes());
+    assert_eq!(CompressedImage::from_dds(&dds), Err("a 0x4 image has no pixels".into()));
+
+    let ktx2 = ktx2(131, 4, 0, &image(PixelFormat::Bc1, 4, 1, 1).levels);
+    assert_eq!(CompressedImage::from_ktx2(&ktx2), Err("a 4x0 image has no pixels".into()));
+  }
+}
@@ -0,0 +1,121 @@
+/// Turns variable frame times into a whole number of fixed simulation
+/// steps, so the simulation advances the same way at any frame rate.
+///
+/// Time that doesn't add up to a full step is carried over to the next
+/// frame; `alpha` says how far into the next step the frame is, so
+/// rendering can interpolate between the last two simulated states.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTimestep {
+  step: f64,
+  max_steps: u32,
+  accumulator: f64,
+  ticks: u64,
+}
+
+impl FixedTimestep {
+  /// `step` is the simulated time per step, in seconds.
+  pub fn new(step: f64) -> Self {
+    assert!(step > 0.0, "Fixed step must be positive!");
+    FixedTimestep { step, max_steps: 8, accumulator: 0.0, ticks: 0 }
+  }
+
+  /// Caps how many steps one frame may run. After a long stall (a
+  /// breakpoint, loading, a slow machine) the missing time is dropped
+  /// instead of being simulated all at once, which would make the next
+  /// frame slower still.
+  pub fn with_max_steps(mut self, max_steps: u32) -> Self {
+    self.max_steps = max_steps.max(1);
+    self
+  }
+
+  pub fn step(&self) -> f64 {
+    self.step
+  }
+
+  pub fn max_steps(&self) -> u32 {
+    self.max_steps
+  }
+
+  /// Steps run since the start. Together with the step length this is the
+  /// simulation clock, which (unlike the wall clock) replays identically.
+  pub fn ticks(&self) -> u64 {
+    self.ticks
+  }
+
+  /// Adds a frame's worth of time and returns how many steps to run now.
+  pub fn advance(&mut self, frame_time: f64) -> u32 {
+    self.accumulator += frame_time.max(0.0);
+
+    let mut steps = (self.accumulator / self.step) as u64;
+    if steps > self.max_steps as u64 {
+      steps = self.max_steps as u64;
+      // Keep the fraction of a step so alpha stays continuous.
+      self.accumulator %= self.step;
+    } else {
+      self.accumulator -= steps as f64 * self.step;
+    }
+
+    self.ticks += steps;
+    steps as u32
+  }
+
+  /// How far between the previous and the next step the current frame is,
+  /// from 0 to 1.
+  pub fn alpha(&self) -> f64 {
+    (self.accumulator / self.step).clamp(0.0, 1.0)
+  }
+}
+
+impl Default for FixedTimestep {
+  /// 60 steps per second.
+  fn default() -> Self {
+    Self::new(1.0 / 60.0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn leftover_time_carries_over_as_alpha() {
+    let mut timestep = FixedTimestep::new(0.25);
+
+    assert_eq!(timestep.advance(0.625), 2);
+    assert_eq!(timestep.alpha(), 0.5);
+    assert_eq!(timestep.advance(0.125), 1);
+    assert_eq!(timestep.alpha(), 0.0);
+    assert_eq!(timestep.advance(0.0625), 0);
+    assert_eq!(timestep.alpha(), 0.25);
+    assert_eq!(timestep.ticks(), 3);
+  }
+
+  #[test]
+  fn long_frames_are_clamped_to_max_steps() {
+    let mut timestep = FixedTimestep::new(0.25).with_max_steps(4);
+
+    // A 10 second stall only runs 4 steps; the rest is dropped, apart from
+    // the part of a step that keeps alpha where it was going to be.
+    assert_eq!(timestep.advance(10.125), 4);
+    assert_eq!(timestep.alpha(), 0.5);
+    assert_eq!(timestep.ticks(), 4);
+
+    // The next frame runs normally again.
+    assert_eq!(timestep.advance(0.125), 1);
+    assert_eq!(timestep.alpha(), 0.0);
+  }
+
+  #[test]
+  fn negative_frame_times_are_ignored() {
+    let mut timestep = FixedTimestep::new(0.25);
+    timestep.advance(0.125);
+
+    assert_eq!(timestep.advance(-1.0), 0);
+    assert_eq!(timestep.alpha(), 0.5);
+  }
+
+  #[test]
+  fn max_steps_is_at_least_one() {
+    assert_eq!(FixedTimestep::default().with_max_steps(0).max_steps(), 1);
+  }
+}
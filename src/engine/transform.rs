@@ -0,0 +1,44 @@
+use cgmath::{One, VectorSpace, Vector3};
+
+use super::types::{Mat4, Quat, Vec3};
+
+/// Translation, rotation and (non-uniform) scale, applied in that order
+/// when read right to left: a point is scaled, then rotated, then moved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+  pub translation: Vec3,
+  pub rotation: Quat,
+  pub scale: Vec3,
+}
+
+impl Transform {
+  pub fn from_translation(translation: Vec3) -> Self {
+    Transform { translation, ..Default::default() }
+  }
+
+  pub fn matrix(&self) -> Mat4 {
+    Mat4::from_translation(self.translation)
+      * Mat4::from(self.rotation)
+      * Mat4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+  }
+
+  /// Blends from `previous` to `current` by `alpha` (0 gives `previous`,
+  /// 1 gives `current`), taking the shortest path between rotations.
+  pub fn interpolate(previous: &Transform, current: &Transform, alpha: f32) -> Transform {
+    Transform {
+      translation: previous.translation.lerp(current.translation, alpha),
+      rotation: previous.rotation.nlerp(current.rotation, alpha),
+      scale: previous.scale.lerp(current.scale, alpha),
+    }
+  }
+}
+
+impl Default for Transform {
+  fn default() -> Self {
+    Transform {
+      translation: Vector3::new(0.0, 0.0, 0.0),
+      rotation: Quat::one(),
+      scale: Vector3::new(1.0, 1.0, 1.0),
+    }
+  }
+}
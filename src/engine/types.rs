@@ -1,3 +1,5 @@
 pub type Mat4 = cgmath::Matrix4<f32>;
 pub type Point3 = cgmath::Point3<f32>;
-pub type Vec3 = cgmath::Vector3<f32>;
\ No newline at end of file
+pub type Vec3 = cgmath::Vector3<f32>;
+pub type Vec4 = cgmath::Vector4<f32>;
+pub type Quat = cgmath::Quaternion<f32>;
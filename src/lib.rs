@@ -1,2 +1,4 @@
 pub mod engine;
-pub mod data;
\ No newline at end of file
+pub mod data;
+
+pub use engine::error::{Error, Result};
@@ -4,174 +4,111 @@
 extern crate gl;
 extern crate glfw;
 
-use cgmath::{perspective, vec3, Deg, EuclideanSpace, SquareMatrix};
-use glfw::{Action, Context, GlfwReceiver, Key, OpenGlProfileHint, WindowHint, WindowMode};
+use cgmath::EuclideanSpace;
 
 mod engine;
-use engine::{object::Object, scene::Scene, skybox::Skybox, *};
+use engine::{
+  app::{self, App, AppContext, WindowConfig, WindowEvent},
+  input::Bindings,
+  ecs::{Schedule, World},
+  scene::{NodeId, Scene},
+  *,
+};
 
 mod data;
-use data::*;
 
 use types::*;
 
-static mut LOCK_MOUSE: bool = false;
-
-fn main() {
-  let window_width: u32 = 800;
-  let window_height: u32 = 600;
-
-  let mut delta_time: f64;
-  let mut last_frame: f64 = 0.0;
-
-  let mut glfw = glfw::init(glfw::fail_on_errors).unwrap();
-
-  // These seem to have no impact on the functionality of the program,
-  // but might as well have them anyway.
-  glfw.window_hint(WindowHint::ContextVersion(3, 3));
-  glfw.window_hint(WindowHint::OpenGlProfile(OpenGlProfileHint::Core));
-
-  #[cfg(target_os = "macos")]
-  glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
-
-  let (mut window, events) = glfw
-    .create_window(
-      window_width,
-      window_height,
-      "Cogwheel 0.0.1 Demo",
-      WindowMode::Windowed,
-    )
-    .expect("Failed to create GLFW window.");
-
-  let mut camera = Camera::new(Point3::new(0.0, 0.0, 3.0), window_width, window_height);
-
-  window.set_key_polling(true);
-  window.set_cursor_pos_polling(true);
-  window.set_scroll_polling(true);
-  window.make_current();
-  window.set_scroll_callback(handle_scroll);
-  window.set_framebuffer_size_callback(handle_resize);
-
-  // This loads OpenGL function pointers (the ones used inside unsafe blocks)
-  gl::load_with(|s| window.get_proc_address(s) as *const _);
-
-  let mut cube_shader = Shader { id: 0 };
-  cube_shader.initialize("shaders/default.vert", "shaders/default.frag");
-
-  let mut skybox_shader = Shader { id: 1 };
-  skybox_shader.initialize("shaders/skybox.vert", "shaders/skybox.frag");
-
-  let scene = Scene::new();
-
-  let cube = Object::new(
-    Vertices::get_vertices(Vertices::Cube),
-    "assets/images/marble.jpg",
-    vec3(0.0, 0.0, 0.0),
-  );
-
-  let cube2 = Object::new(
-    Vertices::get_vertices(Vertices::Cube),
-    "assets/images/marble.jpg",
-    vec3(5.0, 0.0, 0.0),
-  );
-
-  let cube3 = Object::new(
-    Vertices::get_vertices(Vertices::Cube),
-    "assets/images/marble.jpg",
-    vec3(1.0, 2.0, 4.0),
-  );
-
-  let skybox = Skybox::new(
-    Vertices::get_vertices(Vertices::Skybox),
-    &[
-      "assets/images/skybox/right.jpg",
-      "assets/images/skybox/left.jpg",
-      "assets/images/skybox/top.jpg",
-      "assets/images/skybox/bottom.jpg",
-      "assets/images/skybox/front.jpg",
-      "assets/images/skybox/back.jpg",
-    ],
-  );
-
-  cube_shader.activate();
-  cube_shader.set_int("skybox", 0);
-
-  skybox_shader.activate();
-  skybox_shader.set_int("skybox", 0);
-
-  unsafe {
-    gl::Enable(gl::DEPTH_TEST);
-    gl::DepthFunc(gl::ALWAYS);
-    gl::Enable(gl::BLEND);
-    gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-  }
-
-  while !window.should_close() {
-    let current_frame: f64 = glfw.get_time();
-    delta_time = current_frame - last_frame;
-    last_frame = current_frame;
-
-    unsafe {
-      gl::ClearColor(0.9, 0.9, 0.9, 1.0);
-      gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-
-      let mut projection = Mat4::identity();
-      projection = projection * perspective(Deg(camera.get_fov()), 800.0 / 600.0, 0.1, 100.0);
-
-      cube_shader.activate();
+struct Demo {
+  scene: Scene,
+  cube_shader: Shader,
+  world: World,
+  schedule: Schedule,
+  camera: Camera,
+  // Cycled through with the "next_camera" action.
+  controllers: Vec<Box<dyn CameraController>>,
+  active_controller: usize,
+  // Stops the camera from following the mouse while set.
+  lock_mouse: bool,
+  // The scene node last clicked on with "select", if any.
+  selected: Option<NodeId>,
+}
 
-      cube_shader.set_vec3("cameraPos", &camera.get_pos().to_vec());
-      cube_shader.set_mat4("view", camera.get_view_matrix());
-      cube_shader.set_mat4("projection", projection);
+impl App for Demo {
+  fn init(ctx: &mut AppContext) -> error::Result<Self> {
+    let (width, height) = ctx.framebuffer_size();
+    let mut camera = Camera::new(Point3::new(0.0, 0.0, 3.0));
+    camera.set_viewport_size(width, height);
 
-      cube.draw(&mut cube_shader);
-      cube2.draw(&mut cube_shader);
-      cube3.draw(&mut cube_shader);
-      // plane.draw(&mut cube_shader);
-      // window1.draw(&mut cube_shader);
-      // window2.draw(&mut cube_shader);
+    ctx.input_mut().set_bindings(Bindings::load("assets/input.ron")?);
 
-      skybox_shader.activate();
-      skybox.draw(&mut skybox_shader, &mut camera, projection);
+    // Level content comes from the scene file; entities spawned by gameplay
+    // code live in the world and are drawn with their own shader.
+    let mut scene = Scene::load("assets/scenes/demo.ron")?;
+    if let Some(id) = scene.find("cube2") {
+      if let Some(object) = &mut scene.node_mut(id).object {
+        object.material = data::materials::Materials::Emerald.get_material();
+      }
+    }
+    if let Some(id) = scene.find("cube3") {
+      if let Some(object) = &mut scene.node_mut(id).object {
+        // Brushed gold.
+        object.material = material::PbrMaterial::new(Vec3::new(1.0, 0.78, 0.34), 1.0, 0.35).into();
+      }
     }
 
-    handle_window_event(&mut window, delta_time, &mut camera, &events);
-
-    window.swap_buffers();
-    glfw.poll_events();
+    let cube_shader = Shader::new("shaders/default.vert", "shaders/default.frag")?;
+    cube_shader.activate();
+    cube_shader.set_int("skybox", 0);
 
-    let error = unsafe { gl::GetError() };
-    if error != gl::NO_ERROR {
-      println!("OpenGL Error: {}", error);
+    unsafe {
+      gl::Enable(gl::DEPTH_TEST);
+      gl::DepthFunc(gl::ALWAYS);
+      gl::Enable(gl::BLEND);
+      gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
     }
-  }
 
-  unsafe {
-    cube.delete();
-    // plane.delete();
-    gl::DeleteProgram(cube_shader.id);
+    Ok(Demo {
+      scene,
+      cube_shader,
+      world: World::new(),
+      // Gameplay systems are added here and run once per fixed step.
+      schedule: Schedule::new(),
+      camera,
+      controllers: vec![
+        Box::new(FpsController::default()),
+        Box::new(FreeFlyController::default()),
+        Box::new(OrbitController::default()),
+      ],
+      active_controller: 0,
+      lock_mouse: false,
+      selected: None,
+    })
   }
 
-  drop(window);
-  drop(glfw);
+  fn fixed_update(&mut self, ctx: &mut AppContext, step: f64) -> () {
+    self.schedule.run(&mut self.world, step);
 
-  fn handle_window_event(
-    window: &mut glfw::Window,
-    delta_time: f64,
-    camera: &mut Camera,
-    events: &GlfwReceiver<(f64, glfw::WindowEvent)>,
-  ) {
-    let camera_speed: f32 = 2.5 * delta_time as f32;
+    // Moving and rolling are rates, so they advance with the simulation.
+    let input = ControllerInput::from_input(ctx.input());
+    let movement = ControllerInput { movement: input.movement, roll: input.roll, ..Default::default() };
+    self.controllers[self.active_controller].update(&mut self.camera, &movement, step as f32);
+  }
 
-    if window.get_key(Key::Escape) == Action::Press {
-      window.set_should_close(true)
+  fn on_event(&mut self, _ctx: &mut AppContext, event: &WindowEvent) -> () {
+    if let WindowEvent::FramebufferSize(width, height) = *event {
+      self.camera.set_viewport_size(width.max(0) as u32, height.max(0) as u32);
     }
+  }
+
+  fn update(&mut self, ctx: &mut AppContext, delta_time: f64) -> () {
+    let input = ctx.input();
 
-    if window.get_key(Key::Q) == Action::Press {
+    if input.is_pressed("toggle_wireframe") {
       unsafe {
         let mut poly_mode: i32 = 0;
         gl::GetIntegerv(gl::POLYGON_MODE, &mut poly_mode);
-        if gl::LINE == poly_mode.try_into().unwrap() {
+        if gl::LINE as i32 == poly_mode {
           gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
         } else {
           gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
@@ -179,48 +116,76 @@ fn main() {
       }
     }
 
-    if window.get_key(Key::L) == Action::Press {
-      unsafe {
-        LOCK_MOUSE = !LOCK_MOUSE;
-      }
+    if input.is_pressed("toggle_mouse_lock") {
+      self.lock_mouse = !self.lock_mouse;
     }
 
-    if window.get_key(Key::W) == Action::Press {
-      camera.update_camera_pos_fb(camera_speed);
+    if input.is_pressed("next_camera") {
+      self.active_controller = (self.active_controller + 1) % self.controllers.len();
+      self.controllers[self.active_controller].attach(&self.camera);
     }
 
-    if window.get_key(Key::S) == Action::Press {
-      camera.update_camera_pos_fb(-camera_speed);
+    // Looking and zooming follow the mouse every frame, as mouse movement
+    // only counts once however many steps the frame ran.
+    let controller_input = ControllerInput::from_input(input);
+    let look = ControllerInput {
+      look: if self.lock_mouse { (0.0, 0.0) } else { controller_input.look },
+      look_rate: controller_input.look_rate,
+      zoom: controller_input.zoom,
+      ..Default::default()
+    };
+    self.controllers[self.active_controller].update(&mut self.camera, &look, delta_time as f32);
+
+    if input.is_pressed("select") {
+      if let Some((x, y)) = ctx.cursor_position() {
+        let ray = self.camera.screen_to_ray(x, y);
+        self.selected = self.scene.raycast(&ray).map(|hit| hit.node);
+      }
     }
 
-    if window.get_key(Key::A) == Action::Press {
-      camera.update_camera_pos_lr(-camera_speed);
+    if input.is_pressed("quit") {
+      ctx.quit();
     }
+  }
 
-    if window.get_key(Key::D) == Action::Press {
-      camera.update_camera_pos_lr(camera_speed);
-    }
+  fn render(&mut self, ctx: &mut AppContext, _alpha: f32) -> () {
+    let camera = &self.camera;
 
-    for (_, event) in glfw::flush_messages(events) {
-      match event {
-        glfw::WindowEvent::CursorPos(x, y) => unsafe {
-          if !LOCK_MOUSE {
-            camera.handle_mouse(x * 2.0, y * 2.0);
-          }
-        },
-        glfw::WindowEvent::Scroll(_, scrl) => {
-          camera.handle_scroll(scrl);
-        }
-        _ => {}
-      }
+    unsafe {
+      let bg = self.scene.bg_color;
+      gl::ClearColor(bg.x, bg.y, bg.z, 1.0);
+      gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
     }
-  }
 
-  fn handle_scroll(_window: &mut glfw::Window, x_offset: f64, y_offset: f64) {}
+    self.cube_shader.activate();
 
-  fn handle_resize(_window: &mut glfw::Window, width: i32, height: i32) {
-    unsafe {
-      gl::Viewport(0, 0, width, height);
+    self.cube_shader.set_vec3("cameraPos", &camera.get_pos().to_vec());
+    self.cube_shader.set_mat4("view", camera.get_view_matrix());
+    self.cube_shader.set_mat4("projection", camera.get_projection_matrix());
+
+    ecs::render(&self.world, &mut self.cube_shader, ctx.fallback_texture());
+    // plane.draw(&mut cube_shader);
+    // window1.draw(&mut cube_shader);
+    // window2.draw(&mut cube_shader);
+
+    self.scene.draw(camera);
+
+    let error = unsafe { gl::GetError() };
+    if error != gl::NO_ERROR {
+      println!("OpenGL Error: {}", error);
     }
   }
 }
+
+fn main() {
+  let config = WindowConfig {
+    title: "Cogwheel 0.0.1 Demo".into(),
+    ..Default::default()
+  };
+
+  // The demo has no fallback assets, so report what broke and quit cleanly.
+  if let Err(e) = app::run::<Demo>(config, FixedTimestep::default()) {
+    eprintln!("{}", e);
+    std::process::exit(1);
+  }
+}
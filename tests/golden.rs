@@ -0,0 +1,54 @@
+//! Golden-image tests: fixed scenes are rendered offscreen and compared
+//! with the images checked in next to them. Run with `--features headless`
+//! on a machine with Mesa (llvmpipe is enough).
+//!
+//! After a change that is meant to alter the output, set
+//! `UPDATE_GOLDEN=1` to write the new images instead of comparing.
+#![cfg(feature = "headless")]
+
+use cogwheel::engine::headless::HeadlessRenderer;
+use cogwheel::engine::scene::Scene;
+use cogwheel::engine::types::{Point3, Vec3};
+use cogwheel::engine::Camera;
+use image::RgbaImage;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 120;
+
+// Rasterizers round a little differently, so each channel may be off by
+// `CHANNEL_TOLERANCE`, and a few pixels (edges, mostly) may be off by more.
+const CHANNEL_TOLERANCE: u8 = 8;
+const MAX_DIFFERENT_PIXELS: f32 = 0.01;
+
+#[test]
+fn scene_matches_golden_image() {
+  let mut renderer = HeadlessRenderer::new(WIDTH, HEIGHT).expect("an OpenGL context for offscreen rendering");
+  let mut scene = Scene::load("tests/golden/scene.ron").expect("the test scene");
+  let mut camera = Camera::new(Point3::new(2.5, 2.0, 4.0));
+  camera.look_at(Point3::new(0.0, 0.0, 0.0), Vec3::unit_y());
+
+  let image = renderer.render_scene(&mut scene, &mut camera);
+  compare_with_golden(&image, "tests/golden/scene.png");
+}
+
+fn compare_with_golden(image: &RgbaImage, path: &str) -> () {
+  if std::env::var_os("UPDATE_GOLDEN").is_some() {
+    image.save(path).expect("the golden image to be written");
+    return;
+  }
+
+  let golden = image::open(path).expect("the golden image").to_rgba8();
+  assert_eq!(image.dimensions(), golden.dimensions(), "{} has a different size", path);
+
+  let different = image
+    .pixels()
+    .zip(golden.pixels())
+    .filter(|(a, b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > CHANNEL_TOLERANCE))
+    .count();
+  let allowed = (MAX_DIFFERENT_PIXELS * (WIDTH * HEIGHT) as f32) as usize;
+  if different > allowed {
+    let actual = path.replace(".png", ".actual.png");
+    image.save(&actual).expect("the rendered image to be written");
+    panic!("{} of {} pixels differ from {}, see {}", different, WIDTH * HEIGHT, path, actual);
+  }
+}